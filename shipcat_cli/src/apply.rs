@@ -1,22 +1,45 @@
-use std::path::Path;
-use tokio::fs;
-
 use crate::{
-    diff, helm,
-    kubeapi::ShipKube,
-    kubectl, track,
+    chart, diff,
+    events::{self, ApplyEvent},
+    graph, helm, history,
+    hooks::{self, HookPhase},
+    kubeapi::{self, MinimalMfCrd, ShipKube, CANARY_LABEL},
+    kubectl,
+    lock::DeployLock,
+    track,
     webhooks::{self, UpgradeState},
 };
+use chrono::{DateTime, Duration, Utc};
+use k8s_openapi::api::apps::v1::Deployment;
 use serde_json::json;
 
 use shipcat_definitions::{
-    status::{make_date, Condition},
-    structs::{Metadata, NotificationMode},
+    status::{make_date, Condition, ManifestStatus, RolloutRecord},
+    structs::{
+        rolloutstrategy::{CanaryStep, CanaryStrategy},
+        Metadata, NotificationMode,
+    },
     Config, Manifest, PrimaryWorkload, ReconciliationMode, Region,
 };
 
 use super::{ErrorKind, Result, ResultExt};
 
+/// Number of rollouts to keep in the `.status.history` of a shipcatmanifest
+const MAX_HISTORY: usize = 20;
+
+/// How often reconciles fetch secrets to look for rotations (when nothing else changed)
+const SECRET_CHECK_MINS: i64 = 30;
+
+/// Whether secrets last checked at `checked` (RFC 3339) are due another check
+///
+/// Every check fetches all the secrets of a service from vault.
+fn secrets_check_due(checked: Option<&str>, now: DateTime<Utc>) -> bool {
+    match checked.and_then(|c| DateTime::parse_from_rfc3339(c).ok()) {
+        Some(t) => now.signed_duration_since(t) >= Duration::minutes(SECRET_CHECK_MINS),
+        None => true,
+    }
+}
+
 /// Information from an upgrade
 ///
 /// This information is generated by apply on a best-effort basis.
@@ -35,8 +58,10 @@ pub struct UpgradeInfo {
     pub region: String,
     /// Validated namespace inferred from region
     pub namespace: String,
-    /// Computed diff string (if available)
-    pub diff: Option<String>,
+    /// Computed diff against the cluster (if available)
+    pub diff: Option<diff::Changes>,
+    /// Reason given for applying during a deployment freeze (if any)
+    pub freezeOverride: Option<String>,
 }
 
 impl UpgradeInfo {
@@ -58,6 +83,7 @@ impl UpgradeInfo {
             region: mf.region.clone(),
             namespace: mf.namespace.clone(),
             diff: None,
+            freezeOverride: None,
         }
     }
 }
@@ -82,23 +108,192 @@ impl UpgradeInfo {
 /// It is also entirely responsible for sending webhooks on errors / successes.
 /// As such, it's entirely responsible for not propagating random errors here with `?`
 /// Every error cases is something that might need to be notified.
+///
+/// With `rollback` set, a failed rollout is followed by an apply of the
+/// last successfully rolled out version (when waiting for rollouts).
+/// With `prune` set, objects of the service that are no longer templated are deleted.
+/// With `recreate` set, Deployments whose selector changed are recreated (keeping their pods),
+/// otherwise a selector change fails the apply.
+#[allow(clippy::too_many_arguments)] // TODO: bundle the cli flags
 pub async fn apply(
     svc: String,
     force: bool,
     region: &Region,
     conf: &Config,
     wait: bool,
+    rollback: bool,
+    prune: bool,
+    recreate: bool,
     passed_version: Option<String>,
+    override_freeze: Option<String>,
+) -> Result<Option<UpgradeInfo>> {
+    let ver = VersionRequest::new(passed_version, false);
+    apply_locked(
+        svc,
+        force,
+        region,
+        conf,
+        wait,
+        rollback,
+        prune,
+        recreate,
+        ver,
+        override_freeze,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn apply_locked(
+    svc: String,
+    force: bool,
+    region: &Region,
+    conf: &Config,
+    wait: bool,
+    rollback: bool,
+    prune: bool,
+    recreate: bool,
+    ver: VersionRequest,
+    override_freeze: Option<String>,
 ) -> Result<Option<UpgradeInfo>> {
     match region.reconciliationMode {
-        ReconciliationMode::CrdOwned => apply_kubectl(&svc, force, region, conf, wait, passed_version).await,
+        ReconciliationMode::CrdOwned => {
+            // Hold the deploy lock for the duration of the apply (including any rollback)
+            let s = ShipKube::new_within(&svc, &region.namespace).await?;
+            let lock = s.lock_new("apply").await?;
+            let applying = apply_kubectl(
+                &svc,
+                force,
+                region,
+                conf,
+                wait,
+                rollback,
+                prune,
+                recreate,
+                ver,
+                override_freeze,
+                &lock,
+            );
+            let res = s.renewing(&lock, applying).await;
+            if let Err(e) = s.unlock(&lock).await {
+                warn!("Unable to unlock {}: {}", svc, e);
+            }
+            res
+        }
+    }
+}
+
+/// shipcat rollback
+///
+/// Applies a previous version of a service through `apply`.
+/// Without an explicit version, the last successfully rolled out version that
+/// differs from the current one is taken from the shipcatmanifest `.status`.
+///
+/// Unlike `apply`, this may override a version pinned in manifests.
+/// The pin is applied again at the next reconcile, so it should be reverted in manifests as well.
+/// With `prune` set, objects of the service that are no longer templated are deleted.
+pub async fn rollback(
+    svc: String,
+    to: Option<String>,
+    region: &Region,
+    conf: &Config,
+    wait: bool,
+    prune: bool,
+    override_freeze: Option<String>,
+) -> Result<Option<UpgradeInfo>> {
+    let version = match to {
+        Some(v) => v,
+        None => {
+            let s = ShipKube::new_within(&svc, &region.namespace).await?;
+            let crd = s.get_minimal().await?;
+            let status = crd.status.unwrap_or_default();
+            match history::previous_version(&status, &crd.spec.version) {
+                Some(v) => v,
+                None => bail!("No previous version of '{}' to roll back to", svc),
+            }
+        }
+    };
+    info!("Rolling back {} to {}", svc, version);
+    apply_locked(
+        svc,
+        false,
+        region,
+        conf,
+        wait,
+        false,
+        prune,
+        false,
+        VersionRequest::new(Some(version), true),
+        override_freeze,
+    )
+    .await
+}
+
+/// shipcat apply for multiple services
+///
+/// Services are applied in waves ordered by their dependencies via `graph::waves`.
+/// Each wave is applied with at most `n_workers` parallel applies.
+/// After the first failure in a wave, no more applies are started,
+/// and the ones in flight are allowed to finish before bailing.
+#[allow(clippy::too_many_arguments)] // TODO: bundle the cli flags
+pub async fn apply_many(
+    svcs: Vec<String>,
+    force: bool,
+    region: &Region,
+    conf: &Config,
+    wait: bool,
+    rollback: bool,
+    prune: bool,
+    recreate: bool,
+    override_freeze: Option<String>,
+    n_workers: usize,
+) -> Result<Vec<UpgradeInfo>> {
+    use futures::stream::{FuturesUnordered, StreamExt};
+
+    let graph = graph::full_graph(conf, region).await?;
+    let waves = graph::waves(&graph, &svcs)?;
+    let mut applied = vec![];
+    for (i, wave) in waves.into_iter().enumerate() {
+        info!("Applying wave {}: {}", i + 1, wave.join(", "));
+        let mut queue = wave.into_iter();
+        let mut running = FuturesUnordered::new();
+        let mut errs = vec![];
+        loop {
+            while errs.is_empty() && running.len() < n_workers.max(1) {
+                match queue.next() {
+                    Some(svc) => {
+                        let ovr = override_freeze.clone();
+                        running.push(apply(
+                            svc, force, region, conf, wait, rollback, prune, recreate, None, ovr,
+                        ))
+                    }
+                    None => break,
+                }
+            }
+            match running.next().await {
+                Some(Ok(ui)) => applied.extend(ui),
+                Some(Err(e)) => {
+                    warn!("{}", e);
+                    errs.push(e);
+                }
+                None => break,
+            }
+        }
+        if !errs.is_empty() {
+            let skipped = queue.collect::<Vec<_>>();
+            if !skipped.is_empty() {
+                warn!("Skipped applying {}", skipped.join(", "));
+            }
+            bail!("Failed to apply {} service(s) in wave {}", errs.len(), i + 1);
+        }
     }
+    Ok(applied)
 }
 
 /// Reason for an apply being allowed through
 ///
 /// Some of these imply others. We pick the strongest one we can.
-#[derive(Debug)]
+#[derive(Serialize, Clone, Debug)]
 pub enum UpgradeReason {
     /// New service
     NewService,
@@ -120,50 +315,84 @@ impl ToString for UpgradeReason {
     }
 }
 
-/// First version of apply that does not use tiller
+/// How the version of an apply was resolved
 ///
-/// This writes events to uses the shipcatmanifest crd
-#[allow(clippy::cognitive_complexity)] // TODO: refactor this!
-async fn apply_kubectl(
-    svc: &str,
-    force: bool,
-    region: &Region,
-    conf: &Config,
-    wait: bool,
-    passed_version: Option<String>,
-) -> Result<Option<UpgradeInfo>> {
-    if let Err(e) = webhooks::ensure_requirements(&region) {
-        warn!("Could not ensure webhook requirements: {}", e);
+/// A version is set EITHER via `-t SOMEVER` on CLI, or pinned in manifest.
+/// In rolling environments, it can fall back to the running version.
+#[derive(Serialize, Clone, Debug)]
+pub struct VersionResolution {
+    /// Version pinned in manifests
+    pub pinned: Option<String>,
+    /// Version passed in externally
+    pub passed: Option<String>,
+    /// Version in the currently applied shipcatmanifest crd
+    pub running: Option<String>,
+    /// Version that will be applied
+    pub resolved: String,
+}
+
+/// A version passed in externally
+#[derive(Clone, Debug)]
+struct VersionRequest {
+    version: Option<String>,
+    /// Whether the version may differ from a version pinned in manifests (rollbacks only)
+    override_pin: bool,
+}
+
+impl VersionRequest {
+    fn new(version: Option<String>, override_pin: bool) -> Self {
+        VersionRequest {
+            version,
+            override_pin,
+        }
     }
-    let mfbase = shipcat_filebacked::load_manifest(&svc, &conf, &region).await?;
 
-    // A version is set EITHER via `-t SOMEVER` on CLI, or pinned in manifest
-    if passed_version.is_some() && mfbase.version.is_some() && mfbase.version != passed_version {
-        error!("Overriding a pinned version will be undone at next reconcile");
-        bail!(
-            "Cannot override version for '{}' because it is pinned in manifests",
-            svc
-        );
+    /// The version to use over a version pinned in manifests (if any)
+    ///
+    /// Pinned versions can only be overridden by rollbacks.
+    fn resolve(&self, svc: &str, pinned: Option<&String>) -> Result<Option<String>> {
+        match (pinned, &self.version) {
+            (Some(p), Some(v)) if p != v => {
+                if !self.override_pin {
+                    error!("Overriding a pinned version will be undone at next reconcile");
+                    bail!(
+                        "Cannot override version for '{}' because it is pinned in manifests",
+                        svc
+                    );
+                }
+                warn!(
+                    "Overriding version {} pinned in manifests for '{}' with {} until the next reconcile",
+                    p, svc, v
+                );
+                Ok(Some(v.clone()))
+            }
+            (Some(p), _) => Ok(Some(p.clone())),
+            (None, v) => Ok(v.clone()),
+        }
     }
-    let explicit_version = mfbase.version.clone().or(passed_version);
+}
 
-    if !mfbase.regions.contains(&region.name) {
+/// Resolve the version to apply along with the reason it implies (if any)
+///
+/// Fetches the minimal shipcatmanifest crd to compare against what's running.
+/// Safe to bail from, this happens before anything is applied.
+async fn resolve_version(
+    svc: &str,
+    mfbase: &Manifest,
+    s: &ShipKube,
+    ver: VersionRequest,
+) -> Result<(VersionResolution, Option<MinimalMfCrd>, Option<UpgradeReason>)> {
+    let region = &mfbase.region;
+    let explicit_version = ver.resolve(svc, mfbase.version.as_ref())?;
+
+    if !mfbase.regions.contains(region) {
         bail!(
             "Cannot deploy '{}' to a region it's not configured for in its manifest",
             svc
         );
     }
 
-    // Interact with the kube api to get the shipcatmanifest crd and its .status
-    // This lets us work out:
-    // - if the service has been installed before (negates the need for a diff)
-    // - if we need to apply a new crd (so we have an atomic change)
-    // - if we need to interact with secret-manager TODO: do
-    let s = ShipKube::new(&mfbase).await?;
-
-    // Next large batch is working out the reason for the upgrade (if any)
     let mut reason = None;
-
     // Fetch the minimial shipcatmanifest crd to read version + metadata
     // Safe to bail, pre CRD apply. Next run can retry if kube api getter fails.
     let (actual_version, crd) = match s.get_minimal().await {
@@ -197,30 +426,235 @@ async fn apply_kubectl(
             }
         }
     };
+    let resolution = VersionResolution {
+        pinned: mfbase.version.clone(),
+        passed: ver.version,
+        running: crd.as_ref().map(|o| o.spec.version.clone()),
+        resolved: actual_version,
+    };
+    Ok((resolution, crd, reason))
+}
+
+/// Dry-run plan of an apply
+///
+/// Everything `apply` works out before it decides to upgrade.
+/// Serialized as json so it can be posted on pull requests.
+#[derive(Serialize, Debug)]
+pub struct ApplyPlan {
+    /// Name of service
+    pub name: String,
+    /// Validated region requested for installation
+    pub region: String,
+    /// Validated namespace inferred from region
+    pub namespace: String,
+    /// How the version was resolved
+    pub version: VersionResolution,
+    /// Reason the apply would go through (none if up to date)
+    pub reason: Option<UpgradeReason>,
+    /// Diff of the shipcatmanifest crd (none if unchanged)
+    pub crdDiff: Option<String>,
+    /// Full diff of the generated kubernetes yaml with secrets obfuscated
+    ///
+    /// Only available if the service is already installed.
+    pub templateDiff: Option<String>,
+    /// Objects that would be pruned as they are no longer templated
+    pub prunes: Vec<String>,
+}
+
+/// shipcat apply --plan
+///
+/// Goes through `apply` up to the decision point without mutating anything.
+/// No crd or chart is applied, no webhooks are sent, and no status is written.
+pub async fn plan(
+    svc: String,
+    region: &Region,
+    conf: &Config,
+    prune: bool,
+    passed_version: Option<String>,
+) -> Result<ApplyPlan> {
+    match region.reconciliationMode {
+        ReconciliationMode::CrdOwned => plan_kubectl(&svc, region, conf, prune, passed_version).await,
+    }
+}
+
+async fn plan_kubectl(
+    svc: &str,
+    region: &Region,
+    conf: &Config,
+    prune: bool,
+    passed_version: Option<String>,
+) -> Result<ApplyPlan> {
+    let mfbase = shipcat_filebacked::load_manifest(svc, conf, region).await?;
+    let s = ShipKube::new(&mfbase).await?;
+    let ver = VersionRequest::new(passed_version, false);
+    let (resolution, crd, mut reason) = resolve_version(svc, &mfbase, &s, ver).await?;
+    region.versioningScheme.verify(&resolution.resolved)?;
+
+    // Diff the CRD rather than applying it
+    let mfcrd = mfbase.version(resolution.resolved.clone());
+    let crd_diff = s.diff(mfcrd.clone()).await?;
+    if crd_diff.is_some() {
+        reason = reason.or(Some(UpgradeReason::ManifestChange));
+    }
+
+    // Template diff only possible if already installed
+    let mut prunes = vec![];
+    let template_diff = if let Some(o) = crd {
+        let mut mf = mfcrd.complete(region).await?;
+        if let Some(sum) = o.status.and_then(|s| s.secret_checksum) {
+            if mf.secrets_match(&sum) == Some(false) {
+                reason = reason.or(Some(UpgradeReason::SecretChecksum));
+            }
+        }
+        mf.uid = o.metadata.uid;
+        let tpl = helm::template(&mf, None).await?;
+        if prune {
+            prunes = prune_refs(&tpl, &s).await?;
+            if !prunes.is_empty() {
+                reason = reason.or(Some(UpgradeReason::TemplateDiff));
+            }
+        }
+        let kdiff = diff_kubeapi_full(&mf, &tpl, &s).await?;
+        if diff::minify(&kdiff).is_empty() {
+            None
+        } else {
+            reason = reason.or(Some(UpgradeReason::TemplateDiff));
+            Some(kdiff)
+        }
+    } else {
+        None
+    };
+
+    Ok(ApplyPlan {
+        name: svc.to_string(),
+        region: region.name.clone(),
+        namespace: region.namespace.clone(),
+        version: resolution,
+        reason,
+        crdDiff: crd_diff,
+        templateDiff: template_diff,
+        prunes,
+    })
+}
+
+/// First version of apply that does not use tiller
+///
+/// This writes events to uses the shipcatmanifest crd
+#[allow(clippy::cognitive_complexity, clippy::too_many_arguments)] // TODO: refactor this!
+async fn apply_kubectl(
+    svc: &str,
+    force: bool,
+    region: &Region,
+    conf: &Config,
+    wait: bool,
+    rollback: bool,
+    prune: bool,
+    recreate: bool,
+    ver: VersionRequest,
+    override_freeze: Option<String>,
+    lock: &DeployLock,
+) -> Result<Option<UpgradeInfo>> {
+    if let Err(e) = webhooks::ensure_requirements(&region) {
+        warn!("Could not ensure webhook requirements: {}", e);
+    }
+    let mfbase = shipcat_filebacked::load_manifest(&svc, &conf, &region).await?;
+
+    // Interact with the kube api to get the shipcatmanifest crd and its .status
+    // This lets us work out:
+    // - if the service has been installed before (negates the need for a diff)
+    // - if we need to apply a new crd (so we have an atomic change)
+    // - if secrets have been rotated since the last apply (via the stored secret checksum)
+    let s = ShipKube::new(&mfbase).await?;
+
+    // Next large batch is working out the reason for the upgrade (if any)
+    let (resolution, crd, mut reason) = resolve_version(svc, &mfbase, &s, ver).await?;
+    events::emit(ApplyEvent::VersionResolved {
+        service: svc.into(),
+        resolution: resolution.clone(),
+    });
+    let actual_version = resolution.resolved;
     let can_diff = crd.is_some();
+    // Remember what we can roll back to before the crd is replaced
+    let rollback_version = crd
+        .as_ref()
+        .and_then(|o| o.status.as_ref())
+        .and_then(|s| s.summary.as_ref())
+        .and_then(|s| s.last_successful_rollout_version.clone());
     debug!("using {}={}", svc, actual_version);
     // no shoehorning in illegal versions in the crd!
     region.versioningScheme.verify(&actual_version)?;
+    // before anything is notified or run
+    verify_canary(&mfbase)?;
 
     // Complete and apply the CRD
     let mfcrd = mfbase.version(actual_version.clone());
-    let crd_changed = s.apply(mfcrd.clone()).await?;
+
+    // Fetch all the secrets so we can create a completed manifest
+    // Done before the up to date check so that rotated secrets trigger an upgrade,
+    // but only every SECRET_CHECK_MINS when nothing else needs an upgrade.
+    let status = crd.as_ref().and_then(|o| o.status.clone()).unwrap_or_default();
+    let mut completed = None;
+    if force || reason.is_some() || secrets_check_due(status.secrets_checked.as_deref(), Utc::now()) {
+        let c = mfcrd.clone().complete(&region).await;
+        if let (Ok(m), Some(old)) = (&c, &status.secret_checksum) {
+            if m.secrets_match(old) == Some(false) {
+                info!("{} secrets changed since last apply", svc);
+                reason = reason.or(Some(UpgradeReason::SecretChecksum));
+            }
+        }
+        completed = Some(c);
+    }
+
+    // Refuse to change anything during a freeze (unless overridden)
+    let team = mfcrd.metadata.as_ref().map(|m| m.team.as_str());
+    let mut freeze = region
+        .active_freeze(Utc::now(), svc, team)
+        .map(|f| f.name.clone());
+    if let (Some(f), Some(why)) = (&freeze, &override_freeze) {
+        warn!("Overriding the {} freeze for {}: {}", f, svc, why);
+        freeze = None;
+    }
+    // crd changes are caught here, template changes once the full diff is known
+    if let Some(f) = &freeze {
+        if force || reason.is_some() || s.diff(mfcrd.clone()).await?.is_some() {
+            return Err(ErrorKind::DeployFrozen(svc.into(), f.clone()).into());
+        }
+    }
+
+    let crd_changed = if can_diff {
+        s.apply(mfcrd.clone()).await?
+    } else {
+        // creating the crd takes the lock that `lock_new` could not take
+        s.install(mfcrd.clone(), lock).await?;
+        true
+    };
+    events::emit(ApplyEvent::CrdApplied {
+        service: svc.into(),
+        changed: crd_changed,
+    });
     // Cheap reconcile ends here if !changed && !force
     if crd_changed {
         reason = reason.or(Some(UpgradeReason::ManifestChange));
     }
     if reason.is_none() && !force {
+        if let Some(Ok(m)) = &completed {
+            // record when we checked (and a baseline for checksums from older versions)
+            s.update_secret_checksum(&m.secret_checksum()).await?;
+        }
         info!("{} up to date (crd check)", svc);
         return Ok(None);
     }
 
     // Prepare for an actual upgrade now..
     let mut ui = UpgradeInfo::new(&mfcrd);
+    ui.freezeOverride = override_freeze;
     webhooks::apply_event(UpgradeState::Pending, &ui, &region, &conf).await;
 
-    // Fetch all the secrets so we can create a completed manifest
-    // TODO: check scp.status.secretChecksum against secret-manager instead
-    let mut mf = match mfcrd.complete(&region).await {
+    let completed = match completed {
+        Some(c) => c,
+        None => mfcrd.clone().complete(region).await,
+    };
+    let mut mf = match completed {
         Ok(m) => m,
         Err(e) => {
             // Fire failed events if secrets fail to resolve
@@ -249,20 +683,28 @@ async fn apply_kubectl(
     };
 
     // Create completed kubernetes yaml (via shipcat values | helm template)
-    let tfile = format!("{}.kube.gen.yml", svc);
-    let tpth = Path::new(".").join(tfile.clone());
-    if let Err(e) = helm::template(&mf, Some(tpth)).await {
-        // Errors here are obscure, and should not happen, but pass them up anyway
-        webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-        s.update_generate_false("ResolveFailure", e.description().to_string())
-            .await?;
-        return Err(e);
-    }
+    let tpl = match helm::template(&mf, None).await {
+        Ok(t) => t,
+        Err(e) => {
+            // Errors here are obscure, and should not happen, but pass them up anyway
+            webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
+            s.update_generate_false("ResolveFailure", e.description().to_string())
+                .await?;
+            return Err(e);
+        }
+    };
 
     // Attach diff to UpgradeInfo if diffing is possible
     if can_diff {
         // helm diff only supports diffing if already installed..
-        match diff_kubectl(&mf, &tfile).await {
+        let kdiff = diff_kubeapi(&mf, &tpl, &s, prune).await;
+        if let Ok(d) = &kdiff {
+            events::emit(ApplyEvent::DiffComputed {
+                service: svc.into(),
+                diff: d.clone(),
+            });
+        }
+        match kdiff {
             Ok(Some(kdiff)) => {
                 ui.diff = Some(kdiff);
                 reason = reason.or(Some(UpgradeReason::TemplateDiff));
@@ -290,14 +732,55 @@ async fn apply_kubectl(
         }
     }
 
+    // Only a template diff can get us here during a freeze
+    if let Some(f) = freeze {
+        webhooks::apply_event(UpgradeState::Cancelled, &ui, region, conf).await;
+        return Err(ErrorKind::DeployFrozen(svc.into(), f).into());
+    }
+
     // We cannot be here without a reason now, although you have to convince yourself.
     let ureason = reason.expect("cannot apply without a reason");
+    events::emit(ApplyEvent::Reason {
+        service: svc.into(),
+        reason: ureason.clone(),
+    });
     webhooks::apply_event(UpgradeState::Started, &ui, &region, &conf).await;
     s.update_generate_true().await?; // if this fails, stop, want .status to be correct
 
-    match upgrade_kubectl(&mf, &tfile).await {
+    // Pre-deploy hooks (e.g. migrations) must succeed before anything else changes
+    if let Err(e) = hooks::run(&mf, HookPhase::PreDeploy, &tpl, &s).await {
+        error!("{} from {}", e, ui.name);
+        webhooks::apply_event(UpgradeState::HookFailed, &ui, region, conf).await;
+        let reason = e.to_string();
+        s.update_apply_false(ureason.to_string(), "PreDeployHookFailure", reason)
+            .await?;
+        return Err(e);
+    }
+
+    // Progress through canary steps before touching the main workload
+    let canary = mf.rolloutStrategy.as_ref().and_then(|rs| rs.canary());
+    let mut canaried = false;
+    if let (Some(c), UpgradeReason::VersionChange) = (canary, &ureason) {
+        if !wait {
+            warn!("Skipping canary steps for {} (not waiting)", ui.name);
+        } else if let Err(e) = canary_kubectl(&mf, &tpl, c, &s).await {
+            error!("{} from {}", e, ui.name);
+            webhooks::apply_event(UpgradeState::Failed, &ui, region, conf).await;
+            let reason = e.to_string();
+            s.update_rollout_false(&actual_version, "CanaryFailure", reason)
+                .await?;
+            return Err(e);
+        } else {
+            canaried = true;
+        }
+    }
+
+    match upgrade_kubeapi(&mf, &tpl, &s, prune, recreate).await {
         Err(e) => {
             error!("{} from {}", e, ui.name);
+            if canaried {
+                let _ = s.delete_canary().await;
+            }
             webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
             let reason = e.description().to_string();
             s.update_apply_false(ureason.to_string(), "ApplyFailure", reason)
@@ -306,11 +789,28 @@ async fn apply_kubectl(
         }
         Ok(_) => {
             let _ = s.update_apply_true(ureason.to_string()).await;
+            let _ = s.update_secret_checksum(&mf.secret_checksum()).await;
             if !wait {
                 info!("successfully applied {} (without waiting)", ui.name);
             } else {
-                match track::workload_rollout(&mf, &s).await {
+                let rollout = track::workload_rollout(&mf, &s).await;
+                if canaried {
+                    // main workload has taken over (or failed), canary no longer needed
+                    let _ = s.delete_canary().await;
+                }
+                match rollout {
                     Ok(true) => {
+                        if let Err(e) = hooks::run(&mf, HookPhase::PostDeploy, &tpl, &s).await {
+                            error!("{} from {}", e, ui.name);
+                            webhooks::apply_event(UpgradeState::HookFailed, &ui, region, conf).await;
+                            let reason = e.to_string();
+                            s.update_rollout_false(&actual_version, "PostDeployHookFailure", reason)
+                                .await?;
+                            if rollback {
+                                rollback_kubectl(&s, &mf, rollback_version, prune, region, conf).await;
+                            }
+                            return Err(e);
+                        }
                         info!("successfully rolled out {}", &ui.name);
                         webhooks::apply_event(UpgradeState::Completed, &ui, &region, &conf).await;
                         s.update_rollout_true(&actual_version).await?;
@@ -323,76 +823,282 @@ async fn apply_kubectl(
                         // TODO: collect these for .status call ^?
                         warn!("failed to roll out {}", &ui.name);
                         webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-                        s.update_rollout_false("Timeout", reason).await?; // TODO: chain
+                        s.update_rollout_false(&actual_version, "Timeout", reason).await?; // TODO: chain
+                        if rollback {
+                            rollback_kubectl(&s, &mf, rollback_version, prune, region, conf).await;
+                        }
                         return Err(ErrorKind::UpgradeTimeout(mf.name.clone(), time).into());
                     }
                     Err(e) => {
                         webhooks::apply_event(UpgradeState::Failed, &ui, &region, &conf).await;
-                        s.update_rollout_false("RolloutTrackFailure", e.description().to_string())
-                            .await?; // TODO: chain
+                        let (cond, reason) = match e.kind() {
+                            ErrorKind::RolloutBlocked(_, cond, msg, logs) => {
+                                let logs = logs.as_ref().map(|l| format!("\n{}", l)).unwrap_or_default();
+                                (cond.clone(), format!("{}{}", msg, logs))
+                            }
+                            _ => ("RolloutTrackFailure".into(), e.description().to_string()),
+                        };
+                        s.update_rollout_false(&actual_version, &cond, reason).await?; // TODO: chain
+                        if rollback {
+                            rollback_kubectl(&s, &mf, rollback_version, prune, region, conf).await;
+                        }
                         return Err(e);
                     }
                 }
             }
         }
     };
-    // cleanups in non-error cases
-    let _ = fs::remove_file(&tfile).await;
     Ok(Some(ui))
 }
 
-/// Shell out to kubectl apply
+/// Roll back a failed rollout to the last successfully rolled out version
 ///
-/// Assumes you have written your template file from `helm template`
-async fn upgrade_kubectl(mf: &Manifest, tfile: &str) -> Result<()> {
-    // upgrade it using the same command
-    let applyvec = vec![
-        "apply".into(),
-        format!("-n={}", mf.namespace),
-        "-f".into(),
-        tfile.into(),
-        "--prune".into(),
-        // NB: assumes one deploy per namespace
-        format!("-l=app.kubernetes.io/name={}", mf.name),
-    ];
-    info!("kubectl {}", applyvec.join(" "));
-    kubectl::kexec(applyvec)
+/// Applies the crd and chart at the previous version and tracks that rollout.
+/// Errors are not propagated; the original rollout failure is what gets returned.
+/// Instead, everything is notified through the rollback variants of `UpgradeState`.
+async fn rollback_kubectl(
+    s: &ShipKube,
+    failed: &Manifest,
+    rollback_version: Option<String>,
+    prune: bool,
+    region: &Region,
+    conf: &Config,
+) {
+    let version = match rollback_version {
+        Some(v) => v,
+        None => {
+            warn!("Not rolling back {}: no successful rollout recorded", failed.name);
+            return;
+        }
+    };
+    if Some(&version) == failed.version.as_ref() {
+        warn!(
+            "Not rolling back {}: {} is the last successful version",
+            failed.name, version
+        );
+        return;
+    }
+    warn!("Rolling back {} to {}", failed.name, version);
+    let mfbase = match shipcat_filebacked::load_manifest(&failed.name, conf, region).await {
+        Ok(m) => m.version(version.clone()),
+        Err(e) => {
+            error!("Unable to load {} for rollback: {}", failed.name, e);
+            return;
+        }
+    };
+    let ui = UpgradeInfo::new(&mfbase);
+    webhooks::apply_event(UpgradeState::RollbackStarted, &ui, region, conf).await;
+    match rollback_kubectl_inner(s, mfbase, failed.uid.clone(), prune, region).await {
+        Ok(true) => {
+            info!("successfully rolled back {} to {}", ui.name, version);
+            webhooks::apply_event(UpgradeState::RollbackCompleted, &ui, region, conf).await;
+            let _ = s.update_rollout_true(&version).await;
+        }
+        Ok(false) => {
+            let _ = track::debug(failed, s).await;
+            warn!("failed to roll back {} to {}", ui.name, version);
+            webhooks::apply_event(UpgradeState::RollbackFailed, &ui, region, conf).await;
+            let reason = format!("timed out waiting for rollback to {}", version);
+            let _ = s.update_rollout_false(&version, "RollbackTimeout", reason).await;
+        }
+        Err(e) => {
+            error!("failed to roll back {} to {}: {}", ui.name, version, e);
+            webhooks::apply_event(UpgradeState::RollbackFailed, &ui, region, conf).await;
+            let reason = e.description().to_string();
+            let _ = s.update_rollout_false(&version, "RollbackFailure", reason).await;
+        }
+    }
+}
+
+async fn rollback_kubectl_inner(
+    s: &ShipKube,
+    mfcrd: Manifest,
+    uid: Option<String>,
+    prune: bool,
+    region: &Region,
+) -> Result<bool> {
+    s.apply(mfcrd.clone()).await?;
+    let mut mf = mfcrd.complete(region).await?;
+    mf.uid = uid;
+    let tpl = helm::template(&mf, None).await?;
+    // selector migrations are never undone automatically
+    upgrade_kubeapi(&mf, &tpl, s, prune, false).await?;
+    let _ = s.update_secret_checksum(&mf.secret_checksum()).await;
+    track::workload_rollout(&mf, s).await
+}
+
+/// Progress a canary rollout through its steps
+///
+/// Every step below 100% scales a `{name}-canary` Deployment and waits for it to be ready,
+/// then pauses while checking the canary pods for restarts.
+/// The final step is the normal upgrade of the main Deployment (done by the caller).
+/// The canary is removed if any step fails.
+async fn canary_kubectl(mf: &Manifest, tpl: &str, canary: &CanaryStrategy, s: &ShipKube) -> Result<()> {
+    for step in canary.steps.iter().filter(|st| st.weight < 100) {
+        if let Err(e) = canary_step(mf, tpl, step, canary.maxRestarts, s).await {
+            warn!("canary step {}% of {} failed: {}", step.weight, mf.name, e);
+            let _ = s.delete_canary().await;
+            return Err(e.chain_err(|| ErrorKind::CanaryFailure(mf.name.clone(), step.weight)));
+        }
+    }
+    Ok(())
+}
+
+async fn canary_step(
+    mf: &Manifest,
+    tpl: &str,
+    step: &CanaryStep,
+    max_restarts: u32,
+    s: &ShipKube,
+) -> Result<()> {
+    use futures_timer::Delay;
+    let replicas = step.replicas(mf.min_replicas());
+    info!(
+        "Canary step {}%: scaling {}-canary to {} replicas",
+        step.weight, mf.name, replicas
+    );
+    let dep = canary_deployment(tpl, &mf.name, replicas)?;
+    s.apply_object(&serde_json::to_value(dep)?, false).await?;
+    if !track::canary_rollout(mf, s, replicas).await? {
+        bail!("timed out waiting for {} canary replicas", replicas);
+    }
+    // health gate: no unexpected restarts while pausing
+    let pause = step.pause_seconds();
+    let mut paused = 0;
+    loop {
+        let restarts = track::canary_restarts(s).await?;
+        if restarts > max_restarts {
+            bail!("canary pods restarted {} times (max {})", restarts, max_restarts);
+        }
+        if paused >= pause {
+            break;
+        }
+        Delay::new(std::time::Duration::from_secs(10)).await;
+        paused += 10;
+    }
+    Ok(())
+}
+
+/// Ensure a canary rolloutStrategy can be rolled out
+///
+/// Only the native chart excludes canary pods from the main Deployment selector,
+/// so helm rendered services cannot use canaries.
+fn verify_canary(mf: &Manifest) -> Result<()> {
+    if mf.rolloutStrategy.as_ref().and_then(|rs| rs.canary()).is_some() && !chart::enabled(mf) {
+        bail!(
+            "{} has a canary rolloutStrategy, which needs the natively rendered {} chart",
+            mf.name,
+            chart::NATIVE_CHART
+        );
+    }
+    Ok(())
+}
+
+/// Derive the canary Deployment from the main Deployment in a `helm template` output
+///
+/// The canary pods keep the labels of the main pods (so they are picked up by the same
+/// Service), but are told apart by the `CANARY_LABEL` in the canary selector.
+/// The main Deployment must exclude that label from its selector, otherwise both
+/// Deployments would fight over the canary pods.
+fn canary_deployment(tpl: &str, name: &str, replicas: u32) -> Result<Deployment> {
+    for to in tpl.split("---") {
+        let mut dep: Deployment = match serde_yaml::from_str(to) {
+            Ok(d) => d,
+            Err(_) => continue, // not a Deployment
+        };
+        let mut meta = dep.metadata.unwrap_or_default();
+        if meta.name.as_deref() != Some(name) {
+            continue;
+        }
+        meta.name = Some(format!("{}-canary", name));
+        dep.metadata = Some(meta);
+
+        let mut spec = match dep.spec {
+            Some(s) => s,
+            None => bail!("Deployment {} has no spec", name),
+        };
+        spec.replicas = Some(replicas as i32);
+        let exprs = spec.selector.match_expressions.take().unwrap_or_default();
+        let (excluded, exprs): (Vec<_>, Vec<_>) = exprs.into_iter().partition(|e| e.key == CANARY_LABEL);
+        if !excluded.iter().any(|e| e.operator == "DoesNotExist") {
+            bail!(
+                "Deployment {} selector does not exclude canary pods (needs {} DoesNotExist)",
+                name,
+                CANARY_LABEL
+            );
+        }
+        if !exprs.is_empty() {
+            spec.selector.match_expressions = Some(exprs);
+        }
+        spec.selector
+            .match_labels
+            .get_or_insert_with(Default::default)
+            .insert(CANARY_LABEL.into(), "true".into());
+        spec.template
+            .metadata
+            .get_or_insert_with(Default::default)
+            .labels
+            .get_or_insert_with(Default::default)
+            .insert(CANARY_LABEL.into(), "true".into());
+        dep.spec = Some(spec);
+        return Ok(dep);
+    }
+    bail!("No Deployment {} found in the template", name)
+}
+
+/// Server-side apply of every object in a template
+///
+/// Objects are applied in template order with `kubeapi::FIELD_MANAGER` as the owner.
+/// With `prune`, objects of the service that are no longer in the template are deleted after.
+/// With `recreate`, Deployments whose selector changed are recreated.
+async fn upgrade_kubeapi(mf: &Manifest, tpl: &str, s: &ShipKube, prune: bool, recreate: bool) -> Result<()> {
+    let objs = helm::objects(tpl)?;
+    info!("applying {} objects for {}", objs.len(), mf.name);
+    s.apply_objects(&objs, recreate)
         .await
         .chain_err(|| ErrorKind::KubectlApplyFailure(mf.name.clone()))?;
+    if prune {
+        s.prune_objects(&objs)
+            .await
+            .chain_err(|| ErrorKind::KubectlApplyFailure(mf.name.clone()))?;
+    }
     Ok(())
 }
 
-/// Minified kubectl diff shell out
-///
-/// Requires kubernetes 1.13
-pub async fn diff_kubectl(mf: &Manifest, tfile: &str) -> Result<Option<String>> {
-    let namespace = mf.namespace.clone();
-    let pth = Path::new(tfile);
-    let (kdiffunobfusc, kdifferr, success) = kubectl::diff(pth.to_path_buf(), &namespace).await?;
+/// Identifiers of the objects pruning would delete after applying a template
+async fn prune_refs(tpl: &str, s: &ShipKube) -> Result<Vec<String>> {
+    let orphans = s.orphaned_objects(&helm::objects(tpl)?).await?;
+    Ok(orphans.iter().map(kubeapi::object_ref).collect())
+}
 
+/// Full dry-run server-side apply diff with secrets obfuscated
+async fn diff_kubeapi_full(mf: &Manifest, tpl: &str, s: &ShipKube) -> Result<String> {
+    let kdiffunobfusc = s.diff_objects(&helm::objects(tpl)?).await?;
     let kubediff = diff::obfuscate_secrets(
         kdiffunobfusc, // move this away quickly..
         mf.get_secrets(),
     );
     debug!("Full diff (obfuscated): \n{}", kubediff);
+    Ok(kubediff)
+}
 
-    if !success {
-        let err = kdifferr.trim();
-        if err != "exit status 1" {
-            warn!("diff {} stderr: {}", mf.name, kdifferr.trim());
-            bail!(
-                "kubectl diff {} returned: {}",
-                mf.name,
-                kdifferr.lines().next().unwrap()
-            );
-        }
+/// Structural dry-run server-side apply diff with secrets obfuscated
+///
+/// With `prune`, the objects that would be pruned are included as removed.
+pub async fn diff_kubeapi(
+    mf: &Manifest,
+    tpl: &str,
+    s: &ShipKube,
+    prune: bool,
+) -> Result<Option<diff::Changes>> {
+    let mut changes = s.diff_changes(&helm::objects(tpl)?).await?;
+    changes.obfuscate(&mf.get_secrets());
+    if prune {
+        changes = changes.removing(prune_refs(tpl, s).await?);
     }
-
-    let smalldiff = diff::minify(&kubediff);
-    Ok(if !smalldiff.is_empty() {
-        debug!("{}", kubediff); // full diff for logs
-        println!("{}", smalldiff);
-        Some(smalldiff)
+    Ok(if !changes.is_empty() {
+        events::print_human(&changes.render(false));
+        Some(changes)
     } else {
         None
     })
@@ -402,6 +1108,16 @@ pub async fn diff_kubectl(mf: &Manifest, tfile: &str) -> Result<Option<String>>
 ///
 /// Optionally wait for the main resource
 pub async fn restart(mf: &Manifest, wait: bool) -> Result<()> {
+    let sk = ShipKube::new(&mf).await?;
+    let lock = sk.lock("restart").await?;
+    let res = sk.renewing(&lock, restart_locked(mf, &sk, wait)).await;
+    if let Err(e) = sk.unlock(&lock).await {
+        warn!("Unable to unlock {}: {}", mf.name, e);
+    }
+    res
+}
+
+async fn restart_locked(mf: &Manifest, sk: &ShipKube, wait: bool) -> Result<()> {
     for w in &mf.workers {
         let r = Restartable {
             name: w.container.name.clone(),
@@ -424,16 +1140,15 @@ pub async fn restart(mf: &Manifest, wait: bool) -> Result<()> {
         );
         return Ok(());
     }
-    let sk = ShipKube::new(&mf).await?;
     // wait for primary if we are waiting
-    if track::workload_rollout(&mf, &sk).await? {
+    if track::workload_rollout(&mf, sk).await? {
         info!("successfully restarted {}/{}", mf.workload.to_string(), &mf.name);
         Ok(())
     } else {
         let time = mf.estimate_wait_time();
         let reason = format!("timed out waiting {}s for rollout to restart", time);
         //let _ = kubectl::debug_rollout_status(&mf).await;
-        let _ = track::debug(&mf, &sk).await;
+        let _ = track::debug(&mf, sk).await;
         warn!("failed to roll out {}", &mf.name);
         warn!("{}", reason);
         Err(ErrorKind::UpgradeTimeout(mf.name.clone(), time).into())
@@ -465,6 +1180,16 @@ async fn trigger_rollout_restart(r: Restartable) -> Result<()> {
 /// when (and only when) a service disappears from disk.
 pub async fn delete(svc: &str, reg: &Region, conf: &Config) -> Result<()> {
     let s = ShipKube::new_within(&svc, &reg.namespace).await?;
+    // NB: no unlock needed on success, the lock goes with the crd
+    let lock = s.lock("delete").await?;
+    let res = s.renewing(&lock, delete_locked(&s, svc, reg, conf)).await;
+    if res.is_err() {
+        let _ = s.unlock(&lock).await;
+    }
+    res
+}
+
+async fn delete_locked(s: &ShipKube, svc: &str, reg: &Region, conf: &Config) -> Result<()> {
     match s.get().await {
         // audit all events if it's possible to deserialize current crd
         Ok(mfk) => {
@@ -577,6 +1302,17 @@ impl ShipKube {
         self.patch(&data).await
     }
 
+    pub async fn update_secret_checksum(&self, checksum: &str) -> Result<()> {
+        debug!("Setting secret checksum");
+        let data = json!({
+            "status": {
+                "secretChecksum": checksum,
+                "secretsChecked": make_date(),
+            }
+        });
+        self.patch(&data).await
+    }
+
     pub async fn update_apply_false(&self, ureason: String, err: &str, reason: String) -> Result<()> {
         debug!("Setting applied false");
         let now = make_date();
@@ -597,10 +1333,11 @@ impl ShipKube {
         self.patch(&data).await
     }
 
-    pub async fn update_rollout_false(&self, err: &str, reason: String) -> Result<()> {
+    pub async fn update_rollout_false(&self, version: &str, err: &str, reason: String) -> Result<()> {
         debug!("Setting rolledout false");
         let cond = Condition::bad(&self.applier, err, reason.clone());
         let now = make_date();
+        let history = self.history_with(version, &cond).await;
         let data = json!({
             "status": {
                 "conditions": {
@@ -610,7 +1347,8 @@ impl ShipKube {
                     "lastRollout": now,
                     "lastFailureReason": reason,
                     "lastAction": "Rollout",
-                }
+                },
+                "history": history,
             }
         });
         self.patch(&data).await
@@ -620,6 +1358,7 @@ impl ShipKube {
         debug!("Setting rolledout true");
         let now = make_date();
         let cond = Condition::ok(&self.applier);
+        let history = self.history_with(version, &cond).await;
         let data = json!({
             "status": {
                 "conditions": {
@@ -631,9 +1370,136 @@ impl ShipKube {
                     "lastFailureReason": null,
                     "lastAction": "Rollout",
                     "lastSuccessfulRolloutVersion": version,
-                }
+                },
+                "history": history,
             }
         });
         self.patch(&data).await
     }
+
+    // helper to append a rollout record to the existing history
+    //
+    // merge patches replace lists, so the current history is fetched first
+    async fn history_with(&self, version: &str, cond: &Condition) -> Vec<RolloutRecord> {
+        let status = match self.get_minimal().await {
+            Ok(o) => o.status.unwrap_or_default(),
+            Err(e) => {
+                warn!("Unable to fetch rollout history: {}", e);
+                ManifestStatus::default()
+            }
+        };
+        let mut history = status.history;
+        history.push(RolloutRecord {
+            version: version.to_string(),
+            status: cond.status,
+            timestamp: cond.last_transition.clone(),
+            apply_reason: status.summary.and_then(|s| s.last_apply_reason),
+            message: cond.message.clone(),
+            source: cond.source.clone(),
+        });
+        let excess = history.len().saturating_sub(MAX_HISTORY);
+        history.drain(..excess);
+        history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canary_deployment, secrets_check_due, verify_canary, VersionRequest};
+    use crate::kubeapi::CANARY_LABEL;
+    use chrono::{Duration, Utc};
+    use shipcat_definitions::Manifest;
+
+    const TPL: &str = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: fake-ask
+spec:
+  selector:
+    app: fake-ask
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: fake-ask
+  labels:
+    app: fake-ask
+spec:
+  replicas: 4
+  selector:
+    matchLabels:
+      app: fake-ask
+    matchExpressions:
+    - key: shipcat.babylontech.co.uk/canary
+      operator: DoesNotExist
+  template:
+    metadata:
+      labels:
+        app: fake-ask
+    spec:
+      containers:
+      - name: fake-ask
+        image: "quay.io/babylonhealth/fake-ask:1.2.0"
+"#;
+
+    #[test]
+    fn canary_deployment_from_template() {
+        let dep = canary_deployment(TPL, "fake-ask", 1).unwrap();
+        assert_eq!(dep.metadata.unwrap().name, Some("fake-ask-canary".into()));
+        let spec = dep.spec.unwrap();
+        assert_eq!(spec.replicas, Some(1));
+        let selector = spec.selector.match_labels.unwrap();
+        assert_eq!(selector.get(CANARY_LABEL), Some(&"true".to_string()));
+        // keeps the app label so the service routes to canary pods
+        let labels = spec.template.metadata.unwrap().labels.unwrap();
+        assert_eq!(labels.get("app"), Some(&"fake-ask".to_string()));
+        assert_eq!(labels.get(CANARY_LABEL), Some(&"true".to_string()));
+        assert!(spec.selector.match_expressions.is_none());
+
+        assert!(canary_deployment(TPL, "other", 1).is_err());
+        // main selector would also match the canary pods
+        let overlapping = TPL.replace("operator: DoesNotExist", "operator: Exists");
+        assert!(canary_deployment(&overlapping, "fake-ask", 1).is_err());
+    }
+
+    #[test]
+    fn canary_needs_native_chart() {
+        let mut mf = Manifest::test("fake-ask");
+        assert!(verify_canary(&mf).is_ok());
+        // helm rendered charts do not exclude canary pods from the main selector
+        mf.chart = Some("custom".into());
+        mf.rolloutStrategy =
+            Some(serde_yaml::from_str("canary: { steps: [ { weight: 50 }, { weight: 100 } ] }").unwrap());
+        assert!(verify_canary(&mf).is_err());
+    }
+
+    #[test]
+    fn rollback_overrides_pinned_version() {
+        let pinned = Some("1.2.0".to_string());
+        let apply = VersionRequest::new(Some("1.1.0".into()), false);
+        assert!(apply.resolve("fake-ask", pinned.as_ref()).is_err());
+        let same = VersionRequest::new(Some("1.2.0".into()), false);
+        assert_eq!(same.resolve("fake-ask", pinned.as_ref()).unwrap(), pinned);
+        let unpinned = VersionRequest::new(None, false);
+        assert_eq!(unpinned.resolve("fake-ask", pinned.as_ref()).unwrap(), pinned);
+
+        let rollback = VersionRequest::new(Some("1.1.0".into()), true);
+        assert_eq!(
+            rollback.resolve("fake-ask", pinned.as_ref()).unwrap(),
+            Some("1.1.0".into())
+        );
+        assert_eq!(rollback.resolve("fake-ask", None).unwrap(), Some("1.1.0".into()));
+    }
+
+    #[test]
+    fn secret_checks_rate_limited() {
+        let now = Utc::now();
+        assert!(secrets_check_due(None, now));
+        assert!(secrets_check_due(Some("garbage"), now));
+        let recent = (now - Duration::minutes(5)).to_rfc3339();
+        assert!(!secrets_check_due(Some(&recent), now));
+        let stale = (now - Duration::hours(1)).to_rfc3339();
+        assert!(secrets_check_due(Some(&stale), now));
+    }
 }
@@ -96,6 +96,9 @@ struct DeploymentPayload {
     service: String,
     version: String,
     manifests_revision: String,
+    /// Reason given for applying during a deployment freeze
+    #[serde(skip_serializing_if = "Option::is_none")]
+    freeze_override: Option<String>,
 }
 impl DeploymentPayload {
     fn new(whc: &WHC, info: &UpgradeInfo) -> Self {
@@ -105,6 +108,7 @@ impl DeploymentPayload {
             service: info.name.clone(),
             version: info.version.clone(),
             manifests_revision: whc["SHIPCAT_AUDIT_REVISION"].clone(),
+            freeze_override: info.freezeOverride.clone(),
         }
     }
 }
@@ -239,4 +243,36 @@ mod tests {
         );
         assert_eq!(ae.domain_type, "reconciliation");
     }
+
+    #[test]
+    fn audit_rollback_has_distinct_status() {
+        let mut whc: BTreeMap<String, String> = BTreeMap::default();
+        whc.insert("SHIPCAT_AUDIT_CONTEXT_ID".into(), "egcontextid".into());
+        whc.insert("SHIPCAT_AUDIT_REVISION".into(), "egrevision".into());
+
+        let arp = audit::ReconciliationPayload::new(&whc, "region_name");
+        let ae = audit::AuditEvent::new(
+            audit::AuditType::Deployment,
+            &whc,
+            &UpgradeState::RollbackCompleted,
+            arp,
+        );
+        let json = serde_json::to_value(&ae).unwrap();
+        assert_eq!(json["status"], "ROLLBACK_COMPLETED");
+    }
+
+    #[test]
+    fn audit_deployment_records_freeze_override() {
+        let mut whc: BTreeMap<String, String> = BTreeMap::default();
+        whc.insert("SHIPCAT_AUDIT_REVISION".into(), "egrevision".into());
+
+        let mf = Manifest::test("fake-svc");
+        let mut ud = UpgradeInfo::new(&mf);
+        let plain = serde_json::to_value(audit::DeploymentPayload::new(&whc, &ud)).unwrap();
+        assert!(plain.get("freeze_override").is_none());
+
+        ud.freezeOverride = Some("hotfix for INC-123".into());
+        let json = serde_json::to_value(audit::DeploymentPayload::new(&whc, &ud)).unwrap();
+        assert_eq!(json["freeze_override"], "hotfix for INC-123");
+    }
 }
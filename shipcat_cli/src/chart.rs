@@ -0,0 +1,599 @@
+use k8s_openapi::{
+    api::{
+        apps::v1::Deployment,
+        autoscaling::v2beta2::HorizontalPodAutoscaler,
+        batch::v1::Job,
+        core::v1::{ConfigMap, Secret, Service, ServiceAccount},
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use super::{hooks::HookPhase, kubeapi::CANARY_LABEL, Manifest, Result};
+use shipcat_definitions::structs::{EnvVars, Hook};
+
+/// Name of the chart that can be rendered without helm
+pub const NATIVE_CHART: &str = "base";
+
+/// A kubernetes object generated from the base chart
+#[derive(Clone, Debug)]
+pub enum KubeObject {
+    ServiceAccount(ServiceAccount),
+    ConfigMap(ConfigMap),
+    Secret(Secret),
+    Service(Service),
+    Deployment(Box<Deployment>),
+    HorizontalPodAutoscaler(HorizontalPodAutoscaler),
+}
+
+impl KubeObject {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            KubeObject::ServiceAccount(_) => "ServiceAccount",
+            KubeObject::ConfigMap(_) => "ConfigMap",
+            KubeObject::Secret(_) => "Secret",
+            KubeObject::Service(_) => "Service",
+            KubeObject::Deployment(_) => "Deployment",
+            KubeObject::HorizontalPodAutoscaler(_) => "HorizontalPodAutoscaler",
+        }
+    }
+
+    pub fn metadata(&self) -> Option<&ObjectMeta> {
+        match self {
+            KubeObject::ServiceAccount(o) => o.metadata.as_ref(),
+            KubeObject::ConfigMap(o) => o.metadata.as_ref(),
+            KubeObject::Secret(o) => o.metadata.as_ref(),
+            KubeObject::Service(o) => o.metadata.as_ref(),
+            KubeObject::Deployment(o) => o.metadata.as_ref(),
+            KubeObject::HorizontalPodAutoscaler(o) => o.metadata.as_ref(),
+        }
+    }
+
+    /// Yaml document for the object (including the leading `---`)
+    pub fn to_yaml(&self) -> Result<String> {
+        let res = match self {
+            KubeObject::ServiceAccount(o) => serde_yaml::to_string(o),
+            KubeObject::ConfigMap(o) => serde_yaml::to_string(o),
+            KubeObject::Secret(o) => serde_yaml::to_string(o),
+            KubeObject::Service(o) => serde_yaml::to_string(o),
+            KubeObject::Deployment(o) => serde_yaml::to_string(o),
+            KubeObject::HorizontalPodAutoscaler(o) => serde_yaml::to_string(o),
+        };
+        Ok(res?)
+    }
+}
+
+/// Manifest properties whose templates have not been ported from the base chart
+pub fn unsupported(mf: &Manifest) -> Vec<&'static str> {
+    let mut res = vec![];
+    if !mf.sidecars.is_empty() {
+        res.push("sidecars");
+    }
+    if !mf.initContainers.is_empty() {
+        res.push("initContainers");
+    }
+    if !mf.workers.is_empty() {
+        res.push("workers");
+    }
+    if !mf.cronJobs.is_empty() {
+        res.push("cronJobs");
+    }
+    if !mf.rbac.is_empty() {
+        res.push("rbac");
+    }
+    if !mf.prometheusAlerts.is_empty() {
+        res.push("prometheusAlerts");
+    }
+    res
+}
+
+/// Whether a manifest should be rendered natively rather than through helm
+///
+/// Opt-in via `SHIPCAT_NATIVE_CHARTS=1` while the port is verified against the helm chart.
+/// Falls back to helm for other charts and for properties that have not been ported.
+pub fn enabled(mf: &Manifest) -> bool {
+    if std::env::var("SHIPCAT_NATIVE_CHARTS").unwrap_or_default() != "1" {
+        return false;
+    }
+    if mf.chart.as_deref() != Some(NATIVE_CHART) {
+        return false;
+    }
+    let missing = unsupported(mf);
+    if !missing.is_empty() {
+        debug!(
+            "Rendering {} through helm: {} not supported natively",
+            mf.name,
+            missing.join(", ")
+        );
+        return false;
+    }
+    true
+}
+
+/// Render the base chart for a completed manifest
+///
+/// Equivalent of `helm template charts/base` with the manifest as values.
+/// Objects are returned in the order they should be applied.
+pub fn render(mf: &Manifest) -> Result<Vec<KubeObject>> {
+    let missing = unsupported(mf);
+    if !missing.is_empty() {
+        bail!(
+            "Cannot render {} natively: {} not supported",
+            mf.name,
+            missing.join(", ")
+        );
+    }
+    let config = config_map(mf)?;
+    let secrets = secrets(mf)?;
+    // pod annotations that roll the deployment when configs or secrets change
+    let config_sum = checksum(config.iter().cloned().map(KubeObject::ConfigMap))?;
+    let secret_sum = checksum(secrets.iter().cloned().map(KubeObject::Secret))?;
+
+    let mut objs = vec![KubeObject::ServiceAccount(service_account(mf)?)];
+    objs.extend(config.map(KubeObject::ConfigMap));
+    objs.extend(secrets.into_iter().map(KubeObject::Secret));
+    objs.extend(service(mf)?.map(KubeObject::Service));
+    objs.push(KubeObject::Deployment(Box::new(deployment(
+        mf,
+        &config_sum,
+        &secret_sum,
+    )?)));
+    objs.extend(hpa(mf)?.map(KubeObject::HorizontalPodAutoscaler));
+    Ok(objs)
+}
+
+/// Serialize rendered objects into a multi-document yaml stream like `helm template`
+pub fn to_yaml(objs: &[KubeObject]) -> Result<String> {
+    let docs = objs.iter().map(KubeObject::to_yaml).collect::<Result<Vec<_>>>()?;
+    Ok(docs.join("\n") + "\n")
+}
+
+fn checksum(objs: impl Iterator<Item = KubeObject>) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for o in objs {
+        hasher.input(o.to_yaml()?.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.result()))
+}
+
+// metadata with the labels and ownerReferences from chart.shipcatRefs
+fn metadata(mf: &Manifest, name: &str, typed: bool) -> Value {
+    let mut labels = BTreeMap::new();
+    if typed {
+        labels.extend(mf.labels.clone());
+        labels.insert("type".to_string(), "service".to_string());
+    }
+    labels.insert("app".into(), mf.name.clone());
+    labels.insert("app.kubernetes.io/name".into(), mf.name.clone());
+    labels.insert(
+        "app.kubernetes.io/version".into(),
+        mf.version.clone().unwrap_or_default(),
+    );
+    labels.insert("app.kubernetes.io/managed-by".into(), "shipcat".into());
+    json!({
+        "name": name,
+        "labels": labels,
+        "ownerReferences": [{
+            "apiVersion": "babylontech.co.uk/v1",
+            "kind": "ShipcatManifest",
+            "controller": false,
+            "name": mf.name,
+            "uid": mf.uid.clone().unwrap_or_default(),
+        }]
+    })
+}
+
+fn service_account(mf: &Manifest) -> Result<ServiceAccount> {
+    Ok(serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ServiceAccount",
+        "metadata": metadata(mf, &mf.name, true),
+        "automountServiceAccountToken": !mf.rbac.is_empty(),
+    }))?)
+}
+
+fn config_map(mf: &Manifest) -> Result<Option<ConfigMap>> {
+    let cfg = match &mf.configs {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+    let data = cfg
+        .files
+        .iter()
+        .map(|f| (f.dest.clone(), f.value.clone().unwrap_or_default()))
+        .collect::<BTreeMap<_, _>>();
+    Ok(Some(serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": metadata(mf, &format!("{}-config", mf.name), true),
+        "data": data,
+    }))?))
+}
+
+fn secrets(mf: &Manifest) -> Result<Vec<Secret>> {
+    let mut res = vec![];
+    // secretFiles are base64 encoded already
+    for (k, v) in &mf.secretFiles {
+        res.push(serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": metadata(mf, k, false),
+            "type": "Opaque",
+            "data": { "file": v },
+        }))?);
+    }
+    if !mf.secrets.is_empty() {
+        let data = mf
+            .secrets
+            .iter()
+            .map(|(k, v)| (k.clone(), base64::encode(v)))
+            .collect::<BTreeMap<_, _>>();
+        res.push(serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": metadata(mf, &format!("{}-secrets", mf.name), false),
+            "type": "Opaque",
+            "data": data,
+        }))?);
+    }
+    Ok(res)
+}
+
+// health port if it differs from the main http port
+fn health_port(mf: &Manifest) -> Option<u32> {
+    let port = mf.health.as_ref().and_then(|h| h.port);
+    if port == mf.httpPort {
+        None
+    } else {
+        port
+    }
+}
+
+fn service(mf: &Manifest) -> Result<Option<Service>> {
+    let http = match mf.httpPort {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let mut ports = vec![json!({ "port": 80, "targetPort": http, "protocol": "TCP", "name": "http" })];
+    if let Some(hp) = health_port(mf) {
+        ports.push(json!({ "port": hp, "protocol": "TCP", "name": "health" }));
+    }
+    for p in &mf.ports {
+        ports.push(json!({ "port": p.port, "protocol": p.protocol, "name": p.name }));
+    }
+    Ok(Some(serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "Service",
+        "metadata": metadata(mf, &mf.name, true),
+        "spec": {
+            "ports": ports,
+            "selector": { "app": mf.name },
+        }
+    }))?))
+}
+
+fn env(mf: &Manifest, vars: &EnvVars) -> Vec<Value> {
+    let mut env = vec![];
+    for (k, v) in &vars.plain {
+        env.push(json!({ "name": k, "value": v }));
+    }
+    for k in &vars.secrets {
+        env.push(json!({
+            "name": k,
+            "valueFrom": {
+                "secretKeyRef": { "name": format!("{}-secrets", mf.name), "key": k }
+            }
+        }));
+    }
+    let builtins = vec![
+        ("SERVICE_NAME", mf.name.clone()),
+        ("ENV_NAME", mf.environment.clone()),
+        ("REGION_NAME", mf.region.clone()),
+        ("SERVICE_VERSION", mf.version.clone().unwrap_or_default()),
+    ];
+    for (k, v) in builtins {
+        env.push(json!({ "name": k, "value": v }));
+    }
+    if let Some(k) = &mf.kafka {
+        if k.mountPodIP {
+            env.push(json!({
+                "name": "HOST_NAME",
+                "valueFrom": { "fieldRef": { "fieldPath": "status.podIP" } }
+            }));
+        }
+    }
+    env
+}
+
+fn container(mf: &Manifest) -> Result<Value> {
+    let mut c = json!({
+        "name": mf.name,
+        "image": format!(
+            "{}:{}",
+            mf.image.clone().unwrap_or_default(),
+            mf.version.clone().unwrap_or_default()
+        ),
+        "imagePullPolicy": "IfNotPresent",
+        "resources": mf.resources,
+        "env": env(mf, &mf.env),
+    });
+    if !mf.command.is_empty() {
+        c["command"] = json!(mf.command);
+    }
+    if let Some(http) = mf.httpPort {
+        let mut ports = vec![json!({ "name": "http", "containerPort": http, "protocol": "TCP" })];
+        if let Some(hp) = health_port(mf) {
+            ports.push(json!({ "name": "health-http", "containerPort": hp, "protocol": "TCP" }));
+        }
+        for p in &mf.ports {
+            ports.push(json!({ "name": p.name, "containerPort": p.port, "protocol": p.protocol }));
+        }
+        c["ports"] = json!(ports);
+        if let Some(lp) = &mf.livenessProbe {
+            c["livenessProbe"] = serde_json::to_value(lp)?;
+        }
+        if let Some(rp) = &mf.readinessProbe {
+            c["readinessProbe"] = serde_json::to_value(rp)?;
+        } else if let Some(h) = &mf.health {
+            let port = if health_port(mf).is_some() {
+                "health-http"
+            } else {
+                "http"
+            };
+            c["readinessProbe"] = json!({
+                "httpGet": { "path": h.uri, "port": port },
+                "initialDelaySeconds": h.wait,
+                "periodSeconds": 5,
+            });
+        }
+    }
+    let mut mounts = vec![];
+    if let Some(cfg) = &mf.configs {
+        for f in &cfg.files {
+            mounts.push(json!({
+                "name": format!("{}-config-volume", mf.name),
+                "mountPath": format!("{}{}", cfg.mount, f.dest),
+                "subPath": f.dest,
+            }));
+        }
+    }
+    for vm in &mf.volumeMounts {
+        mounts.push(serde_json::to_value(vm)?);
+    }
+    c["volumeMounts"] = json!(mounts);
+    Ok(c)
+}
+
+fn deployment(mf: &Manifest, config_sum: &str, secret_sum: &str) -> Result<Deployment> {
+    let mut annotations = mf.podAnnotations.clone();
+    annotations.insert("checksum/config".into(), config_sum.into());
+    annotations.insert("checksum/secrets".into(), secret_sum.into());
+
+    let mut volumes = vec![];
+    if mf.configs.is_some() {
+        volumes.push(json!({
+            "name": format!("{}-config-volume", mf.name),
+            "configMap": { "name": format!("{}-config", mf.name) },
+        }));
+    }
+    for v in &mf.volumes {
+        volumes.push(serde_json::to_value(v)?);
+    }
+    let mut pod = json!({
+        "serviceAccountName": mf.name,
+        "containers": [container(mf)?],
+        "volumes": volumes,
+    });
+    if !mf.tolerations.is_empty() {
+        pod["tolerations"] = serde_json::to_value(&mf.tolerations)?;
+    }
+    if !mf.hostAliases.is_empty() {
+        pod["hostAliases"] = serde_json::to_value(&mf.hostAliases)?;
+    }
+
+    let mut rolling = serde_json::to_value(&mf.rollingUpdate)?;
+    if rolling.is_null() && mf.replicaCount == Some(1) {
+        rolling = json!({ "maxUnavailable": 0 });
+    }
+    let mut spec = json!({
+        "revisionHistoryLimit": 20,
+        "strategy": { "rollingUpdate": rolling },
+        "minReadySeconds": 10,
+        "selector": { "matchLabels": { "app": mf.name } },
+        "template": {
+            "metadata": {
+                "labels": { "app": mf.name },
+                "annotations": annotations,
+            },
+            "spec": pod,
+        }
+    });
+    if mf.autoScaling.is_none() {
+        spec["replicas"] = json!(mf.replicaCount);
+    }
+    if mf.rolloutStrategy.as_ref().and_then(|rs| rs.canary()).is_some() {
+        // canary pods share the app label, but belong to the canary Deployment
+        spec["selector"]["matchExpressions"] = json!([{ "key": CANARY_LABEL, "operator": "DoesNotExist" }]);
+    }
+    Ok(serde_json::from_value(json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "metadata": metadata(mf, &mf.name, true),
+        "spec": spec,
+    }))?)
+}
+
+fn hpa(mf: &Manifest) -> Result<Option<HorizontalPodAutoscaler>> {
+    let autoscaling = match &mf.autoScaling {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let mut spec = serde_json::to_value(autoscaling)?;
+    spec["scaleTargetRef"] = json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "name": mf.name,
+    });
+    Ok(Some(serde_json::from_value(json!({
+        "apiVersion": "autoscaling/v2beta2",
+        "kind": "HorizontalPodAutoscaler",
+        "metadata": metadata(mf, &mf.name, false),
+        "spec": spec,
+    }))?))
+}
+
+/// Job running a pre- or post-deploy hook for a manifest
+///
+/// Hook pods get their own `app` label so they are never selected by the service.
+pub fn hook_job(mf: &Manifest, hook: &Hook, phase: HookPhase) -> Result<Job> {
+    let name = format!("{}-{}", mf.name, hook.container.name);
+    let image = match (&hook.container.image, &hook.container.version) {
+        (Some(i), Some(v)) => format!("{}:{}", i, v),
+        _ => format!(
+            "{}:{}",
+            mf.image.clone().unwrap_or_default(),
+            mf.version.clone().unwrap_or_default()
+        ),
+    };
+    let mut c = json!({
+        "name": hook.container.name,
+        "image": image,
+        "imagePullPolicy": "IfNotPresent",
+        "resources": hook.container.resources.as_ref().or(mf.resources.as_ref()),
+        "env": env(mf, &hook.container.env),
+    });
+    if !hook.container.command.is_empty() {
+        c["command"] = json!(hook.container.command);
+    }
+    let mut md = metadata(mf, &name, false);
+    md["annotations"] = json!({ "shipcat.babylontech.co.uk/hook": phase.to_string() });
+    Ok(serde_json::from_value(json!({
+        "apiVersion": "batch/v1",
+        "kind": "Job",
+        "metadata": md,
+        "spec": {
+            "backoffLimit": hook.backoffLimit.unwrap_or(0),
+            "activeDeadlineSeconds": hook.timeout_secs(),
+            "template": {
+                "metadata": { "labels": { "app": name } },
+                "spec": {
+                    "serviceAccountName": mf.name,
+                    "restartPolicy": "Never",
+                    "containers": [c],
+                }
+            }
+        }
+    }))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hook_job, render, to_yaml, KubeObject};
+    use crate::{hooks::HookPhase, kubeapi::CANARY_LABEL};
+    use shipcat_definitions::{
+        structs::{HealthCheck, Hook},
+        Manifest,
+    };
+
+    #[test]
+    fn render_base_chart() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.image = Some("quay.io/babylonhealth/fake-ask".into());
+        mf.httpPort = Some(8000);
+        mf.replicaCount = Some(1);
+        mf.uid = Some("FAKE-GUID".into());
+        mf.health = Some(HealthCheck {
+            uri: "/health".into(),
+            wait: 30,
+            port: None,
+        });
+        mf.env.plain.insert("MODE".into(), "development".into());
+        mf.env.secrets.insert("DB_PASSWORD".into());
+        mf.secrets.insert("DB_PASSWORD".into(), "hunter2".into());
+
+        let objs = render(&mf).unwrap();
+        let kinds = objs.iter().map(KubeObject::kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["ServiceAccount", "Secret", "Service", "Deployment"]);
+        for o in &objs {
+            let md = o.metadata().unwrap();
+            let labels = md.labels.as_ref().unwrap();
+            assert_eq!(labels["app.kubernetes.io/managed-by"], "shipcat");
+            assert_eq!(labels["app.kubernetes.io/version"], "1.0.0");
+            let owner = &md.owner_references.as_ref().unwrap()[0];
+            assert_eq!(owner.kind, "ShipcatManifest");
+            assert_eq!(owner.uid, "FAKE-GUID");
+        }
+
+        let deploy = match &objs[3] {
+            KubeObject::Deployment(d) => d.spec.clone().unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(deploy.replicas, Some(1));
+        let pod = deploy.template.spec.unwrap();
+        let container = &pod.containers[0];
+        assert_eq!(
+            container.image.as_ref().unwrap(),
+            "quay.io/babylonhealth/fake-ask:1.0.0"
+        );
+        let env = container.env.clone().unwrap();
+        assert_eq!(env[0].name, "MODE");
+        let secret_ref = env[1].value_from.clone().unwrap().secret_key_ref.unwrap();
+        assert_eq!(secret_ref.name.unwrap(), "fake-ask-secrets");
+        let annotations = deploy.template.metadata.unwrap().annotations.unwrap();
+        assert_eq!(annotations["checksum/secrets"].len(), 64);
+        assert!(deploy.selector.match_expressions.is_none());
+
+        // helm compatible multi-document output
+        let tpl = to_yaml(&objs).unwrap();
+        assert_eq!(tpl.matches("---").count(), 4);
+        assert!(tpl.contains("kind: Deployment"));
+        assert!(!tpl.contains("hunter2"));
+
+        // canary pods are left to the canary Deployment
+        mf.rolloutStrategy =
+            Some(serde_yaml::from_str("canary: { steps: [ { weight: 50 }, { weight: 100 } ] }").unwrap());
+        let deploy = match &render(&mf).unwrap()[3] {
+            KubeObject::Deployment(d) => d.spec.clone().unwrap(),
+            _ => unreachable!(),
+        };
+        let exclude = &deploy.selector.match_expressions.unwrap()[0];
+        assert_eq!(exclude.key, CANARY_LABEL);
+        assert_eq!(exclude.operator, "DoesNotExist");
+    }
+
+    #[test]
+    fn render_hook_job() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.image = Some("quay.io/babylonhealth/fake-ask".into());
+        mf.secrets.insert("DB_PASSWORD".into(), "hunter2".into());
+        let mut hook = Hook::default();
+        hook.container.name = "migrate".into();
+        hook.container.command = vec!["./migrate".into()];
+        hook.container.env.secrets.insert("DB_PASSWORD".into());
+
+        let job = hook_job(&mf, &hook, HookPhase::PreDeploy).unwrap();
+        let md = job.metadata.unwrap();
+        assert_eq!(md.name.unwrap(), "fake-ask-migrate");
+        assert_eq!(
+            md.annotations.unwrap()["shipcat.babylontech.co.uk/hook"],
+            "preDeploy"
+        );
+        let spec = job.spec.unwrap();
+        assert_eq!(spec.backoff_limit, Some(0));
+        assert_eq!(spec.active_deadline_seconds, Some(600));
+        let labels = spec.template.metadata.unwrap().labels.unwrap();
+        assert_eq!(labels["app"], "fake-ask-migrate");
+        let pod = spec.template.spec.unwrap();
+        assert_eq!(pod.restart_policy.unwrap(), "Never");
+        let container = &pod.containers[0];
+        assert_eq!(
+            container.image.as_ref().unwrap(),
+            "quay.io/babylonhealth/fake-ask:1.0.0"
+        );
+        let env = container.env.clone().unwrap();
+        let secret_ref = env[0].value_from.clone().unwrap().secret_key_ref.unwrap();
+        assert_eq!(secret_ref.name.unwrap(), "fake-ask-secrets");
+    }
+}
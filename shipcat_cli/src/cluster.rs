@@ -4,17 +4,14 @@ use shipcat_filebacked::SimpleManifest;
 
 use super::{kubectl, Error, ErrorKind, Result};
 use crate::{
-    apply, diff, helm,
+    apply, chart, diff, helm,
     kubeapi::ShipKube,
+    report::{DiffReport, DiffResult, ReportFormat},
     webhooks::{self, UpgradeState},
 };
 
-struct DiffResult {
-    name: String,
-    diff: Option<String>,
-}
-async fn diff_summary(svc: String, conf: &Config, reg: &Region) -> Result<DiffResult> {
-    let mut mf = shipcat_filebacked::load_manifest(&svc, &conf, &reg)
+async fn diff_summary(svc: &str, conf: &Config, reg: &Region) -> Result<diff::Changes> {
+    let mut mf = shipcat_filebacked::load_manifest(svc, &conf, &reg)
         .await?
         .complete(&reg)
         .await?;
@@ -24,50 +21,30 @@ async fn diff_summary(svc: String, conf: &Config, reg: &Region) -> Result<DiffRe
     mf.version = mf.version.or(crd.spec.version);
     mf.uid = crd.metadata.uid;
     info!("diffing {}", mf.name);
-    let d = if let Some(kdiffunobfusc) = diff::template_vs_kubectl(&mf).await? {
-        let kubediff = diff::obfuscate_secrets(
-            kdiffunobfusc, // move this away quickly..
-            mf.get_secrets(),
-        );
-        let smalldiff = diff::minify(&kubediff);
-        Some(smalldiff)
-    } else {
-        None
-    };
-    Ok(DiffResult {
-        name: mf.name,
-        diff: d,
-    })
+    let mut changes = diff::template_changes(&mf).await?;
+    changes.obfuscate(&mf.get_secrets());
+    Ok(changes)
 }
 
 /// Diffs all services in a region
 ///
-/// Helper that shells out to kubectl diff in parallel.
-pub async fn mass_diff(conf: &Config, reg: &Region) -> Result<()> {
+/// Uses dry-run server-side applies in parallel, and prints a report in the given format.
+pub async fn mass_diff(conf: &Config, reg: &Region, fmt: ReportFormat) -> Result<()> {
     let svcs = shipcat_filebacked::available(conf, reg).await?;
     assert!(conf.has_secrets());
 
     let mut buffered = stream::iter(svcs)
-        .map(move |mf| diff_summary(mf.base.name, &conf, &reg))
+        .map(move |mf| async move {
+            let res = diff_summary(&mf.base.name, &conf, &reg).await;
+            (mf.base.name, res)
+        })
         .buffer_unordered(10);
 
-    let mut errs = vec![];
-    let mut diffs = vec![];
-    while let Some(r) = buffered.next().await {
-        match r {
-            Ok(dr) => diffs.push(dr),
-            Err(e) => errs.push(e),
-        }
-    }
-    for dr in diffs {
-        if let Some(diff) = dr.diff {
-            info!("{} diff output:\n{}", dr.name, diff);
-        } else {
-            info!("{} unchanged", dr.name)
-        }
-    }
-    if !errs.is_empty() {
-        for e in &errs {
+    let mut report = DiffReport::new(&reg.name);
+    let mut errs = 0;
+    while let Some((svc, r)) = buffered.next().await {
+        if let Err(e) = &r {
+            errs += 1;
             match e {
                 Error(ErrorKind::KubeError(e2), _) => {
                     warn!("{}", e2); // probably missing service (undiffeable)
@@ -82,7 +59,11 @@ pub async fn mass_diff(conf: &Config, reg: &Region) -> Result<()> {
                 }
             }
         }
-        bail!("Failed to diff {} manifests", errs.len());
+        report.push(DiffResult::new(&svc, r));
+    }
+    println!("{}", report.render(fmt)?);
+    if errs > 0 {
+        bail!("Failed to diff {} manifests", errs);
     }
     Ok(())
 }
@@ -96,8 +77,12 @@ async fn check_summary(svc: String, skipped: &[String], conf: &Config, reg: &Reg
     mf.uid = Some("FAKE-GUID".to_string());
 
     info!("verifying template for {}", mf.name);
-    let tpl = helm::template(&mf, None).await?;
-    helm::template_check(&mf, reg, skipped, &tpl)?;
+    if chart::enabled(&mf) {
+        helm::template_check_objects(&mf, reg, skipped, &chart::render(&mf)?)?;
+    } else {
+        let tpl = helm::template(&mf, None).await?;
+        helm::template_check(&mf, reg, skipped, &tpl)?;
+    }
     Ok(mf.name)
 }
 
@@ -216,7 +201,18 @@ async fn crd_reconcile(
     let mut buffered = stream::iter(svcs)
         .map(|mf| {
             debug!("Running CRD reconcile for {:?}", mf.base.name);
-            apply::apply(mf.base.name, force, &reg, &conf, wait_for_rollout, None)
+            apply::apply(
+                mf.base.name,
+                force,
+                &reg,
+                &conf,
+                wait_for_rollout,
+                reg.rollbackOnFailure,
+                true,
+                false,
+                None,
+                None,
+            )
         })
         .buffer_unordered(n_workers);
 
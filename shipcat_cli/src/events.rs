@@ -0,0 +1,157 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::{
+    apply::{UpgradeReason, VersionResolution},
+    diff::Changes,
+    webhooks::UpgradeState,
+    Error, Result,
+};
+
+/// Whether events are streamed to stdout (set once from the cli)
+static JSONL: AtomicBool = AtomicBool::new(false);
+
+/// How to report the progress of an apply
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Progress bars and diffs for terminals
+    Human,
+    /// One json event per line on stdout for CI and chatops
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "human" => Ok(Self::Human),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => bail!("Output format must be human or jsonl"),
+        }
+    }
+}
+
+/// Set the output format for the rest of the process
+pub fn set_output(fmt: OutputFormat) {
+    JSONL.store(fmt == OutputFormat::Jsonl, Ordering::SeqCst);
+}
+
+/// Whether events are streamed instead of human readable output
+pub fn is_jsonl() -> bool {
+    JSONL.load(Ordering::SeqCst)
+}
+
+/// A structured event emitted during an apply
+///
+/// States and reasons serialize exactly like `UpgradeState` and `UpgradeReason`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum ApplyEvent {
+    /// The version to apply has been decided
+    #[serde(rename_all = "camelCase")]
+    VersionResolved {
+        service: String,
+        #[serde(flatten)]
+        resolution: VersionResolution,
+    },
+    /// The shipcatmanifest crd has been applied
+    #[serde(rename_all = "camelCase")]
+    CrdApplied { service: String, changed: bool },
+    /// The generated objects have been diffed against the cluster
+    #[serde(rename_all = "camelCase")]
+    DiffComputed { service: String, diff: Option<Changes> },
+    /// The reason the upgrade is going ahead
+    #[serde(rename_all = "camelCase")]
+    Reason { service: String, reason: UpgradeReason },
+    /// The upgrade moved to a new state (same as the webhook events)
+    #[serde(rename_all = "camelCase")]
+    State { service: String, state: UpgradeState },
+    /// Rollout progress of a workload
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        service: String,
+        workload: String,
+        progress: u32,
+        expected: u32,
+        message: Option<String>,
+    },
+    /// A warning from kubernetes about an object of the service (e.g. FailedScheduling)
+    #[serde(rename_all = "camelCase")]
+    KubeEvent {
+        service: String,
+        object: String,
+        reason: String,
+        message: String,
+    },
+    /// Pods of a workload cannot become ready
+    #[serde(rename_all = "camelCase")]
+    PodFailure {
+        service: String,
+        workload: String,
+        reason: String,
+        message: String,
+        logs: Option<String>,
+    },
+}
+
+/// Print an event as a json line (if streaming events)
+pub fn emit(ev: ApplyEvent) {
+    if !is_jsonl() {
+        return;
+    }
+    match serde_json::to_string(&ev) {
+        Ok(line) => println!("{}", line),
+        Err(e) => warn!("Failed to serialize apply event: {}", e),
+    }
+}
+
+/// Print human readable output
+///
+/// Moved to stderr when stdout is used for streaming events.
+pub fn print_human(msg: &str) {
+    if is_jsonl() {
+        eprintln!("{}", msg);
+    } else {
+        println!("{}", msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApplyEvent;
+    use crate::{apply::UpgradeReason, webhooks::UpgradeState};
+
+    #[test]
+    fn event_vocabulary() {
+        let state = ApplyEvent::State {
+            service: "fake-ask".into(),
+            state: UpgradeState::RollbackStarted,
+        };
+        assert_eq!(
+            serde_json::to_string(&state).unwrap(),
+            r#"{"event":"state","service":"fake-ask","state":"ROLLBACK_STARTED"}"#
+        );
+        let reason = ApplyEvent::Reason {
+            service: "fake-ask".into(),
+            reason: UpgradeReason::VersionChange,
+        };
+        assert_eq!(
+            serde_json::to_string(&reason).unwrap(),
+            r#"{"event":"reason","service":"fake-ask","reason":"VersionChange"}"#
+        );
+        let progress = ApplyEvent::Progress {
+            service: "fake-ask".into(),
+            workload: "fake-ask-worker".into(),
+            progress: 1,
+            expected: 2,
+            message: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&progress).unwrap(),
+            r#"{"event":"progress","service":"fake-ask","workload":"fake-ask-worker","progress":1,"expected":2,"message":null}"#
+        );
+    }
+}
@@ -1,10 +1,18 @@
 use super::Result;
-use std::process::Command;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 // Dumb git wrapper that validates output or bails
 fn exec(args: &[&str]) -> Result<String> {
-    debug!("git {}", args.join(" "));
-    let s = Command::new("git").args(args).output()?;
+    exec_in(Path::new("."), args)
+}
+
+// Same as exec, but run from another directory
+fn exec_in(dir: &Path, args: &[&str]) -> Result<String> {
+    debug!("git -C {} {}", dir.display(), args.join(" "));
+    let s = Command::new("git").arg("-C").arg(dir).args(args).output()?;
     if !s.status.success() {
         bail!("Subprocess failure from git: {}", s.status.code().unwrap_or(1001))
     }
@@ -23,28 +31,115 @@ pub fn merge_base() -> Result<String> {
     Ok(out.trim().to_string())
 }
 
-// Are there local changes in the index or working copy?
-pub fn needs_stash() -> bool {
-    exec(&["diff", "--quiet", "--exit-code"]).is_err()
-        || exec(&["diff", "--cached", "--quiet", "--exit-code"]).is_err()
+/// A temporary checkout of a git reference
+///
+/// Lives in the temp dir rather than the working copy, and is removed when dropped.
+pub struct Worktree {
+    /// Directory in the repository the worktree was added from
+    repo: PathBuf,
+    root: PathBuf,
+    /// The current directory's equivalent inside the worktree
+    pub dir: PathBuf,
 }
 
-// git stash
-pub fn stash_push() -> Result<String> {
-    exec(&["stash", "--quiet"])
-}
+impl Worktree {
+    pub fn add(reference: &str) -> Result<Self> {
+        Worktree::add_in(Path::new("."), reference)
+    }
 
-// git stash pop
-pub fn stash_pop() -> Result<String> {
-    exec(&["stash", "pop", "--quiet"])
+    fn add_in(repo: &Path, reference: &str) -> Result<Self> {
+        let commit = format!("{}^{{commit}}", reference);
+        if exec_in(repo, &["rev-parse", "--verify", "--quiet", &commit]).is_err() {
+            bail!("{} is not a valid git reference", reference);
+        }
+        let id = uuid::Uuid::new_v4().to_simple().to_string();
+        let root = std::env::temp_dir().join(format!("shipcat-{}", id));
+        let path = root.to_string_lossy();
+        exec_in(repo, &["worktree", "add", "--detach", "--quiet", &path, &commit])?;
+        // path of the directory relative to the top of the repository
+        let prefix = exec_in(repo, &["rev-parse", "--show-prefix"])?;
+        let dir = root.join(prefix.trim());
+        Ok(Worktree {
+            repo: repo.to_path_buf(),
+            root,
+            dir,
+        })
+    }
 }
 
-// git checkout <ref>
-pub fn checkout(reference: &str) -> Result<String> {
-    exec(&["checkout", reference, "--quiet"])
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let root = self.root.to_string_lossy();
+        if let Err(e) = exec_in(&self.repo, &["worktree", "remove", "--force", &root]) {
+            warn!("Failed to remove git worktree {}: {}", root, e);
+        }
+    }
 }
 
 // git diff --name-only <ref>
 pub fn diff_filenames(reference: &str) -> Result<String> {
     exec(&["diff", "--name-only", reference])
 }
+
+// Dumb git diff helper that matches normal service files:
+//
+// Effectively checks:
+// git diff --name-only $(git merge-base origin/master HEAD) | grep ./services/{svc}/*
+pub fn changed_services() -> Result<Vec<String>> {
+    use regex::Regex;
+    let merge_base = merge_base()?;
+    let diff_output = diff_filenames(&merge_base)?;
+    let svc_re = Regex::new(r"^services/(?P<svc>[0-9a-z\-]{1,50})/").unwrap();
+    let mut res = vec![];
+    for l in diff_output.lines() {
+        if let Some(caps) = svc_re.captures(l) {
+            if let Some(svc) = caps.name("svc") {
+                let svc = svc.as_str().to_string();
+                if !res.contains(&svc) {
+                    res.push(svc);
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exec_in, Worktree};
+    use std::fs;
+
+    #[test]
+    fn worktree_cleanup() {
+        let id = uuid::Uuid::new_v4().to_simple().to_string();
+        let repo = std::env::temp_dir().join(format!("shipcat-test-{}", id));
+        let svcs = repo.join("services");
+        fs::create_dir_all(svcs.join("fake")).unwrap();
+        fs::write(svcs.join("fake/manifest.yml"), "name: fake\n").unwrap();
+        let git = |args: &[&str]| exec_in(&repo, args).unwrap();
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=shipcat",
+            "-c",
+            "user.email=shipcat@example.com",
+            "commit",
+            "--quiet",
+            "--no-gpg-sign",
+            "-m",
+            "init",
+        ]);
+
+        // the worktree mirrors the directory it was added from
+        let wt = Worktree::add_in(&svcs, "HEAD").unwrap();
+        let root = wt.root.clone();
+        assert!(wt.dir.join("fake/manifest.yml").is_file());
+        assert_eq!(wt.dir, root.join("services/"));
+        drop(wt);
+        assert!(!root.exists());
+        assert!(Worktree::add_in(&svcs, "not-a-ref").is_err());
+
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -128,13 +128,29 @@ pub async fn generate(service: &str, conf: &Config, reg: &Region, dot: bool) ->
 ///
 /// But it would require: TODO: optionally filter edges around node(s)
 pub async fn full(dot: bool, conf: &Config, reg: &Region) -> Result<CatGraph> {
+    let graph = full_graph(conf, reg).await?;
+    let out = if dot {
+        format!("{:?}", dot::Dot::with_config(&graph, &[dot::Config::EdgeNoLabel]))
+    } else {
+        serde_yaml::to_string(&graph)?
+    };
+    println!("{}", out);
+    Ok(graph)
+}
+
+/// Generate dependency graph from services directory without printing it
+pub async fn full_graph(conf: &Config, reg: &Region) -> Result<CatGraph> {
     let mut graph: CatGraph = DiGraph::<_, _>::new();
     for svc in shipcat_filebacked::available(conf, reg).await? {
         debug!("Scanning service {:?}", svc);
 
         let mf = shipcat_filebacked::load_manifest(&svc.base.name, conf, reg).await?;
-        let node = ManifestNode::new(&mf);
-        let idx = graph.add_node(node);
+        // might have been added as a dependency of an earlier service
+        let idx = if let Some(id) = nodeidx_from_name(&mf.name, &graph) {
+            id
+        } else {
+            graph.add_node(ManifestNode::new(&mf))
+        };
 
         for dep in &mf.dependencies {
             let subidx = if let Some(id) = nodeidx_from_name(&dep.name, &graph) {
@@ -149,14 +165,63 @@ pub async fn full(dot: bool, conf: &Config, reg: &Region) -> Result<CatGraph> {
             graph.update_edge(idx, subidx, DepEdge::new(&dep));
         }
     }
+    Ok(graph)
+}
 
-    let out = if dot {
-        format!("{:?}", dot::Dot::with_config(&graph, &[dot::Config::EdgeNoLabel]))
-    } else {
-        serde_yaml::to_string(&graph)?
+/// Group services into waves that can be applied in parallel
+///
+/// Every service is placed in a later wave than any of its dependencies,
+/// including dependencies reached indirectly through services not requested.
+/// Services missing from the graph have no known dependencies and go first.
+pub fn waves(graph: &CatGraph, svcs: &[String]) -> Result<Vec<Vec<String>>> {
+    use petgraph::{algo::toposort, visit::Dfs};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    // Graph of only the requested services with an edge for every dependency path
+    // (services requested more than once only get one node)
+    let mut reduced: DiGraph<String, ()> = DiGraph::new();
+    let idxs: BTreeMap<String, NodeIndex> = svcs
+        .iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|s| (s.clone(), reduced.add_node(s.clone())))
+        .collect();
+    for (svc, ridx) in &idxs {
+        if let Some(start) = nodeidx_from_name(svc, graph) {
+            let mut dfs = Dfs::new(graph, start);
+            while let Some(nx) = dfs.next(graph) {
+                let name = &graph[nx].name;
+                if let Some(dep) = idxs.get(name) {
+                    if name != svc {
+                        reduced.update_edge(*ridx, *dep, ());
+                    }
+                }
+            }
+        }
+    }
+
+    // edges point from dependents to dependencies, so apply in reverse topological order
+    let sorted = match toposort(&reduced, None) {
+        Ok(s) => s,
+        Err(cycle) => bail!("Circular dependency involving {}", reduced[cycle.node_id()]),
     };
-    println!("{}", out);
-    Ok(graph)
+    let mut level: BTreeMap<NodeIndex, usize> = BTreeMap::new();
+    for nx in sorted.into_iter().rev() {
+        let lvl = reduced
+            .neighbors(nx)
+            .map(|dep| level[&dep] + 1)
+            .max()
+            .unwrap_or(0);
+        level.insert(nx, lvl);
+    }
+    let mut res: Vec<Vec<String>> = vec![];
+    for (nx, lvl) in level {
+        if res.len() <= lvl {
+            res.resize(lvl + 1, vec![]);
+        }
+        res[lvl].push(reduced[nx].clone());
+    }
+    Ok(res)
 }
 
 /// Generate first level reverse dependencies for a service
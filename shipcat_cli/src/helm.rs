@@ -9,7 +9,7 @@ use tokio::{
     process::Command,
 };
 
-use super::Result;
+use super::{chart, Result};
 use shipcat_definitions::{Manifest, ReconciliationMode, Region};
 
 pub fn hexists() -> Result<()> {
@@ -86,11 +86,17 @@ pub async fn values(mf: &Manifest, output: &str) -> Result<()> {
 
 /// Analogue of helm template
 ///
-/// Generates helm values to disk, then passes it to helm template
+/// Generates helm values to disk, then passes it to helm template.
+/// Charts that can be rendered natively skip helm entirely (see `chart::enabled`).
 pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String> {
-    let hfile = format!("{}.helm.gen.yml", mf.name);
-    values(&mf, &hfile).await?;
-
+    if chart::enabled(mf) {
+        debug!("Rendering {} natively", mf.name);
+        let tpl = chart::to_yaml(&chart::render(mf)?)?;
+        if let Some(o) = &output {
+            write_template(mf, o, &tpl).await?;
+        }
+        return Ok(tpl);
+    }
     let chart = mf.chart.clone().unwrap();
     if chart.starts_with("git@") {
         let (_tpl, tplerr, success) = clone_chart(&chart).await?;
@@ -99,6 +105,9 @@ pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String>
             bail!("helm failed to fetch template");
         }
     }
+    let hfile = format!("{}.helm.gen.yml", mf.name);
+    values(&mf, &hfile).await?;
+
     // helm template with correct params
     let tplvec = vec![
         "template".into(),
@@ -107,30 +116,58 @@ pub async fn template(mf: &Manifest, output: Option<PathBuf>) -> Result<String>
         hfile.clone(),
     ];
     // NB: this call does NOT need --tiller-namespace (offline call)
-    let (tpl, tplerr, success) = hout(tplvec.clone()).await?;
+    let res = hout(tplvec.clone()).await;
+    // values contain secrets, don't leave them lying around
+    if let Err(e) = fs::remove_file(&hfile).await {
+        warn!("Failed to delete file: {} {}", hfile, e);
+    }
+    let (tpl, tplerr, success) = res?;
     if !success {
         warn!("{} stderr: {}", tplvec.join(" "), tplerr);
         bail!("helm template failed");
     }
     if let Some(o) = &output {
-        let pth = Path::new(".").join(o);
-        debug!("Writing helm template for {} to {}", mf.name, pth.display());
-        let mut f = File::create(&pth).await?;
-        f.write_all(&tpl.as_bytes()).await?;
-        f.sync_data().await?;
-        debug!(
-            "Wrote helm template for {} to {}: \n{}",
-            mf.name,
-            pth.display(),
-            tpl
-        );
-        if let Err(e) = fs::remove_file(&hfile).await {
-            warn!("Failed to delete file: {} {}", hfile, e);
-        }
+        write_template(mf, o, &tpl).await?;
     }
     Ok(tpl)
 }
 
+async fn write_template(mf: &Manifest, output: &Path, tpl: &str) -> Result<()> {
+    let pth = Path::new(".").join(output);
+    debug!("Writing helm template for {} to {}", mf.name, pth.display());
+    let mut f = File::create(&pth).await?;
+    f.write_all(&tpl.as_bytes()).await?;
+    f.sync_data().await?;
+    debug!(
+        "Wrote helm template for {} to {}: \n{}",
+        mf.name,
+        pth.display(),
+        tpl
+    );
+    Ok(())
+}
+
+/// Split the output of `template` into its objects
+///
+/// Documents without a kind (e.g. empty or comment only) are skipped.
+pub fn objects(tpl: &str) -> Result<Vec<serde_json::Value>> {
+    let mut res = vec![];
+    for doc in tpl.split("\n---") {
+        let empty = doc
+            .lines()
+            .map(str::trim)
+            .all(|l| l.is_empty() || l.starts_with('#') || l == "---");
+        if empty {
+            continue;
+        }
+        let obj: serde_json::Value = serde_yaml::from_str(doc)?;
+        if obj.get("kind").is_some() {
+            res.push(obj);
+        }
+    }
+    Ok(res)
+}
+
 /// Helper to validate the assumption of the charts
 ///
 /// This is an addon to checks done through `kubeval`.
@@ -157,23 +194,28 @@ pub fn template_check(mf: &Manifest, reg: &Region, skipped: &[String], tpl: &str
             }
             Ok(o) => o,
         };
-        let name = &obj
-            .metadata
-            .name
-            .clone()
-            .unwrap_or_else(|| format!("unset metadata.name from {}", kind));
+        invalids.extend(check_object(mf, reg, skipped, &kind, &obj.metadata)?);
+    }
+    if !invalids.is_empty() {
+        bail!("Invalid objects: {:?}", invalids);
+    }
+    Ok(())
+}
 
-        let tiller_ok = check_no_tiller_refs(&kind, &obj)?;
-        let ok = match reg.reconciliationMode {
-            ReconciliationMode::CrdOwned => {
-                let owner_ok = check_owner_refs(mf, &kind, &obj)?;
-                let labels_ok = check_labels(mf, &kind, skipped, &obj)?;
-                labels_ok && owner_ok
-            }
-        } && tiller_ok;
-        if !ok {
-            invalids.push(format!("{} {{ {} }}", kind, name));
-        }
+/// Variant of `template_check` for natively rendered objects
+///
+/// Inspects the objects directly rather than re-parsing yaml.
+pub fn template_check_objects(
+    mf: &Manifest,
+    reg: &Region,
+    skipped: &[String],
+    objs: &[chart::KubeObject],
+) -> Result<()> {
+    let mut invalids = vec![];
+    for o in objs {
+        debug!("Checking: {}", o.kind());
+        let meta = o.metadata().cloned().unwrap_or_default();
+        invalids.extend(check_object(mf, reg, skipped, o.kind(), &meta)?);
     }
     if !invalids.is_empty() {
         bail!("Invalid objects: {:?}", invalids);
@@ -181,6 +223,34 @@ pub fn template_check(mf: &Manifest, reg: &Region, skipped: &[String], tpl: &str
     Ok(())
 }
 
+// returns a description of the object if it is invalid
+fn check_object(
+    mf: &Manifest,
+    reg: &Region,
+    skipped: &[String],
+    kind: &str,
+    meta: &ObjectMeta,
+) -> Result<Option<String>> {
+    let name = meta
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("unset metadata.name from {}", kind));
+
+    let tiller_ok = check_no_tiller_refs(kind, meta)?;
+    let ok = match reg.reconciliationMode {
+        ReconciliationMode::CrdOwned => {
+            let owner_ok = check_owner_refs(mf, kind, meta)?;
+            let labels_ok = check_labels(mf, kind, skipped, meta)?;
+            labels_ok && owner_ok
+        }
+    } && tiller_ok;
+    Ok(if ok {
+        None
+    } else {
+        Some(format!("{} {{ {} }}", kind, name))
+    })
+}
+
 use kube::api::{ObjectMeta, TypeMeta};
 #[derive(Deserialize)]
 struct PartialObject {
@@ -194,9 +264,9 @@ struct KubeObject {
     metadata: ObjectMeta,
 }
 
-fn check_labels(mf: &Manifest, kind: &str, skipped: &[String], obj: &KubeObject) -> Result<bool> {
+fn check_labels(mf: &Manifest, kind: &str, skipped: &[String], meta: &ObjectMeta) -> Result<bool> {
     let mut success = true;
-    let labels = &obj.metadata.labels.clone().unwrap_or_else(BTreeMap::new);
+    let labels = &meta.labels.clone().unwrap_or_else(BTreeMap::new);
     match labels.get("app.kubernetes.io/name") {
         Some(n) => {
             if n == &mf.name {
@@ -249,10 +319,10 @@ fn check_labels(mf: &Manifest, kind: &str, skipped: &[String], obj: &KubeObject)
     Ok(success)
 }
 
-fn check_owner_refs(mf: &Manifest, kind: &str, obj: &KubeObject) -> Result<bool> {
+fn check_owner_refs(mf: &Manifest, kind: &str, meta: &ObjectMeta) -> Result<bool> {
     let mut success = true;
     // First ownerReferences must be ShipcatManifest
-    match obj.metadata.owner_references.clone().unwrap_or_default().first() {
+    match meta.owner_references.clone().unwrap_or_default().first() {
         Some(or) => {
             if or.kind == "ShipcatManifest" && or.controller != Some(true) && or.name == mf.name {
                 debug!("{}: valid ownerReference for {}", kind, or.kind);
@@ -282,10 +352,9 @@ fn check_owner_refs(mf: &Manifest, kind: &str, obj: &KubeObject) -> Result<bool>
 }
 
 // charts should not reference tiller
-fn check_no_tiller_refs(kind: &str, obj: &KubeObject) -> Result<bool> {
+fn check_no_tiller_refs(kind: &str, meta: &ObjectMeta) -> Result<bool> {
     let mut success = true;
-    let labels = &obj
-        .metadata
+    let labels = &meta
         .labels
         .as_ref()
         .unwrap_or_else(|| panic!("kind {} has labels", kind));
@@ -0,0 +1,216 @@
+use crate::{kubeapi::ShipKube, Config, Region, Result};
+use shipcat_definitions::status::{Applier, Condition, ManifestStatus};
+
+/// An entry in the deploy timeline of a service
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// When the action happened (RFC 3339 timestamp)
+    pub timestamp: String,
+    /// Action performed (Generate, Apply, Rollout)
+    pub action: String,
+    /// Version the action was performed with
+    pub version: String,
+    /// Whether the action succeeded
+    pub ok: bool,
+    /// Originator of the action (if known)
+    pub source: Option<String>,
+    /// Best effort reason or failure message
+    pub reason: Option<String>,
+}
+
+fn applier_name(a: &Option<Applier>) -> Option<String> {
+    a.as_ref().map(|a| a.name.clone())
+}
+
+fn condition_entry(action: &str, cond: &Condition, version: &str, reason: Option<String>) -> HistoryEntry {
+    HistoryEntry {
+        timestamp: cond.last_transition.clone(),
+        action: action.into(),
+        version: version.into(),
+        ok: cond.status,
+        source: applier_name(&cond.source),
+        reason: if cond.status { reason } else { cond.message.clone() },
+    }
+}
+
+/// Reconstruct the deploy timeline from a shipcatmanifest status
+///
+/// Rollouts come from `.status.history`, falling back to the summary for
+/// manifests that predate it. Generate and apply conditions newer than the
+/// last rollout are included as they represent an unfinished or failed apply.
+/// `version` is the version currently requested in the crd.
+pub fn timeline(status: &ManifestStatus, version: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = status
+        .history
+        .iter()
+        .map(|r| HistoryEntry {
+            timestamp: r.timestamp.clone(),
+            action: "Rollout".into(),
+            version: r.version.clone(),
+            ok: r.status,
+            source: applier_name(&r.source),
+            reason: if r.status {
+                r.apply_reason.clone()
+            } else {
+                r.message.clone()
+            },
+        })
+        .collect();
+
+    let summary = status.summary.clone().unwrap_or_default();
+    if entries.is_empty() {
+        if let (Some(ts), Some(ver)) = (
+            &summary.last_successful_rollout,
+            &summary.last_successful_rollout_version,
+        ) {
+            entries.push(HistoryEntry {
+                timestamp: ts.clone(),
+                action: "Rollout".into(),
+                version: ver.clone(),
+                ok: true,
+                source: None,
+                reason: None,
+            });
+        }
+    }
+
+    // NB: RFC 3339 timestamps in Utc sort lexicographically
+    let last_rollout = entries.last().map(|e| e.timestamp.clone()).unwrap_or_default();
+    let conds = &status.conditions;
+    if let Some(gen) = &conds.generated {
+        if gen.last_transition > last_rollout {
+            entries.push(condition_entry("Generate", gen, version, None));
+        }
+    }
+    if let Some(app) = &conds.applied {
+        if app.last_transition > last_rollout {
+            let reason = summary.last_apply_reason.clone();
+            entries.push(condition_entry("Apply", app, version, reason));
+        }
+    }
+    entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    entries
+}
+
+/// Find the version to roll back to from a shipcatmanifest status
+///
+/// This is the last successfully rolled out version that differs from `current`.
+pub fn previous_version(status: &ManifestStatus, current: &str) -> Option<String> {
+    let from_history = status
+        .history
+        .iter()
+        .rev()
+        .find(|r| r.status && r.version != current)
+        .map(|r| r.version.clone());
+    from_history.or_else(|| {
+        status
+            .summary
+            .as_ref()
+            .and_then(|s| s.last_successful_rollout_version.clone())
+            .filter(|v| v != current)
+    })
+}
+
+/// Entry point for `shipcat history`
+pub async fn show(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    let api = ShipKube::new(&mf).await?;
+    let crd = api.get_minimal().await?;
+    let status = crd.status.unwrap_or_default();
+
+    println!(
+        "{0:<26} {1:<10} {2:<20} {3:<8} {4:<24} REASON",
+        "TIME", "ACTION", "VERSION", "STATUS", "SOURCE"
+    );
+    for e in timeline(&status, &crd.spec.version) {
+        let state = if e.ok { "Success" } else { "Failure" };
+        println!(
+            "{0:<26} {1:<10} {2:<20} {3:<8} {4:<24} {5}",
+            e.timestamp,
+            e.action,
+            e.version,
+            state,
+            e.source.unwrap_or_default(),
+            e.reason.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{previous_version, timeline};
+    use shipcat_definitions::status::ManifestStatus;
+
+    fn status() -> ManifestStatus {
+        serde_yaml::from_str(
+            r#"
+conditions:
+  generated:
+    status: true
+    lastTransitionTime: "2020-04-03T10:00:00Z"
+  applied:
+    status: true
+    lastTransitionTime: "2020-04-03T10:00:05Z"
+  rolledout:
+    status: false
+    reason: Timeout
+    message: timed out waiting 300s for rollout
+    lastTransitionTime: "2020-04-03T10:05:05Z"
+summary:
+  lastApplyReason: VersionChange
+  lastSuccessfulRolloutVersion: 1.1.0
+history:
+- version: 1.0.0
+  status: true
+  timestamp: "2020-04-01T10:00:00Z"
+  applyReason: NewService
+- version: 1.1.0
+  status: true
+  timestamp: "2020-04-02T10:00:00Z"
+  applyReason: VersionChange
+  source:
+    name: clux
+- version: 1.2.0
+  status: false
+  timestamp: "2020-04-03T10:05:05Z"
+  message: timed out waiting 300s for rollout
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn timeline_from_history() {
+        let entries = timeline(&status(), "1.2.0");
+        let versions: Vec<_> = entries.iter().map(|e| e.version.as_str()).collect();
+        // conditions older than the last rollout are not repeated
+        assert_eq!(versions, vec!["1.0.0", "1.1.0", "1.2.0"]);
+        assert_eq!(entries[1].source, Some("clux".into()));
+        assert_eq!(entries[1].reason, Some("VersionChange".into()));
+        assert!(!entries[2].ok);
+    }
+
+    #[test]
+    fn timeline_from_summary_and_conditions() {
+        let mut st = status();
+        st.history = vec![];
+        let mut summary = st.summary.clone().unwrap();
+        summary.last_successful_rollout = Some("2020-04-02T10:00:00Z".into());
+        st.summary = Some(summary);
+        let entries = timeline(&st, "1.2.0");
+        let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, vec!["Rollout", "Generate", "Apply"]);
+        assert_eq!(entries[0].version, "1.1.0");
+        assert_eq!(entries[2].version, "1.2.0");
+    }
+
+    #[test]
+    fn previous_version_skips_current() {
+        let st = status();
+        assert_eq!(previous_version(&st, "1.2.0"), Some("1.1.0".into()));
+        assert_eq!(previous_version(&st, "1.1.0"), Some("1.0.0".into()));
+        let empty = ManifestStatus::default();
+        assert_eq!(previous_version(&empty, "1.2.0"), None);
+    }
+}
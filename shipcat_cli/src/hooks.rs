@@ -0,0 +1,146 @@
+use futures_timer::Delay;
+use k8s_openapi::api::batch::v1::Job;
+use std::{fmt, time::Duration};
+
+use super::{chart, events, helm, kubeapi::ShipKube, ErrorKind, Manifest, Result};
+use shipcat_definitions::structs::Hook;
+
+/// When a hook runs relative to the upgrade of the main workload
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookPhase {
+    PreDeploy,
+    PostDeploy,
+}
+
+impl fmt::Display for HookPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookPhase::PreDeploy => write!(f, "preDeploy"),
+            HookPhase::PostDeploy => write!(f, "postDeploy"),
+        }
+    }
+}
+
+/// Kinds that must exist before a pre-deploy hook can start its pods
+const HOOK_DEPENDENCIES: &[&str] = &["ServiceAccount", "ConfigMap", "Secret"];
+
+/// Run the hooks of a phase in order
+///
+/// Each hook replaces any Job left behind by a previous upgrade, and is waited for.
+/// Stops at the first failing hook, after printing the logs of its pods.
+pub async fn run(mf: &Manifest, phase: HookPhase, tpl: &str, s: &ShipKube) -> Result<()> {
+    let hooks = match phase {
+        HookPhase::PreDeploy => &mf.hooks.preDeploy,
+        HookPhase::PostDeploy => &mf.hooks.postDeploy,
+    };
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    if phase == HookPhase::PreDeploy {
+        // hooks use the service account and secrets of the (possibly new) service
+        let deps = helm::objects(tpl)?
+            .into_iter()
+            .filter(|o| HOOK_DEPENDENCIES.contains(&o["kind"].as_str().unwrap_or_default()))
+            .collect::<Vec<_>>();
+        s.apply_objects(&deps, false).await?;
+    }
+    for hook in hooks {
+        info!("Running {} hook {} for {}", phase, hook.container.name, mf.name);
+        if let Err(e) = run_hook(mf, hook, phase, s).await {
+            let name = format!("{}-{}", mf.name, hook.container.name);
+            let _ = debug_job(s, &name, &hook.container.name).await;
+            return Err(e.chain_err(|| ErrorKind::HookFailure(mf.name.clone(), hook.container.name.clone())));
+        }
+    }
+    Ok(())
+}
+
+async fn run_hook(mf: &Manifest, hook: &Hook, phase: HookPhase, s: &ShipKube) -> Result<()> {
+    let job = chart::hook_job(mf, hook, phase)?;
+    let name = job
+        .metadata
+        .as_ref()
+        .and_then(|md| md.name.clone())
+        .unwrap_or_default();
+    // jobs are immutable, so the one from the last upgrade must go first
+    s.delete_job(&name).await?;
+    let mut waited = 0;
+    while s.get_job(&name).await?.is_some() {
+        if waited >= 60 {
+            bail!("timed out waiting for the previous {} job to be deleted", name);
+        }
+        Delay::new(Duration::from_secs(2)).await;
+        waited += 2;
+    }
+    s.apply_object(&serde_json::to_value(job)?, false).await?;
+
+    // activeDeadlineSeconds fails the job server side; allow some scheduling slack
+    let timeout = hook.timeout_secs() + 60;
+    let mut waited = 0;
+    loop {
+        if let Some(j) = s.get_job(&name).await? {
+            if let Some(res) = job_result(&j) {
+                return res;
+            }
+        }
+        if waited >= timeout {
+            bail!("timed out waiting {}s for {}", timeout, name);
+        }
+        Delay::new(Duration::from_secs(5)).await;
+        waited += 5;
+    }
+}
+
+// outcome of a job if it has finished
+fn job_result(job: &Job) -> Option<Result<()>> {
+    let status = job.status.as_ref()?;
+    if status.succeeded.unwrap_or(0) > 0 {
+        return Some(Ok(()));
+    }
+    for c in status.conditions.iter().flatten() {
+        if c.type_ == "Failed" && c.status == "True" {
+            let why = c.message.clone().or_else(|| c.reason.clone()).unwrap_or_default();
+            return Some(Err(format!("job failed: {}", why).into()));
+        }
+    }
+    None
+}
+
+// print the logs of the pods of a failed hook
+async fn debug_job(s: &ShipKube, name: &str, container: &str) -> Result<()> {
+    for pod in s.get_job_pods(name).await? {
+        let podname = pod.metadata.and_then(|md| md.name).unwrap_or_default();
+        match s.get_pod_logs(&podname, container).await {
+            Ok(logs) => {
+                warn!("Last 30 log lines of {}:", podname);
+                events::print_human(&logs)
+            }
+            Err(e) => warn!("Failed to get logs from {}: {}", podname, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::job_result;
+    use k8s_openapi::api::batch::v1::Job;
+    use serde_json::json;
+
+    #[test]
+    fn hook_job_results() {
+        let job = |status| serde_json::from_value::<Job>(json!({ "status": status })).unwrap();
+        assert!(job_result(&job(json!({ "active": 1 }))).is_none());
+        assert!(job_result(&job(json!({ "succeeded": 1 }))).unwrap().is_ok());
+        let failed = job(json!({
+            "failed": 1,
+            "conditions": [{
+                "type": "Failed",
+                "status": "True",
+                "reason": "BackoffLimitExceeded",
+            }]
+        }));
+        let err = job_result(&failed).unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "job failed: BackoffLimitExceeded");
+    }
+}
@@ -1,21 +1,28 @@
-use crate::{ErrorKind, Manifest, Result};
+use crate::{diff, Error, ErrorKind, Manifest, Result, ResultExt};
 use k8s_openapi::api::{
     apps::v1::{Deployment, ReplicaSet, StatefulSet},
-    core::v1::Pod,
+    batch::{v1::Job, v1beta1::CronJob},
+    core::v1::{Event, Pod},
 };
 use kube::{
-    api::{Api, DeleteParams, ListParams, LogParams, Object, ObjectList, PatchParams, Resource},
+    api::{
+        Api, DeleteParams, ListParams, LogParams, Object, ObjectList, PatchParams, PatchStrategy, PostParams,
+        PropagationPolicy, Resource,
+    },
     client::APIClient,
+    runtime::Informer,
 };
+use serde_json::Value;
 use shipcat_definitions::{
     manifest::ShipcatManifest,
     status::{Applier, ManifestStatus},
 };
+use std::collections::BTreeSet;
 
 /// Client creator
 ///
 /// TODO: embed inside shipcat::apply when needed for other things
-async fn make_client() -> Result<APIClient> {
+pub(crate) async fn make_client() -> Result<APIClient> {
     let config = if let Ok(cfg) = kube::config::incluster_config() {
         cfg
     } else {
@@ -30,7 +37,13 @@ pub struct MinimalManifest {
     pub name: String,
     pub version: String,
 }
-type MinimalMfCrd = Object<MinimalManifest, ManifestStatus>;
+pub type MinimalMfCrd = Object<MinimalManifest, ManifestStatus>;
+
+/// Label distinguishing canary pods from the ones of the main Deployment
+pub const CANARY_LABEL: &str = "shipcat.babylontech.co.uk/canary";
+
+/// Field manager owning the fields shipcat applies through server-side apply
+pub const FIELD_MANAGER: &str = "shipcat";
 
 /// Interface for dealing with kubernetes shipcatmanifests
 pub struct ShipKube {
@@ -38,7 +51,7 @@ pub struct ShipKube {
     client: APIClient,
     pub(crate) applier: Applier,
     api: Api<ShipcatManifest>,
-    name: String,
+    pub(crate) name: String,
     namespace: String,
 }
 
@@ -64,18 +77,78 @@ impl ShipKube {
         Self::new_within(&mf.name, &mf.namespace).await
     }
 
+    /// View for another workload of the same service (e.g. a worker)
+    ///
+    /// Helpers that select on the `app` label will use the workload name instead.
+    pub fn workload(&self, name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            namespace: self.namespace.clone(),
+            applier: self.applier.clone(),
+            api: self.api.clone(),
+            client: self.client.clone(),
+            mfs: self.mfs.clone(),
+        }
+    }
+
     /// Apply a Manifest (e.g. it's CRD wrapper)
+    ///
+    /// Returns whether the applied crd changed.
     pub async fn apply(&self, mf: Manifest) -> Result<bool> {
         assert!(mf.version.is_some()); // ensure crd is in right state w/o secrets
         assert!(mf.is_base());
         // Wrap in the Crd Struct:
         let svc = mf.name.clone();
-        let ns = mf.namespace.clone();
-        let mfcrd = ShipcatManifest::new(&svc, mf);
-        // TODO: use server side apply in 1.15
-        // for now, shell out to kubectl
-        use crate::kubectl;
-        kubectl::apply_resource(&svc, mfcrd, &ns).await
+        let obj = serde_json::to_value(ShipcatManifest::new(&svc, mf))?;
+        let live = self.get_object(&obj).await?;
+        let applied = self.apply_object(&obj, false).await?;
+        // no-op applies leave the resourceVersion alone
+        let changed = match live {
+            Some(l) => l["metadata"]["resourceVersion"] != applied["metadata"]["resourceVersion"],
+            None => true,
+        };
+        let result = if changed { "configured" } else { "unchanged" };
+        info!("shipcatmanifest {} {}", svc, result);
+        Ok(changed)
+    }
+
+    /// Create the CRD of a Manifest that is not installed yet
+    ///
+    /// Unlike `apply`, this fails if the crd already exists.
+    pub async fn create(&self, mf: Manifest) -> Result<()> {
+        assert!(mf.version.is_some()); // ensure crd is in right state w/o secrets
+        assert!(mf.is_base());
+        let svc = mf.name.clone();
+        let obj = ShipcatManifest::new(&svc, mf);
+        let mut req = self
+            .mfs
+            .create(&PostParams::default(), serde_json::to_vec(&obj)?)
+            .map_err(ErrorKind::KubeError)?;
+        // same field manager as `apply` so later applies can remove any field
+        let uri = format!("{}fieldManager={}", req.uri(), FIELD_MANAGER);
+        *req.uri_mut() = uri
+            .parse()
+            .map_err(|e| format!("invalid create url {}: {}", uri, e))?;
+        match self.client.request::<MinimalMfCrd>(req).await {
+            Ok(_) => {
+                info!("shipcatmanifest {} created", svc);
+                Ok(())
+            }
+            Err(kube::Error::Api(ae)) if ae.code == 409 => bail!("{} was installed by someone else", svc),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+
+    /// Diff a Manifest (e.g. it's CRD wrapper) against the applied one
+    ///
+    /// Dry-run variant of `apply` that does not mutate the cluster.
+    pub async fn diff(&self, mf: Manifest) -> Result<Option<String>> {
+        assert!(mf.version.is_some()); // ensure crd is in right state w/o secrets
+        assert!(mf.is_base());
+        let svc = mf.name.clone();
+        let obj = serde_json::to_value(ShipcatManifest::new(&svc, mf))?;
+        let out = self.diff_objects(&[obj]).await?;
+        Ok(if out.trim().is_empty() { None } else { Some(out) })
     }
 
     /// Full CRD fetcher
@@ -125,6 +198,20 @@ impl ShipKube {
         Ok(())
     }
 
+    // helper to send a merge patch to the crd itself (not its status)
+    pub async fn patch_metadata(&self, data: &serde_json::Value) -> Result<()> {
+        let pp = PatchParams::default();
+        let req = self
+            .mfs
+            .patch(&self.name, &pp, serde_json::to_vec(data)?)
+            .map_err(ErrorKind::KubeError)?;
+        self.client
+            .request::<MinimalMfCrd>(req)
+            .await
+            .map_err(ErrorKind::KubeError)?;
+        Ok(())
+    }
+
     // helper to get pod data
     pub async fn get_pods(&self) -> Result<ObjectList<Pod>> {
         let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
@@ -147,12 +234,36 @@ impl ShipKube {
         Ok(pods)
     }
 
-    // helper to get pod logs
-    pub async fn get_pod_logs(&self, podname: &str) -> Result<String> {
+    // helper to get pods by statefulset revision
+    pub async fn get_pods_by_revision(&self, revision: &str) -> Result<ObjectList<Pod>> {
+        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let lp = ListParams {
+            label_selector: Some(format!("app={},controller-revision-hash={}", self.name, revision)),
+            ..Default::default()
+        };
+        let pods = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        Ok(pods)
+    }
+
+    // helper to get the logs of a container in a pod
+    pub async fn get_pod_logs(&self, podname: &str, container: &str) -> Result<String> {
+        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let lp = LogParams {
+            tail_lines: Some(30),
+            container: Some(container.to_string()),
+            ..Default::default()
+        };
+        let logs = api.logs(podname, &lp).await.map_err(ErrorKind::KubeError)?;
+        Ok(logs)
+    }
+
+    // helper to get the logs of the last terminated instance of a container (e.g. after a crash)
+    pub async fn get_previous_pod_logs(&self, podname: &str, container: &str) -> Result<String> {
         let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
         let lp = LogParams {
             tail_lines: Some(30),
-            container: Some(self.name.to_string()),
+            container: Some(container.to_string()),
+            previous: true,
             ..Default::default()
         };
         let logs = api.logs(podname, &lp).await.map_err(ErrorKind::KubeError)?;
@@ -241,10 +352,452 @@ impl ShipKube {
         Ok(deps)
     }
 
+    // helper to get the canary deployment created alongside the main one
+    pub async fn get_canary_deploy(&self) -> Result<Deployment> {
+        let api: Api<Deployment> = Api::namespaced(self.client.clone(), &self.namespace);
+        let name = format!("{}-canary", self.name);
+        let dep = api.get(&name).await.map_err(ErrorKind::KubeError)?;
+        Ok(dep)
+    }
+
+    // helper to get canary pods
+    pub async fn get_canary_pods(&self) -> Result<ObjectList<Pod>> {
+        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let lp = ListParams {
+            label_selector: Some(format!("app={},{}=true", self.name, CANARY_LABEL)),
+            ..Default::default()
+        };
+        let pods = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        Ok(pods)
+    }
+
+    // helper to remove the canary deployment (if it exists)
+    pub async fn delete_canary(&self) -> Result<()> {
+        let api: Api<Deployment> = Api::namespaced(self.client.clone(), &self.namespace);
+        let name = format!("{}-canary", self.name);
+        match api.delete(&name, &DeleteParams::default()).await {
+            Ok(_) => Ok(()),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+
     // helper to get statefulset data
     pub async fn get_statefulset(&self) -> Result<StatefulSet> {
         let api: Api<StatefulSet> = Api::namespaced(self.client.clone(), &self.namespace);
         let ssets = api.get(&self.name).await.map_err(ErrorKind::KubeError)?;
         Ok(ssets)
     }
+
+    // helper to get all events in the namespace
+    //
+    // involved objects can only be selected by exact name, so filtering is left to callers.
+    pub async fn get_events(&self) -> Result<ObjectList<Event>> {
+        let api: Api<Event> = Api::namespaced(self.client.clone(), &self.namespace);
+        let events = api
+            .list(&ListParams::default())
+            .await
+            .map_err(ErrorKind::KubeError)?;
+        Ok(events)
+    }
+
+    // helper to watch the events in the namespace from a resourceVersion
+    pub fn events_informer(&self, version: String) -> Informer<Event> {
+        let res = Resource::namespaced::<Event>(&self.namespace);
+        Informer::new(self.client.clone(), ListParams::default(), res).init_from(version)
+    }
+
+    // helper to get a cronjob (if it exists)
+    pub async fn get_cronjob(&self, name: &str) -> Result<Option<CronJob>> {
+        let api: Api<CronJob> = Api::namespaced(self.client.clone(), &self.namespace);
+        match api.get(name).await {
+            Ok(cj) => Ok(Some(cj)),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(None),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+
+    // helper to get a job (if it exists)
+    pub async fn get_job(&self, name: &str) -> Result<Option<Job>> {
+        let api: Api<Job> = Api::namespaced(self.client.clone(), &self.namespace);
+        match api.get(name).await {
+            Ok(j) => Ok(Some(j)),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(None),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+
+    // helper to get the pods of a job
+    pub async fn get_job_pods(&self, name: &str) -> Result<ObjectList<Pod>> {
+        let api: Api<Pod> = Api::namespaced(self.client.clone(), &self.namespace);
+        let lp = ListParams {
+            label_selector: Some(format!("job-name={}", name)),
+            ..Default::default()
+        };
+        let pods = api.list(&lp).await.map_err(ErrorKind::KubeError)?;
+        Ok(pods)
+    }
+
+    // helper to remove a job and its pods (if it exists)
+    //
+    // jobs orphan their pods by default, so deletion is propagated explicitly.
+    pub async fn delete_job(&self, name: &str) -> Result<()> {
+        let api: Api<Job> = Api::namespaced(self.client.clone(), &self.namespace);
+        let dp = DeleteParams {
+            propagation_policy: Some(PropagationPolicy::Background),
+            ..Default::default()
+        };
+        match api.delete(name, &dp).await {
+            Ok(_) => Ok(()),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+}
+
+/// Kinds of objects created by shipcat charts that are pruned when no longer templated
+///
+/// Kinds whose crds are not installed in a cluster are skipped when pruning.
+const PRUNABLE_KINDS: &[(&str, &str)] = &[
+    ("v1", "ConfigMap"),
+    ("v1", "Secret"),
+    ("v1", "Service"),
+    ("v1", "ServiceAccount"),
+    ("apps/v1", "Deployment"),
+    ("apps/v1", "StatefulSet"),
+    ("batch/v1beta1", "CronJob"),
+    ("autoscaling/v2beta2", "HorizontalPodAutoscaler"),
+    ("rbac.authorization.k8s.io/v1", "Role"),
+    ("rbac.authorization.k8s.io/v1", "RoleBinding"),
+    ("monitoring.coreos.com/v1", "PrometheusRule"),
+];
+
+// dynamic resource for a kind in a namespace
+fn kind_resource(api_version: &str, kind: &str, ns: &str) -> Resource {
+    let (group, version) = match api_version.rfind('/') {
+        Some(i) => (&api_version[..i], &api_version[i + 1..]),
+        None => ("", api_version),
+    };
+    Resource {
+        api_version: api_version.into(),
+        group: group.into(),
+        kind: kind.into(),
+        version: version.into(),
+        namespace: Some(ns.into()),
+    }
+}
+
+/// Resource and name of an arbitrary object from a template
+///
+/// Objects without a namespace are assumed to live in `ns`.
+fn object_resource(obj: &Value, ns: &str) -> Result<(Resource, String)> {
+    let api_version = obj["apiVersion"].as_str().unwrap_or_default();
+    let kind = obj["kind"].as_str().unwrap_or_default();
+    let name = obj["metadata"]["name"].as_str().unwrap_or_default();
+    if api_version.is_empty() || kind.is_empty() || name.is_empty() {
+        bail!("Object is missing apiVersion, kind or metadata.name");
+    }
+    let namespace = obj["metadata"]["namespace"].as_str().unwrap_or(ns);
+    Ok((kind_resource(api_version, kind, namespace), name.into()))
+}
+
+/// Whether an object carrying the labels of a service is owned by its shipcatmanifest
+///
+/// Objects owned by anything else (e.g. Jobs owned by a CronJob) are left alone,
+/// as are objects without owners (which could have been created by hand).
+fn owned_by(obj: &Value, svc: &str) -> bool {
+    match obj["metadata"]["ownerReferences"].as_array() {
+        Some(refs) => refs
+            .iter()
+            .all(|r| r["kind"] == "ShipcatManifest" && r["name"] == svc),
+        None => false,
+    }
+}
+
+/// Requirements of a label selector in a comparable form
+///
+/// Missing and empty fields compare equal regardless of order, as the apiserver may drop or
+/// reorder them. Labels are treated as `In` expressions with a single value.
+fn selector_terms(sel: &Value) -> BTreeSet<(String, String, Vec<String>)> {
+    let mut res = BTreeSet::new();
+    if let Some(labels) = sel["matchLabels"].as_object() {
+        for (k, v) in labels {
+            let value = v.as_str().unwrap_or_default().to_string();
+            res.insert((k.clone(), "In".to_string(), vec![value]));
+        }
+    }
+    for e in sel["matchExpressions"].as_array().into_iter().flatten() {
+        let mut values = e["values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect::<Vec<_>>();
+        values.sort();
+        values.dedup();
+        let key = e["key"].as_str().unwrap_or_default().to_string();
+        let op = e["operator"].as_str().unwrap_or_default().to_string();
+        res.insert((key, op, values));
+    }
+    res
+}
+
+/// Human identifier for an object (Kind/name)
+pub fn object_ref(obj: &Value) -> String {
+    format!(
+        "{}/{}",
+        obj["kind"].as_str().unwrap_or("Unknown"),
+        obj["metadata"]["name"].as_str().unwrap_or("unnamed")
+    )
+}
+
+/// Server-side apply of arbitrary objects (e.g. from `helm template`)
+impl ShipKube {
+    /// Fetch the live version of an object (if it exists)
+    pub async fn get_object(&self, obj: &Value) -> Result<Option<Value>> {
+        let (res, name) = object_resource(obj, &self.namespace)?;
+        let req = res.get(&name).map_err(ErrorKind::KubeError)?;
+        match self.client.request::<Value>(req).await {
+            Ok(o) => Ok(Some(o)),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(None),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+
+    /// Server-side apply an object as the shipcat field manager
+    ///
+    /// Conflicts with other managers are forced; shipcat is the source of truth.
+    /// Returns the object as persisted (or as it would have been in a dry run).
+    pub async fn apply_object(&self, obj: &Value, dry_run: bool) -> Result<Value> {
+        let (res, name) = object_resource(obj, &self.namespace)?;
+        let pp = PatchParams {
+            dry_run,
+            patch_strategy: PatchStrategy::Apply,
+            force: true,
+            field_manager: Some(FIELD_MANAGER.into()),
+        };
+        // json is valid yaml, so it is an acceptable apply-patch body
+        let req = res
+            .patch(&name, &pp, serde_json::to_vec(obj)?)
+            .map_err(ErrorKind::KubeError)?;
+        let o = self
+            .client
+            .request::<Value>(req)
+            .await
+            .map_err(|e| Error::from(ErrorKind::KubeError(e)))
+            .chain_err(|| ErrorKind::ObjectApplyFailure(self.name.clone(), object_ref(obj)))?;
+        Ok(o)
+    }
+
+    /// Server-side apply a list of objects in order
+    ///
+    /// Selectors are immutable, so Deployments whose selector changed can only be recreated.
+    /// That is only done with `recreate`, and fails otherwise.
+    /// Stops at the first object that fails to apply.
+    pub async fn apply_objects(&self, objs: &[Value], recreate: bool) -> Result<()> {
+        for o in objs {
+            if o["kind"] == "Deployment" {
+                self.replace_selector(o, recreate).await?;
+            }
+            self.apply_object(o, false).await?;
+            debug!("{} applied", object_ref(o));
+        }
+        Ok(())
+    }
+
+    /// Diff a list of objects against their live versions
+    ///
+    /// Uses dry-run server-side apply to get the merged objects the apiserver would persist.
+    /// The output is a unified diff in the same format as `kubectl diff`.
+    pub async fn diff_objects(&self, objs: &[Value]) -> Result<String> {
+        let mut pairs = vec![];
+        for o in objs {
+            let (res, name) = object_resource(o, &self.namespace)?;
+            let live = self.get_object(o).await?;
+            let merged = self.apply_object(o, true).await?;
+            let file = format!(
+                "{}.{}.{}.{}",
+                res.api_version.replace('/', "."),
+                res.kind,
+                res.namespace.unwrap_or_default(),
+                name
+            );
+            pairs.push((file, live, merged));
+        }
+        diff::live_vs_merged(pairs)
+    }
+
+    /// Structural diff of a list of objects against their live versions
+    ///
+    /// Same dry-run server-side apply as `diff_objects`, but compared field by field.
+    pub async fn diff_changes(&self, objs: &[Value]) -> Result<diff::Changes> {
+        let mut pairs = vec![];
+        for o in objs {
+            let live = self.get_object(o).await?;
+            let merged = self.apply_object(o, true).await?;
+            pairs.push((live, merged));
+        }
+        Ok(diff::Changes::live_vs_merged(pairs))
+    }
+
+    /// Objects of this service that are absent from a list of templated objects
+    ///
+    /// Finds objects through the labels enforced by `helm::template_check`,
+    /// and only considers those owned by this service's shipcatmanifest.
+    /// The canary Deployment is never considered orphaned; rollouts clean it up.
+    pub async fn orphaned_objects(&self, objs: &[Value]) -> Result<Vec<Value>> {
+        let keep = objs.iter().map(object_ref).collect::<Vec<_>>();
+        let canary = format!("Deployment/{}-canary", self.name);
+        let lp = ListParams {
+            label_selector: Some(format!(
+                "app.kubernetes.io/name={},app.kubernetes.io/managed-by=shipcat",
+                self.name
+            )),
+            ..Default::default()
+        };
+        let mut orphans = vec![];
+        for &(api_version, kind) in PRUNABLE_KINDS {
+            let res = kind_resource(api_version, kind, &self.namespace);
+            let req = res.list(&lp).map_err(ErrorKind::KubeError)?;
+            let list = match self.client.request::<Value>(req).await {
+                Ok(l) => l,
+                Err(kube::Error::Api(ae)) if ae.code == 404 => {
+                    debug!("Not pruning {}: kind not installed", kind);
+                    continue;
+                }
+                Err(e) => return Err(ErrorKind::KubeError(e).into()),
+            };
+            for mut o in list["items"].as_array().cloned().unwrap_or_default() {
+                // list items do not carry their type
+                o["apiVersion"] = api_version.into();
+                o["kind"] = kind.into();
+                let oref = object_ref(&o);
+                if !keep.contains(&oref) && oref != canary && owned_by(&o, &self.name) {
+                    orphans.push(o);
+                }
+            }
+        }
+        Ok(orphans)
+    }
+
+    /// Delete a live Deployment whose selector differs from the templated one
+    ///
+    /// Its ReplicaSets and pods are orphaned rather than deleted,
+    /// and get adopted by the recreated Deployment when they match its new selector.
+    /// Without `recreate`, a changed selector is an error.
+    async fn replace_selector(&self, obj: &Value, recreate: bool) -> Result<()> {
+        use futures_timer::Delay;
+        let live = match self.get_object(obj).await? {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let (old, new) = (&live["spec"]["selector"], &obj["spec"]["selector"]);
+        if selector_terms(old) == selector_terms(new) {
+            return Ok(());
+        }
+        let oref = object_ref(obj);
+        if !recreate {
+            bail!(
+                "{} selector changed from {} to {}, it must be recreated (apply with --recreate-deployments)",
+                oref,
+                old,
+                new
+            );
+        }
+        warn!("Recreating {} with a new selector (its pods are kept)", oref);
+        let (res, name) = object_resource(obj, &self.namespace)?;
+        let dp = DeleteParams {
+            propagation_policy: Some(PropagationPolicy::Orphan),
+            ..Default::default()
+        };
+        let req = res.delete(&name, &dp).map_err(ErrorKind::KubeError)?;
+        match self.client.request::<Value>(req).await {
+            Ok(_) => {}
+            Err(kube::Error::Api(ae)) if ae.code == 404 => return Ok(()),
+            Err(e) => return Err(ErrorKind::KubeError(e).into()),
+        }
+        // applying while the orphan finalizer runs would patch the terminating object
+        for _ in 0..30 {
+            if self.get_object(obj).await?.is_none() {
+                return Ok(());
+            }
+            Delay::new(std::time::Duration::from_secs(1)).await;
+        }
+        bail!("timed out waiting for {} to be deleted", oref)
+    }
+
+    /// Delete an object (if it exists)
+    pub async fn delete_object(&self, obj: &Value) -> Result<()> {
+        let (res, name) = object_resource(obj, &self.namespace)?;
+        let req = res
+            .delete(&name, &DeleteParams::default())
+            .map_err(ErrorKind::KubeError)?;
+        match self.client.request::<Value>(req).await {
+            Ok(_) => Ok(()),
+            Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+            Err(e) => Err(ErrorKind::KubeError(e).into()),
+        }
+    }
+
+    /// Delete the objects of this service that are absent from a list of templated objects
+    ///
+    /// Returns the identifiers of the pruned objects.
+    pub async fn prune_objects(&self, objs: &[Value]) -> Result<Vec<String>> {
+        let mut pruned = vec![];
+        for o in self.orphaned_objects(objs).await? {
+            self.delete_object(&o).await?;
+            let oref = object_ref(&o);
+            info!("pruned {} from {}", oref, self.name);
+            pruned.push(oref);
+        }
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{object_resource, owned_by, selector_terms};
+    use serde_json::json;
+
+    #[test]
+    fn object_resource_groups() {
+        let dep = json!({ "apiVersion": "apps/v1", "kind": "Deployment", "metadata": { "name": "fake" } });
+        let (res, name) = object_resource(&dep, "apps").unwrap();
+        assert_eq!(name, "fake");
+        assert_eq!((res.group.as_str(), res.version.as_str()), ("apps", "v1"));
+        assert_eq!(res.namespace.as_deref(), Some("apps"));
+
+        let svc = json!({ "apiVersion": "v1", "kind": "Service", "metadata": { "name": "fake", "namespace": "dev" } });
+        let (res, _) = object_resource(&svc, "apps").unwrap();
+        assert_eq!((res.group.as_str(), res.version.as_str()), ("", "v1"));
+        assert_eq!(res.namespace.as_deref(), Some("dev"));
+
+        assert!(object_resource(&json!({ "kind": "Service" }), "apps").is_err());
+    }
+
+    #[test]
+    fn selector_changes() {
+        let labels = json!({ "matchLabels": { "app": "fake", "tier": "web" } });
+        let normalized = json!({ "matchExpressions": [], "matchLabels": { "tier": "web", "app": "fake" } });
+        assert_eq!(selector_terms(&labels), selector_terms(&normalized));
+        let canary = json!({
+            "matchLabels": { "app": "fake", "tier": "web" },
+            "matchExpressions": [{ "key": "canary", "operator": "DoesNotExist" }],
+        });
+        assert_ne!(selector_terms(&labels), selector_terms(&canary));
+        let fewer = json!({ "matchLabels": { "app": "fake" } });
+        assert_ne!(selector_terms(&labels), selector_terms(&fewer));
+    }
+
+    #[test]
+    fn owned_objects() {
+        let labelled = json!({ "metadata": { "name": "fake-worker" } });
+        assert!(!owned_by(&labelled, "fake"));
+        let owned =
+            json!({ "metadata": { "ownerReferences": [{ "kind": "ShipcatManifest", "name": "fake" }] } });
+        assert!(owned_by(&owned, "fake"));
+        assert!(!owned_by(&owned, "other"));
+        let job = json!({ "metadata": { "ownerReferences": [{ "kind": "CronJob", "name": "fake-cron" }] } });
+        assert!(!owned_by(&job, "fake"));
+    }
 }
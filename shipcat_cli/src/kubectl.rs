@@ -242,22 +242,12 @@ pub async fn port_forward(mf: &Manifest) -> Result<()> {
     Ok(())
 }
 
-/// Apply the kube object an applyable file
+/// Write a kube object to a temporary `{name}.crd.gen.yml` file
 ///
-/// CRDs itself, Manifest and Config typically.
-/// Returns whether or not the file was configured
-pub async fn apply_resource<K: k8s_openapi::Resource + Serialize>(
-    name: &str,
-    data: K,
-    ns: &str,
-) -> Result<bool> {
-    use std::{
-        fs::{self, File},
-        io::Write,
-        path::Path,
-    };
+/// Returns the filename written relative to the current directory.
+fn write_resource<K: k8s_openapi::Resource + Serialize>(name: &str, data: K) -> Result<String> {
+    use std::{fs::File, io::Write, path::Path};
 
-    // Write it to a temporary file:
     let datafile = format!("{}.crd.gen.yml", name);
     let pth = Path::new(".").join(&datafile);
     debug!("Writing {} CRD for {} to {}", K::KIND, name, pth.display());
@@ -271,6 +261,22 @@ pub async fn apply_resource<K: k8s_openapi::Resource + Serialize>(
         pth.display(),
         encoded
     );
+    Ok(datafile)
+}
+
+/// Apply the kube object an applyable file
+///
+/// CRDs itself, Manifest and Config typically.
+/// Returns whether or not the file was configured
+pub async fn apply_resource<K: k8s_openapi::Resource + Serialize>(
+    name: &str,
+    data: K,
+    ns: &str,
+) -> Result<bool> {
+    use std::fs;
+
+    // Write it to a temporary file:
+    let datafile = write_resource(name, data)?;
 
     // Apply it using kubectl apply
     debug!("Applying {} CRD for {}", K::KIND, name);
@@ -296,6 +302,7 @@ pub async fn apply_resource<K: k8s_openapi::Resource + Serialize>(
     let _ = fs::remove_file(&datafile); // try to remove temporary file
     Ok(changed)
 }
+
 /// Find all ManifestCrds in a given namespace
 ///
 /// Allows us to purge manifests that are not in Manifest::available()
@@ -314,27 +321,6 @@ async fn find_all_manifest_crds(ns: &str) -> Result<Vec<String>> {
     Ok(out.split(' ').map(String::from).collect())
 }
 
-use std::path::PathBuf;
-// Kubectl diff experiment (ignores secrets)
-pub async fn diff(pth: PathBuf, ns: &str) -> Result<(String, String, bool)> {
-    let args = vec![
-        "diff".into(),
-        format!("-n={}", ns),
-        format!("-f={}", pth.display()),
-    ];
-    // need the error code here so re-implent - and discard stderr
-    debug!("kubectl {}", args.join(" "));
-
-    let s = Command::new("kubectl").args(&args).output().await?;
-    let out: String = String::from_utf8_lossy(&s.stdout).into();
-    let err: String = String::from_utf8_lossy(&s.stderr).into();
-    trace!("out: {}, err: {}", out, err);
-    if err.contains("the dryRun alpha feature is disabled") {
-        bail!("kubectl diff is not supported in your cluster: {}", err.trim());
-    }
-    Ok((out, err, s.status.success()))
-}
-
 pub async fn find_redundant_manifests(ns: &str, svcs: &[String]) -> Result<Vec<String>> {
     use std::collections::HashSet;
     let requested: HashSet<_> = svcs.iter().cloned().collect();
@@ -345,16 +331,24 @@ pub async fn find_redundant_manifests(ns: &str, svcs: &[String]) -> Result<Vec<S
 
 // Get a version of a service from the current shipcatmanifest crd
 pub async fn get_running_version(svc: &str, ns: &str) -> Result<String> {
+    get_running_version_in(svc, ns, None).await
+}
+
+// Get a version of a service from the shipcatmanifest crd in an explicit kube context
+pub async fn get_running_version_in(svc: &str, ns: &str, context: Option<&str>) -> Result<String> {
     // kubectl get shipcatmanifest $* -o jsonpath='{.spec.version}'
-    let mfargs = vec![
+    let mut mfargs = vec![
         "get".into(),
         "shipcatmanifest".into(),
         svc.into(),
         format!("-n={}", ns),
-        "-ojsonpath='{.spec.version}'".into(),
+        "-ojsonpath={.spec.version}".into(),
     ];
+    if let Some(ctx) = context {
+        mfargs.push(format!("--context={}", ctx));
+    }
     match kout(mfargs).await {
-        Ok((kout, true)) => Ok(kout),
+        Ok((kout, true)) => Ok(kout.trim().to_string()),
         _ => bail!("Manifest for '{}' not found in {}", svc, ns),
     }
 }
@@ -65,10 +65,34 @@ error_chain! {
             description("kube call failed")
             display("kube {} of {} failed", &call, &svc)
         }
+        ObjectApplyFailure(svc: String, obj: String) {
+            description("server-side apply of an object failed")
+            display("Apply of {} for {} failed", &obj, &svc)
+        }
         UpgradeTimeout(svc: String, secs: u32) {
             description("upgrade timed out")
             display("{} upgrade timed out waiting {}s for deployment(s) to come online", &svc, secs)
         }
+        RolloutBlocked(svc: String, reason: String, message: String, logs: Option<String>) {
+            description("rollout cannot progress")
+            display("{} rollout cannot progress: {}", &svc, &message)
+        }
+        DeployFrozen(svc: String, freeze: String) {
+            description("deployments are frozen")
+            display("{} cannot be applied during the {} freeze (see --override-freeze)", &svc, &freeze)
+        }
+        DeployLocked(svc: String, holder: String, action: String) {
+            description("service is locked")
+            display("{} is locked by {} for {} (see `shipcat unlock {}` if stale)", &svc, &holder, &action, &svc)
+        }
+        CanaryFailure(svc: String, weight: u32) {
+            description("canary rollout failed")
+            display("{} canary failed at {}% weight", &svc, weight)
+        }
+        HookFailure(svc: String, hook: String) {
+            description("hook job failed")
+            display("{} hook {} failed", &svc, &hook)
+        }
         SlackSendFailure(hook: String) {
             description("slack message send failed")
             display("Failed to send the slack message to '{}' ", &hook)
@@ -118,12 +142,30 @@ pub mod track;
 /// Status subcommand
 pub mod status;
 
+/// History subcommand
+pub mod history;
+
 /// Apply logic
 pub mod apply;
 
+/// Deploy locks on shipcatmanifests
+pub mod lock;
+
+/// Version promotion between regions
+pub mod promote;
+
+/// Long-running reconciliation of shipcatmanifests
+pub mod operator;
+
 /// A small CLI helm template interface
 pub mod helm;
 
+/// Native rendering of the base chart
+pub mod chart;
+
+/// Pre- and post-deploy hook jobs
+pub mod hooks;
+
 /// A small CLI kong config generator interface
 pub mod kong;
 
@@ -143,6 +185,9 @@ pub use top::{OutputFormat, ResourceOrder};
 /// Diffing module for values
 pub mod diff;
 
+/// Diff summaries for pull requests and CI
+pub mod report;
+
 /// Git stuff
 pub mod git;
 
@@ -151,6 +196,9 @@ pub mod env;
 
 /// Webhook mux/demux
 pub mod webhooks;
+
+/// Structured apply events for CI and chatops
+pub mod events;
 pub use webhooks::UpgradeState;
 
 /// Simple printers
@@ -0,0 +1,367 @@
+use crate::{kubeapi::ShipKube, ErrorKind, Manifest, Region, Result};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use futures_timer::Delay;
+use serde_json::json;
+use shipcat_definitions::status::Applier;
+use std::future::Future;
+
+/// Annotation on a shipcatmanifest holding its deploy lock
+pub const LOCK_ANNOTATION: &str = "shipcat.babylontech.co.uk/lock";
+
+/// How long a lock is valid for before others can take it over
+///
+/// This only matters when the holder crashed without unlocking,
+/// as holders renew their lock every `LOCK_RENEW_SECS`.
+const LOCK_TTL_SECS: i64 = 10 * 60;
+
+/// How often a holder pushes the expiry of its lock forward
+const LOCK_RENEW_SECS: u64 = 2 * 60;
+
+/// How long to wait for a lock held by someone else before giving up
+const LOCK_WAIT_SECS: u64 = 5 * 60;
+
+/// How many times to retry a lock update that raced with another update
+const LOCK_MAX_CONFLICTS: u32 = 5;
+
+/// A lease on a shipcatmanifest preventing concurrent changes to a service
+///
+/// Stored as json in the `LOCK_ANNOTATION` of the shipcatmanifest.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeployLock {
+    /// Originator holding the lock (as inferred by `Applier`)
+    pub holder: String,
+    /// Link to logs or origin of the holder (if possible)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Unique id of the process holding the lock
+    pub id: String,
+    /// Action the lock was taken for
+    pub action: String,
+    /// When the lock expires (RFC 3339)
+    pub expires: String,
+}
+
+fn expiry() -> String {
+    let t = Utc::now() + Duration::seconds(LOCK_TTL_SECS);
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl DeployLock {
+    fn new(applier: &Applier, action: &str) -> Self {
+        DeployLock {
+            holder: applier.name.clone(),
+            url: applier.url.clone(),
+            id: uuid::Uuid::new_v4().to_string(),
+            action: action.into(),
+            expires: expiry(),
+        }
+    }
+
+    /// The same lock with its expiry pushed forward
+    fn renewed(&self) -> Self {
+        DeployLock {
+            expires: expiry(),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the lock can be taken over by someone else
+    ///
+    /// Unparseable expiry timestamps are considered expired.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        match DateTime::parse_from_rfc3339(&self.expires) {
+            Ok(t) => t.with_timezone(&Utc) < now,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Outcome of a single attempt at taking a lock
+enum LockAttempt {
+    Acquired,
+    Held(DeployLock),
+    Conflict,
+    /// No shipcatmanifest to hold the lock
+    Missing,
+}
+
+fn is_api_error(e: &crate::Error, code: u16) -> bool {
+    match e.kind() {
+        ErrorKind::KubeError(kube::Error::Api(ae)) => ae.code == code,
+        _ => false,
+    }
+}
+
+/// Deploy lock handling for shipcatmanifests
+impl ShipKube {
+    async fn current_lock(&self) -> Result<Option<(DeployLock, Option<String>)>> {
+        let crd = self.get_minimal().await?;
+        let rv = crd.metadata.resource_version.clone();
+        let lock = crd
+            .metadata
+            .annotations
+            .and_then(|a| a.get(LOCK_ANNOTATION).cloned())
+            .and_then(|l| serde_json::from_str(&l).ok());
+        Ok(lock.map(|l| (l, rv)))
+    }
+
+    async fn try_lock(&self, lock: &DeployLock) -> Result<LockAttempt> {
+        let crd = match self.get_minimal().await {
+            Ok(o) => o,
+            Err(e) if is_api_error(&e, 404) => return Ok(LockAttempt::Missing),
+            Err(e) => return Err(e),
+        };
+        let existing = crd
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(LOCK_ANNOTATION))
+            .and_then(|l| serde_json::from_str::<DeployLock>(l).ok());
+        if let Some(held) = existing {
+            if held.id != lock.id && !held.is_expired(Utc::now()) {
+                return Ok(LockAttempt::Held(held));
+            }
+        }
+        // resourceVersion makes this fail with a conflict if someone raced us
+        let data = json!({
+            "metadata": {
+                "resourceVersion": crd.metadata.resource_version,
+                "annotations": { LOCK_ANNOTATION: serde_json::to_string(lock)? }
+            }
+        });
+        match self.patch_metadata(&data).await {
+            Ok(_) => Ok(LockAttempt::Acquired),
+            Err(e) if is_api_error(&e, 409) => Ok(LockAttempt::Conflict),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Take a lock, waiting up to `wait_secs` if it is held by someone else
+    ///
+    /// Returns whether there was a shipcatmanifest to lock.
+    async fn acquire(&self, lock: &DeployLock, wait_secs: u64) -> Result<bool> {
+        let mut waited = 0;
+        let mut conflicts = 0;
+        loop {
+            match self.try_lock(lock).await? {
+                LockAttempt::Acquired => {
+                    debug!("Locked {} for {}", self.name, lock.action);
+                    return Ok(true);
+                }
+                LockAttempt::Missing => return Ok(false),
+                LockAttempt::Conflict => {
+                    conflicts += 1;
+                    if conflicts > LOCK_MAX_CONFLICTS {
+                        bail!("Unable to lock {} (too many concurrent updates)", self.name);
+                    }
+                    // exponential backoff from 200ms
+                    Delay::new(std::time::Duration::from_millis(100 << conflicts)).await;
+                }
+                LockAttempt::Held(held) => {
+                    if waited >= wait_secs {
+                        return Err(
+                            ErrorKind::DeployLocked(self.name.clone(), held.holder, held.action).into(),
+                        );
+                    }
+                    if waited == 0 {
+                        info!(
+                            "Waiting up to {}s for {} lock on {} held by {}",
+                            wait_secs, held.action, self.name, held.holder
+                        );
+                    }
+                    Delay::new(std::time::Duration::from_secs(5)).await;
+                    waited += 5;
+                }
+            }
+        }
+    }
+
+    /// Take the deploy lock of a service
+    ///
+    /// Waits for a lock held by someone else, and fails naming the holder
+    /// if it is not released in time.
+    /// Fails if the service has no shipcatmanifest to hold the lock.
+    pub async fn lock(&self, action: &str) -> Result<DeployLock> {
+        let lock = DeployLock::new(&self.applier, action);
+        if !self.acquire(&lock, LOCK_WAIT_SECS).await? {
+            bail!("{} has no shipcatmanifest to lock", self.name);
+        }
+        Ok(lock)
+    }
+
+    /// Take the deploy lock of a service that might not be installed yet
+    ///
+    /// Like `lock`, but without a shipcatmanifest the lock is only taken by `install`.
+    pub async fn lock_new(&self, action: &str) -> Result<DeployLock> {
+        let lock = DeployLock::new(&self.applier, action);
+        if !self.acquire(&lock, LOCK_WAIT_SECS).await? {
+            debug!(
+                "No shipcatmanifest for {} to lock until it is installed",
+                self.name
+            );
+        }
+        Ok(lock)
+    }
+
+    /// Create the shipcatmanifest of a new service and take its lock from `lock_new`
+    ///
+    /// Fails if someone else installed the service or took the lock in the meantime.
+    pub async fn install(&self, mf: Manifest, lock: &DeployLock) -> Result<()> {
+        self.create(mf).await?;
+        if !self.acquire(lock, 0).await? {
+            bail!("{} was deleted while installing it", self.name);
+        }
+        Ok(())
+    }
+
+    /// Run an operation while holding a lock
+    ///
+    /// The lock is renewed in the background until the operation completes,
+    /// so that long rollouts do not lose it to an expiry.
+    pub async fn renewing<T>(&self, lock: &DeployLock, op: impl Future<Output = Result<T>>) -> Result<T> {
+        use futures::future::{select, Either};
+        match select(Box::pin(op), Box::pin(self.keep_alive(lock))).await {
+            Either::Left((res, _)) => res,
+            // renewals stopped (lock taken over), but the operation still has to finish
+            Either::Right((_, op)) => op.await,
+        }
+    }
+
+    async fn keep_alive(&self, lock: &DeployLock) {
+        loop {
+            Delay::new(std::time::Duration::from_secs(LOCK_RENEW_SECS)).await;
+            match self.renew(lock).await {
+                Ok(true) => debug!("Renewed {} lock on {}", lock.action, self.name),
+                Ok(false) => return,
+                Err(e) => warn!("Unable to renew lock on {}: {}", self.name, e),
+            }
+        }
+    }
+
+    /// Push the expiry of a lock forward
+    ///
+    /// Returns false if the lock has been taken over.
+    async fn renew(&self, lock: &DeployLock) -> Result<bool> {
+        let renewed = lock.renewed();
+        for _ in 0..LOCK_MAX_CONFLICTS {
+            let rv = match self.current_lock().await {
+                Ok(Some((held, rv))) if held.id == lock.id => rv,
+                Ok(Some((held, _))) => {
+                    warn!("Lock on {} was taken over by {}", self.name, held.holder);
+                    return Ok(false);
+                }
+                // not installed yet, or lock removed by `shipcat unlock`
+                Ok(None) => return Ok(true),
+                Err(e) if is_api_error(&e, 404) => return Ok(true),
+                Err(e) => return Err(e),
+            };
+            let data = json!({
+                "metadata": {
+                    "resourceVersion": rv,
+                    "annotations": { LOCK_ANNOTATION: serde_json::to_string(&renewed)? }
+                }
+            });
+            match self.patch_metadata(&data).await {
+                Ok(_) => return Ok(true),
+                Err(e) if is_api_error(&e, 409) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        bail!(
+            "Unable to renew lock on {} (too many concurrent updates)",
+            self.name
+        )
+    }
+
+    /// Release a deploy lock taken by `lock`
+    ///
+    /// Leaves the annotation alone if the lock has since been taken over.
+    pub async fn unlock(&self, lock: &DeployLock) -> Result<()> {
+        match self.current_lock().await {
+            Ok(Some((held, rv))) if held.id == lock.id => self.remove_lock(rv).await,
+            Ok(Some((held, _))) => {
+                warn!("Lock on {} was taken over by {}", self.name, held.holder);
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            // crd gone (e.g. after delete)
+            Err(e) if is_api_error(&e, 404) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Remove the deploy lock of a service regardless of its holder
+    ///
+    /// Returns the lock that was removed (if any).
+    pub async fn force_unlock(&self) -> Result<Option<DeployLock>> {
+        match self.current_lock().await? {
+            Some((held, _)) => {
+                self.remove_lock(None).await?;
+                Ok(Some(held))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn remove_lock(&self, rv: Option<String>) -> Result<()> {
+        let mut data = json!({
+            "metadata": {
+                "annotations": { LOCK_ANNOTATION: serde_json::Value::Null }
+            }
+        });
+        if let Some(v) = rv {
+            data["metadata"]["resourceVersion"] = v.into();
+        }
+        self.patch_metadata(&data).await
+    }
+}
+
+/// Entry point for `shipcat unlock`
+///
+/// Escape hatch for locks left behind by crashed or cancelled applies.
+pub async fn unlock(svc: &str, reg: &Region) -> Result<()> {
+    let s = ShipKube::new_within(svc, &reg.namespace).await?;
+    match s.force_unlock().await? {
+        Some(l) => {
+            let origin = l.url.map(|u| format!(" ({})", u)).unwrap_or_default();
+            info!(
+                "Removed {} lock on {} held by {}{}",
+                l.action, svc, l.holder, origin
+            );
+        }
+        None => info!("{} is not locked", svc),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeployLock;
+    use chrono::{Duration, Utc};
+    use shipcat_definitions::status::Applier;
+
+    #[test]
+    fn lock_expiry() {
+        let applier = Applier {
+            name: "deploy#42".into(),
+            url: None,
+        };
+        let lock = DeployLock::new(&applier, "apply");
+        assert_eq!(lock.holder, "deploy#42");
+        assert!(!lock.is_expired(Utc::now()));
+        assert!(lock.is_expired(Utc::now() + Duration::hours(1)));
+        let renewed = lock.renewed();
+        assert_eq!(renewed.id, lock.id);
+        assert!(renewed.expires >= lock.expires);
+
+        let encoded = serde_json::to_string(&lock).unwrap();
+        let decoded: DeployLock = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.id, lock.id);
+
+        let garbage = DeployLock {
+            expires: "soon".into(),
+            ..lock
+        };
+        assert!(garbage.is_expired(Utc::now()));
+    }
+}
@@ -2,8 +2,8 @@
 #[macro_use] extern crate log;
 
 use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
-use shipcat::{kubeapi::ShipKube, *};
-use std::{process, str::FromStr};
+use shipcat::{kubeapi::ShipKube, report::ReportFormat, *};
+use std::{process, str::FromStr, time::Duration};
 
 fn print_error_debug(e: &Error) {
     use std::env;
@@ -103,6 +103,12 @@ fn build_cli() -> App<'static, 'static> {
                 .short("s")
                 .long("secrets")
                 .help("Verifies secrets exist everywhere"))
+              .arg(Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .default_value("human")
+                .possible_values(&["human", "sarif"])
+                .help("Output format. Sarif is for code scanning annotations."))
               .about("Validate the shipcat manifest"))
 
         .subcommand(SubCommand::with_name("verify")
@@ -190,6 +196,12 @@ fn build_cli() -> App<'static, 'static> {
             .setting(AppSettings::SubcommandRequiredElseHelp)
             .about("Perform cluster level recovery / reconcilation commands")
             .subcommand(SubCommand::with_name("diff")
+                .arg(Arg::with_name("format")
+                    .takes_value(true)
+                    .default_value("human")
+                    .possible_values(&["human", "markdown", "json", "junit"])
+                    .long("format")
+                    .help("Report format. Secrets are always obfuscated."))
                 .about("Diff all services against the a region"))
             .subcommand(SubCommand::with_name("check")
                 .arg(Arg::with_name("skip-kinds")
@@ -215,6 +227,31 @@ fn build_cli() -> App<'static, 'static> {
                     .help("Number of worker threads used"))
                 .subcommand(SubCommand::with_name("reconcile")
                     .about("Reconcile vault policies with manifest state"))))
+        .subcommand(SubCommand::with_name("operator")
+            .arg(Arg::with_name("num-jobs")
+                .short("j")
+                .long("num-jobs")
+                .takes_value(true)
+                .help("Number of services reconciled in parallel"))
+            .arg(Arg::with_name("min-interval")
+                .long("min-interval")
+                .takes_value(true)
+                .help("Minimum number of seconds between reconciles of a service"))
+            .arg(Arg::with_name("resync")
+                .long("resync")
+                .takes_value(true)
+                .help("Number of seconds between reconciles of every service"))
+            .arg(Arg::with_name("port")
+                .long("port")
+                .takes_value(true)
+                .help("Port to serve the health endpoint on"))
+            .about("Continuously reconcile services on changes to shipcat custom resources"))
+        .subcommand(SubCommand::with_name("schema")
+            .arg(Arg::with_name("kind")
+                .required(true)
+                .possible_values(&["manifest", "config", "region"])
+                .help("What to generate a schema for"))
+            .about("Generate JSON Schema for manifests, shipcat.conf or regions"))
         // all the listers (hidden from cli output)
         .subcommand(SubCommand::with_name("list-regions")
             .setting(AppSettings::Hidden)
@@ -293,11 +330,94 @@ fn build_cli() -> App<'static, 'static> {
               .arg(Arg::with_name("force")
                     .long("force")
                     .help("Apply template even if no changes are detected"))
+              .arg(Arg::with_name("rollback-on-failure")
+                    .long("rollback-on-failure")
+                    .conflicts_with("no-wait")
+                    .help("Roll back to the last successful version if the rollout fails"))
+              .arg(Arg::with_name("no-prune")
+                    .long("no-prune")
+                    .help("Do not delete objects of the service that are no longer templated"))
+              .arg(Arg::with_name("recreate-deployments")
+                    .long("recreate-deployments")
+                    .help("Recreate Deployments whose selector changed (their pods are kept)"))
+              .arg(Arg::with_name("output")
+                    .takes_value(true)
+                    .default_value("human")
+                    .possible_values(&["human", "jsonl"])
+                    .long("output")
+                    .short("o")
+                    .help("Output format. Jsonl streams one apply event per line on stdout."))
+              .arg(Arg::with_name("override-freeze")
+                    .long("override-freeze")
+                    .takes_value(true)
+                    .value_name("reason")
+                    .help("Apply during a deployment freeze (the reason is audited)"))
+              .arg(Arg::with_name("plan")
+                    .long("plan")
+                    .conflicts_with("force")
+                    .help("Print what would be applied as json without applying anything"))
+              .arg(Arg::with_name("all-changed")
+                    .long("all-changed")
+                    .conflicts_with_all(&["service", "tag", "plan"])
+                    .help("Apply all services changed in git since the merge-base with origin/master"))
+              .arg(Arg::with_name("num-jobs")
+                    .short("j")
+                    .long("num-jobs")
+                    .takes_value(true)
+                    .help("Number of parallel applies per wave when applying multiple services"))
               .arg(Arg::with_name("service")
-                .required(true)
-                .help("Service to apply"))
+                .required_unless("all-changed")
+                .multiple(true)
+                .help("Service(s) to apply"))
             .about("Apply a service's configuration in kubernetes (through helm)"))
 
+        .subcommand(SubCommand::with_name("rollback")
+              .arg(Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .help("Version to roll back to (defaults to the previous successful version)"))
+              .arg(Arg::with_name("no-wait")
+                    .long("no-wait")
+                    .help("Do not wait for service timeout"))
+              .arg(Arg::with_name("no-prune")
+                    .long("no-prune")
+                    .help("Do not delete objects of the service that are no longer templated"))
+              .arg(Arg::with_name("override-freeze")
+                    .long("override-freeze")
+                    .takes_value(true)
+                    .value_name("reason")
+                    .help("Roll back during a deployment freeze (the reason is audited)"))
+              .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service to roll back"))
+            .about("Apply a previous version of a service in kubernetes"))
+
+        .subcommand(SubCommand::with_name("promote")
+              .arg(Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .required(true)
+                .help("Region to take the running version from"))
+              .arg(Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .required(true)
+                .help("Region to pin the version in"))
+              .arg(Arg::with_name("all")
+                .long("all")
+                .conflicts_with("service")
+                .help("Promote every service whose versions differ"))
+              .arg(Arg::with_name("service")
+                .required_unless("all")
+                .help("Service to promote"))
+            .about("Pin the version running in one region in the manifests of another"))
+
+        .subcommand(SubCommand::with_name("history")
+              .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service to show the deploy history for"))
+            .about("Show the deploy timeline of a service from its shipcatmanifest status"))
+
         .subcommand(SubCommand::with_name("restart")
               .arg(Arg::with_name("no-wait")
                     .long("no-wait")
@@ -313,6 +433,12 @@ fn build_cli() -> App<'static, 'static> {
                 .help("Service to delete"))
             .about("Delete a service's shipcatmanifest from kubernetes"))
 
+        .subcommand(SubCommand::with_name("unlock")
+              .arg(Arg::with_name("service")
+                .required(true)
+                .help("Service to unlock"))
+            .about("Remove a stale deploy lock from a service's shipcatmanifest"))
+
         .subcommand(SubCommand::with_name("env")
               .arg(Arg::with_name("service")
                 .required(true)
@@ -327,14 +453,27 @@ fn build_cli() -> App<'static, 'static> {
               .arg(Arg::with_name("git")
                 .long("git")
                 .global(true)
-                .help("Comparing with master using a temporary git stash and git checkout"))
+                .help("Comparing with the merge-base of origin/master in a temporary git worktree"))
+              .arg(Arg::with_name("ref")
+                .long("ref")
+                .takes_value(true)
+                .conflicts_with("git")
+                .help("Comparing with a git reference in a temporary git worktree"))
               .arg(Arg::with_name("with-region")
                 .long("with-region")
                 .global(true)
                 .takes_value(true)
                 .conflicts_with("git")
+                .conflicts_with("ref")
                 .conflicts_with("crd")
                 .help("Comparing with the same service in a different region"))
+              .arg(Arg::with_name("all-regions")
+                .long("all-regions")
+                .conflicts_with("git")
+                .conflicts_with("ref")
+                .conflicts_with("with-region")
+                .conflicts_with("secrets")
+                .help("Comparing every region of the service against the selected region"))
               .arg(Arg::with_name("tag")
                 .long("tag")
                 .short("t")
@@ -342,7 +481,8 @@ fn build_cli() -> App<'static, 'static> {
                 .help("Image version to deploy"))
               .arg(Arg::with_name("service")
                 .required(true)
-                .help("Service to be diffed"))
+                .multiple(true)
+                .help("Service to be diffed (several with --git or --ref)"))
               .arg(Arg::with_name("crd")
                 .long("crd")
                 .help("Compare the shipcatmanifest crd output instead of the full kube yaml"))
@@ -352,7 +492,17 @@ fn build_cli() -> App<'static, 'static> {
               .arg(Arg::with_name("minify")
                 .short("m")
                 .long("minify")
-                .help("Minify the diff context"))
+                .help("Only show the changed fields of each object"))
+              .arg(Arg::with_name("json")
+                .long("json")
+                .conflicts_with("with-region")
+                .help("Alias for --format json"))
+              .arg(Arg::with_name("format")
+                .takes_value(true)
+                .default_value("human")
+                .possible_values(&["human", "markdown", "json", "junit"])
+                .long("format")
+                .help("Report format. Secrets are always obfuscated in reports."))
               .arg(Arg::with_name("obfuscate")
                 .long("obfuscate")
                 .requires("secrets")
@@ -362,6 +512,7 @@ fn build_cli() -> App<'static, 'static> {
                 .short("s")
                 .help("Fetch secrets before comparing")
                 .conflicts_with("git")
+                .conflicts_with("ref")
                 .conflicts_with("crd"))
             .about("Diff a service's yaml output against master or kubernetes"))
 
@@ -535,6 +686,10 @@ async fn resolve_config(args: &ArgMatches<'_>, ct: ConfigState) -> Result<(Confi
 
 fn void<T>(_x: T) {} // helper so that dispatch_commands can return Result<()>
 
+fn stdout_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
 /// Dispatch clap arguments to shipcat handlers
 ///
 /// A boring and somewhat error-prone "if-x-then-fnx dance". We are relying on types
@@ -554,6 +709,8 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
     } else if let Some(a) = args.subcommand_matches("login") {
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return shipcat::auth::login(&conf, &region, a.is_present("force")).await;
+    } else if let Some(a) = args.subcommand_matches("schema") {
+        return shipcat::show::schema(a.value_of("kind").unwrap());
     } else if let Some(a) = args.subcommand_matches("self-upgrade") {
         let tag = if let Some(v) = a.value_of("tag") {
             Some(semver::Version::parse(v).expect("tag must be valid semver"))
@@ -708,8 +865,9 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         } else {
             ConfigState::Base
         };
+        let fmt = a.value_of("format").unwrap().parse()?;
         let (conf, region) = resolve_config(a, ss).await?;
-        return shipcat::validate::manifest(services, &conf, &region, a.is_present("secrets")).await;
+        return shipcat::validate::manifest(services, &conf, &region, a.is_present("secrets"), fmt).await;
     } else if let Some(a) = args.subcommand_matches("verify") {
         return if a.value_of("region").is_some() {
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
@@ -803,20 +961,59 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         let (conf, region) = resolve_config(a, config_state).await?;
         return shipcat::env::print_bash(&svc, &conf, &region, mock).await;
     } else if let Some(a) = args.subcommand_matches("diff") {
-        let svc = a.value_of("service").map(String::from).unwrap();
-        let diff_exit = if a.is_present("crd") {
-            // NB: no secrets in CRD
+        let svcs = a.values_of("service").unwrap().map(String::from).collect::<Vec<_>>();
+        let against_git = a.is_present("git") || a.is_present("ref");
+        if svcs.len() > 1 && !against_git {
+            return Err("Several services can only be diffed with --git or --ref".into());
+        }
+        let svc = svcs[0].clone();
+        let fmt: ReportFormat = if a.is_present("json") {
+            ReportFormat::Json
+        } else {
+            a.value_of("format").unwrap().parse()?
+        };
+        let diff_exit = if against_git {
+            // special - diff against another checkout, leaving the working copy alone
+            // does not support mocking (but also has no secrets)
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
-            if a.is_present("git") {
-                shipcat::diff::values_vs_git(&svc, &conf, &region).await?
+            let reference = match a.value_of("ref") {
+                Some(r) => r.to_string(),
+                None => shipcat::git::merge_base()?,
+            };
+            let crd = a.is_present("crd");
+            let report = shipcat::diff::vs_ref(&svcs, &conf, &region, &reference, crd).await?;
+            if fmt == ReportFormat::Human {
+                for dr in &report.services {
+                    match (&dr.diff, &dr.error) {
+                        (Some(changes), _) => println!("{}", changes.render(stdout_is_tty())),
+                        (None, Some(e)) => error!("Failed to diff {}: {}", dr.name, e),
+                        (None, None) => {}
+                    }
+                }
             } else {
-                shipcat::diff::values_vs_kubectl(&svc, &conf, &region).await?
+                println!("{}", report.render(fmt)?);
             }
-        } else if a.is_present("git") {
-            // special - serial git diff
+            process::exit(report.exit_code());
+        } else if a.is_present("all-regions") {
+            // special - diff between every region and the selected one
             // does not support mocking (but also has no secrets)
             let (conf, region) = resolve_config(a, ConfigState::Base).await?;
-            shipcat::diff::template_vs_git(&svc, &conf, &region).await?
+            let crd = a.is_present("crd");
+            let matrix = shipcat::diff::values_vs_all_regions(&svc, &conf, &region, crd).await?;
+            if fmt == ReportFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&matrix)?);
+            } else if fmt != ReportFormat::Human {
+                return Err("--all-regions only supports the human and json formats".into());
+            } else if !matrix.fields.is_empty() {
+                println!("{}", matrix.render());
+            }
+            matrix.fields.is_empty()
+        } else if fmt != ReportFormat::Human && (a.is_present("crd") || a.is_present("with-region")) {
+            return Err("--crd and --with-region only support the human format".into());
+        } else if a.is_present("crd") {
+            // NB: no secrets in CRD
+            let (conf, region) = resolve_config(a, ConfigState::Base).await?;
+            shipcat::diff::values_vs_kubectl(&svc, &conf, &region).await?
         } else if a.is_present("with-region") {
             // special - diff between two regions
             // does not support mocking (but also has no secrets)
@@ -854,13 +1051,25 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 mf.uid = Some("FAKE-GUID".to_string());
                 mf.version = mf.version.or(Some("latest".to_string()));
             }
-            let diff = shipcat::diff::template_vs_kubectl(&mf).await?;
-            if let Some(mut out) = diff {
+            if fmt != ReportFormat::Human {
+                let mut changes = shipcat::diff::template_changes(&mf).await?;
+                changes.obfuscate(&mf.get_secrets());
+                let mut report = report::DiffReport::new(&region.name);
+                report.push(report::DiffResult::new(&svc, Ok(changes)));
+                println!("{}", report.render(fmt)?);
+                process::exit(report.exit_code());
+            } else if a.is_present("minify") {
+                let mut changes = shipcat::diff::template_changes(&mf).await?;
                 if a.is_present("obfuscate") {
-                    out = shipcat::diff::obfuscate_secrets(out, mf.get_secrets())
+                    changes.obfuscate(&mf.get_secrets())
                 };
-                if a.is_present("minify") {
-                    out = shipcat::diff::minify(&out)
+                if !changes.is_empty() {
+                    println!("{}", changes.render(stdout_is_tty()));
+                }
+                changes.is_empty()
+            } else if let Some(mut out) = shipcat::diff::template_vs_kubectl(&mf).await? {
+                if a.is_present("obfuscate") {
+                    out = shipcat::diff::obfuscate_secrets(out, mf.get_secrets())
                 };
                 println!("{}", out);
                 false
@@ -888,16 +1097,88 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
     // ------------------------------------------------------------------------------
     // everything below needs a kube context!
     else if let Some(a) = args.subcommand_matches("apply") {
-        let svc = a.value_of("service").map(String::from).unwrap();
         // this absolutely needs secrets..
         let (conf, region) = resolve_config(a, ConfigState::Filtered).await?;
         let wait = !a.is_present("no-wait");
         let force = a.is_present("force");
         let ver = a.value_of("tag").map(String::from); // needed for some subcommands
+        let rollback = a.is_present("rollback-on-failure") || region.rollbackOnFailure;
+        let prune = !a.is_present("no-prune");
+        let recreate = a.is_present("recreate-deployments");
+        let override_freeze = a.value_of("override-freeze").map(String::from);
+        shipcat::events::set_output(a.value_of("output").unwrap().parse()?);
         assert!(conf.has_secrets()); // sanity on cluster disruptive commands
-        return shipcat::apply::apply(svc, force, &region, &conf, wait, ver)
+        let mut svcs: Vec<String> = if a.is_present("all-changed") {
+            let available = shipcat_filebacked::available(&conf, &region).await?;
+            shipcat::git::changed_services()?
+                .into_iter()
+                .filter(|s| available.iter().any(|mf| &mf.base.name == s))
+                .collect()
+        } else {
+            a.values_of("service").unwrap().map(String::from).collect()
+        };
+        if svcs.len() != 1 || a.is_present("all-changed") {
+            if ver.is_some() || a.is_present("plan") {
+                return Err("--tag and --plan can only be used when applying a single service".into());
+            }
+            let jobs = a.value_of("num-jobs").unwrap_or("4").parse().unwrap();
+            return shipcat::apply::apply_many(
+                svcs,
+                force,
+                &region,
+                &conf,
+                wait,
+                rollback,
+                prune,
+                recreate,
+                override_freeze,
+                jobs,
+            )
+            .await
+            .map(void);
+        }
+        let svc = svcs.remove(0);
+        if a.is_present("plan") {
+            let plan = shipcat::apply::plan(svc, &region, &conf, prune, ver).await?;
+            println!("{}", serde_json::to_string_pretty(&plan)?);
+            return Ok(());
+        }
+        return shipcat::apply::apply(
+            svc,
+            force,
+            &region,
+            &conf,
+            wait,
+            rollback,
+            prune,
+            recreate,
+            ver,
+            override_freeze,
+        )
+        .await
+        .map(void);
+    } else if let Some(a) = args.subcommand_matches("rollback") {
+        let svc = a.value_of("service").map(String::from).unwrap();
+        let (conf, region) = resolve_config(a, ConfigState::Filtered).await?;
+        let wait = !a.is_present("no-wait");
+        let to = a.value_of("to").map(String::from);
+        let prune = !a.is_present("no-prune");
+        let override_freeze = a.value_of("override-freeze").map(String::from);
+        assert!(conf.has_secrets()); // sanity on cluster disruptive commands
+        return shipcat::apply::rollback(svc, to, &region, &conf, wait, prune, override_freeze)
             .await
             .map(void);
+    } else if let Some(a) = args.subcommand_matches("promote") {
+        let svc = a.value_of("service").map(String::from);
+        let (conf_from, from) = Config::new(ConfigState::Base, a.value_of("from").unwrap()).await?;
+        let (conf_to, to) = Config::new(ConfigState::Base, a.value_of("to").unwrap()).await?;
+        return shipcat::promote::promote(svc, &from, &conf_from, &to, &conf_to)
+            .await
+            .map(void);
+    } else if let Some(a) = args.subcommand_matches("history") {
+        let svc = a.value_of("service").map(String::from).unwrap();
+        let (conf, region) = resolve_config(a, ConfigState::Base).await?;
+        return shipcat::history::show(&svc, &conf, &region).await;
     } else if let Some(a) = args.subcommand_matches("restart") {
         let svc = a.value_of("service").map(String::from).unwrap();
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
@@ -908,6 +1189,10 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
         let svc = a.value_of("service").map(String::from).unwrap();
         let (conf, region) = resolve_config(a, ConfigState::Base).await?;
         return shipcat::apply::delete(&svc, &region, &conf).await.map(void);
+    } else if let Some(a) = args.subcommand_matches("unlock") {
+        let svc = a.value_of("service").map(String::from).unwrap();
+        let (_conf, region) = resolve_config(a, ConfigState::Base).await?;
+        return shipcat::lock::unlock(&svc, &region).await;
     }
     // 4. cluster level commands
     else if let Some(a) = args.subcommand_matches("cluster") {
@@ -925,9 +1210,10 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 return shipcat::cluster::mass_crd(&conf_sec, &conf_base, &region_base, jobs).await;
             }
         }
-        if let Some(_b) = a.subcommand_matches("diff") {
+        if let Some(b) = a.subcommand_matches("diff") {
             let (conf, region) = resolve_config(args, ConfigState::Filtered).await?;
-            return shipcat::cluster::mass_diff(&conf, &region).await;
+            let fmt = b.value_of("format").unwrap().parse()?;
+            return shipcat::cluster::mass_diff(&conf, &region, fmt).await;
         }
         if let Some(b) = a.subcommand_matches("check") {
             let (conf, region) = resolve_config(args, ConfigState::Base).await?;
@@ -948,6 +1234,18 @@ async fn dispatch_commands(args: &ArgMatches<'_>) -> Result<()> {
                 return shipcat::cluster::mass_vault(&conf, &region, jobs).await;
             }
         }
+    } else if let Some(a) = args.subcommand_matches("operator") {
+        let (conf, region) = resolve_config(args, ConfigState::Filtered).await?;
+        assert!(conf.has_secrets()); // sanity on cluster disruptive commands
+        let min_interval = a.value_of("min-interval").unwrap_or("60").parse().unwrap();
+        let resync = a.value_of("resync").unwrap_or("3600").parse().unwrap();
+        let params = shipcat::operator::OperatorParams {
+            workers: a.value_of("num-jobs").unwrap_or("8").parse().unwrap(),
+            min_interval: Duration::from_secs(min_interval),
+            resync: Duration::from_secs(resync),
+            port: a.value_of("port").unwrap_or("8080").parse().unwrap(),
+        };
+        return shipcat::operator::run(conf, region, params).await;
     }
     // ------------------------------------------------------------------------------
     // Dispatch small helpers that does not need secrets
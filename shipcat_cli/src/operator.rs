@@ -0,0 +1,520 @@
+use crate::{apply, kubeapi, Config, ConfigState, Region, Result};
+use futures::{
+    future::{self, Either},
+    lock::Mutex,
+    stream::{FuturesUnordered, StreamExt},
+};
+use futures_timer::Delay;
+use kube::{
+    api::{ListParams, Meta, Resource, WatchEvent},
+    client::APIClient,
+    runtime::Informer,
+};
+use shipcat_definitions::{ShipcatConfig, ShipcatManifest};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Upper bound for the backoff of services failing to reconcile
+const MAX_BACKOFF_SECS: u64 = 60 * 60;
+
+/// How often idle workers check the queue
+const TICK_SECS: u64 = 1;
+
+/// Upper bound for the backoff of failing watches and health listeners
+const MAX_RETRY_SECS: u64 = 60;
+
+/// Delay before retrying something that failed `failures` times in a row
+fn retry_delay(failures: u32) -> Duration {
+    Duration::from_secs(2u64.pow(failures.min(6)).min(MAX_RETRY_SECS))
+}
+
+/// Tuning parameters for `shipcat operator`
+#[derive(Clone, Debug)]
+pub struct OperatorParams {
+    /// Number of services reconciled in parallel
+    pub workers: usize,
+    /// Minimum time between two reconciles of the same service
+    pub min_interval: Duration,
+    /// Time between reconciles of every service (regardless of events)
+    pub resync: Duration,
+    /// Port the health endpoint listens on
+    pub port: u16,
+}
+
+/// Health of the operator as reported by the health endpoint
+#[derive(Serialize, Debug, Default)]
+pub struct Health {
+    /// Number of services waiting to be reconciled
+    pub queued: usize,
+    /// Services being reconciled
+    pub running: Vec<String>,
+    /// Services whose last reconcile failed
+    pub failing: Vec<String>,
+    /// Watches whose last poll failed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failing_watches: Vec<String>,
+}
+
+impl Health {
+    /// Whether the operator is still picking up changes
+    ///
+    /// Failing services do not make the operator unhealthy, as a restart would not fix them.
+    pub fn is_healthy(&self) -> bool {
+        self.failing_watches.is_empty()
+    }
+}
+
+/// Deduplicating and rate limited queue of services to reconcile
+///
+/// A service is never reconciled concurrently with itself; adding it while it runs
+/// makes it reconcile once more afterwards. Reconciles of the same service are spaced
+/// by at least `min_interval`, doubled for every consecutive failure.
+pub struct WorkQueue {
+    queued: VecDeque<String>,
+    running: BTreeSet<String>,
+    dirty: BTreeSet<String>,
+    started: BTreeMap<String, Instant>,
+    failures: BTreeMap<String, u32>,
+    min_interval: Duration,
+}
+
+impl WorkQueue {
+    pub fn new(min_interval: Duration) -> Self {
+        WorkQueue {
+            queued: VecDeque::new(),
+            running: BTreeSet::new(),
+            dirty: BTreeSet::new(),
+            started: BTreeMap::new(),
+            failures: BTreeMap::new(),
+            min_interval,
+        }
+    }
+
+    /// Queue a service for reconciliation (if it is not already queued)
+    pub fn add(&mut self, svc: &str) {
+        if self.running.contains(svc) {
+            self.dirty.insert(svc.into());
+        } else if !self.queued.iter().any(|s| s == svc) {
+            self.queued.push_back(svc.into());
+        }
+    }
+
+    fn backoff(&self, svc: &str) -> Duration {
+        let failures = self.failures.get(svc).cloned().unwrap_or(0).min(16);
+        let max = Duration::from_secs(MAX_BACKOFF_SECS);
+        (self.min_interval * 2u32.pow(failures))
+            .min(max)
+            .max(self.min_interval)
+    }
+
+    /// Take the first queued service that is due for reconciliation
+    pub fn next(&mut self, now: Instant) -> Option<String> {
+        let pos = self.queued.iter().position(|s| match self.started.get(s) {
+            Some(t) => now.duration_since(*t) >= self.backoff(s),
+            None => true,
+        })?;
+        let svc = self.queued.remove(pos)?;
+        self.running.insert(svc.clone());
+        self.started.insert(svc.clone(), now);
+        Some(svc)
+    }
+
+    /// Mark a reconcile as done
+    ///
+    /// Failed services are queued again, and will be retried after their backoff.
+    pub fn done(&mut self, svc: &str, success: bool) {
+        self.running.remove(svc);
+        if success {
+            self.failures.remove(svc);
+        } else {
+            *self.failures.entry(svc.into()).or_insert(0) += 1;
+        }
+        if self.dirty.remove(svc) || !success {
+            self.add(svc);
+        }
+    }
+
+    pub fn health(&self) -> Health {
+        Health {
+            queued: self.queued.len(),
+            running: self.running.iter().cloned().collect(),
+            failing: self.failures.keys().cloned().collect(),
+            failing_watches: vec![],
+        }
+    }
+}
+
+/// Queue a service whose shipcatmanifest has a new generation
+///
+/// Only spec changes bump the generation (status and lock changes do not).
+/// Services that are being reconciled are reconciled again once done.
+fn manifest_changed(
+    generations: &mut BTreeMap<String, i64>,
+    queue: &mut WorkQueue,
+    svc: &str,
+    generation: i64,
+) {
+    if generations.insert(svc.into(), generation) == Some(generation) {
+        return;
+    }
+    debug!("Queueing {} (generation {})", svc, generation);
+    queue.add(svc);
+}
+
+struct Operator {
+    queue: Mutex<WorkQueue>,
+    /// Config and region, re-read from disk when the shipcatconfig changes
+    config: Mutex<(Config, Region)>,
+    /// Last seen generation of every shipcatmanifest
+    generations: Mutex<BTreeMap<String, i64>>,
+    /// Consecutive poll failures of every watch
+    watch_failures: Mutex<BTreeMap<&'static str, u32>>,
+}
+
+impl Operator {
+    /// Record the outcome of polling a watch
+    ///
+    /// Returns how long to wait before polling again.
+    async fn polled(&self, watch: &'static str, res: std::result::Result<(), kube::Error>) -> Duration {
+        let mut failures = self.watch_failures.lock().await;
+        match res {
+            Ok(()) => {
+                failures.remove(watch);
+                Duration::from_secs(0)
+            }
+            Err(e) => {
+                let n = failures.entry(watch).or_insert(0);
+                *n += 1;
+                let delay = retry_delay(*n);
+                warn!("{} watch failed (retrying in {}s): {}", watch, delay.as_secs(), e);
+                delay
+            }
+        }
+    }
+
+    async fn health(&self) -> Health {
+        let mut health = self.queue.lock().await.health();
+        let watches = self.watch_failures.lock().await;
+        health.failing_watches = watches.keys().map(|w| w.to_string()).collect();
+        health
+    }
+
+    async fn enqueue_all(&self) -> Result<()> {
+        let (conf, region) = self.config.lock().await.clone();
+        let svcs = shipcat_filebacked::available(&conf, &region).await?;
+        let mut queue = self.queue.lock().await;
+        for mf in svcs {
+            queue.add(&mf.base.name);
+        }
+        Ok(())
+    }
+
+    async fn reconcile(&self, svc: String) {
+        let (conf, region) = self.config.lock().await.clone();
+        let res = apply::apply(
+            svc.clone(),
+            false,
+            &region,
+            &conf,
+            true,
+            region.rollbackOnFailure,
+            true,
+            false,
+            None,
+            None,
+        )
+        .await;
+        if let Err(e) = &res {
+            warn!("Failed to reconcile {}: {}", svc, e);
+        }
+        // late events for our own crd changes should not trigger another reconcile
+        match kubeapi::ShipKube::new_within(&svc, &region.namespace).await {
+            Ok(s) => {
+                if let Ok(crd) = s.get_minimal().await {
+                    if let Some(g) = crd.metadata.generation {
+                        self.generations.lock().await.insert(svc.clone(), g);
+                    }
+                }
+            }
+            Err(e) => warn!("Unable to refresh generation of {}: {}", svc, e),
+        }
+        self.queue.lock().await.done(&svc, res.is_ok());
+    }
+
+    async fn on_manifest(&self, ev: WatchEvent<ShipcatManifest>) {
+        let o = match ev {
+            WatchEvent::Added(o) | WatchEvent::Modified(o) => o,
+            WatchEvent::Deleted(o) => {
+                // removals are handled by `shipcat cluster crd reconcile`
+                debug!("Ignoring deletion of {}", Meta::name(&o));
+                return;
+            }
+            WatchEvent::Error(e) => {
+                warn!("shipcatmanifest watch error: {:?}", e);
+                return;
+            }
+        };
+        let svc = Meta::name(&o);
+        let generation = o.meta().generation.unwrap_or_default();
+        let mut generations = self.generations.lock().await;
+        let mut queue = self.queue.lock().await;
+        manifest_changed(&mut generations, &mut queue, &svc, generation);
+    }
+
+    async fn on_config(&self, ev: WatchEvent<ShipcatConfig>) {
+        let o = match ev {
+            WatchEvent::Added(o) | WatchEvent::Modified(o) => o,
+            WatchEvent::Deleted(_) => return,
+            WatchEvent::Error(e) => {
+                warn!("shipcatconfig watch error: {:?}", e);
+                return;
+            }
+        };
+        let region = self.config.lock().await.1.name.clone();
+        let name = Meta::name(&o);
+        if name != region && name != "unionised" {
+            return;
+        }
+        info!("shipcatconfig {} changed; reconciling all services", name);
+        match Config::new(ConfigState::Filtered, &region).await {
+            Ok(c) => *self.config.lock().await = c,
+            Err(e) => warn!("Unable to reload config, reconciling with the old one: {}", e),
+        }
+        if let Err(e) = self.enqueue_all().await {
+            warn!("Unable to queue services: {}", e);
+        }
+    }
+}
+
+async fn watch_manifests(op: &Operator, inf: Informer<ShipcatManifest>) -> Result<()> {
+    loop {
+        let mut events = match inf.poll().await {
+            Ok(evs) => {
+                op.polled("shipcatmanifest", Ok(())).await;
+                evs.boxed()
+            }
+            Err(e) => {
+                Delay::new(op.polled("shipcatmanifest", Err(e)).await).await;
+                continue;
+            }
+        };
+        while let Some(ev) = events.next().await {
+            match ev {
+                Ok(e) => op.on_manifest(e).await,
+                Err(e) => warn!("shipcatmanifest watch failed: {}", e),
+            }
+        }
+    }
+}
+
+async fn watch_configs(op: &Operator, inf: Informer<ShipcatConfig>) -> Result<()> {
+    loop {
+        let mut events = match inf.poll().await {
+            Ok(evs) => {
+                op.polled("shipcatconfig", Ok(())).await;
+                evs.boxed()
+            }
+            Err(e) => {
+                Delay::new(op.polled("shipcatconfig", Err(e)).await).await;
+                continue;
+            }
+        };
+        while let Some(ev) = events.next().await {
+            match ev {
+                Ok(e) => op.on_config(e).await,
+                Err(e) => warn!("shipcatconfig watch failed: {}", e),
+            }
+        }
+    }
+}
+
+async fn resync(op: &Operator, every: Duration) -> Result<()> {
+    loop {
+        Delay::new(every).await;
+        debug!("Periodic resync of all services");
+        if let Err(e) = op.enqueue_all().await {
+            warn!("Unable to queue services: {}", e);
+        }
+    }
+}
+
+async fn serve_health(op: &Operator, port: u16) -> Result<()> {
+    let mut listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("Serving health on port {}", port);
+    let mut failures = 0;
+    loop {
+        let mut sock = match listener.accept().await {
+            Ok((sock, _)) => {
+                failures = 0;
+                sock
+            }
+            Err(e) => {
+                // e.g. out of file descriptors; retrying is all we can do
+                failures += 1;
+                let delay = retry_delay(failures);
+                warn!("Health listener failed (retrying in {}s): {}", delay.as_secs(), e);
+                Delay::new(delay).await;
+                continue;
+            }
+        };
+        // every path is the health endpoint; the request itself is irrelevant,
+        // but it is drained so closing the socket does not reset the connection
+        let mut buf = [0; 1024];
+        let _ = tokio::time::timeout(Duration::from_secs(5), sock.read(&mut buf)).await;
+        let health = op.health().await;
+        let status = if health.is_healthy() {
+            "200 OK"
+        } else {
+            "503 Service Unavailable"
+        };
+        let body = serde_json::to_string(&health)?;
+        let res = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        if let Err(e) = sock.write_all(res.as_bytes()).await {
+            debug!("Failed to respond to health check: {}", e);
+        }
+    }
+}
+
+async fn work(op: &Operator, workers: usize) -> Result<()> {
+    let mut running = FuturesUnordered::new();
+    loop {
+        while running.len() < workers.max(1) {
+            let next = op.queue.lock().await.next(Instant::now());
+            match next {
+                Some(svc) => running.push(op.reconcile(svc)),
+                None => break,
+            }
+        }
+        // wake up on completions, or to pick up newly queued services
+        let tick = Delay::new(Duration::from_secs(TICK_SECS));
+        if running.is_empty() {
+            tick.await;
+        } else if let Either::Right(_) = future::select(running.next(), tick).await {
+            trace!("{} reconciles in progress", running.len());
+        }
+    }
+}
+
+/// Entry point for `shipcat operator`
+///
+/// Watches the shipcatmanifests and shipcatconfigs in the region's namespace,
+/// and reconciles affected services through `apply::apply` (which updates their `.status`).
+/// Services are reconciled from the local manifests, as with `shipcat cluster crd reconcile`.
+/// Every service is queued at startup and on every resync.
+pub async fn run(conf: Config, region: Region, params: OperatorParams) -> Result<()> {
+    let client: APIClient = kubeapi::make_client().await?;
+    let lp = ListParams::default();
+    let mfs = Informer::new(
+        client.clone(),
+        lp.clone(),
+        Resource::namespaced::<ShipcatManifest>(&region.namespace),
+    );
+    let cfgs = Informer::new(
+        client,
+        lp,
+        Resource::namespaced::<ShipcatConfig>(&region.namespace),
+    );
+    info!("Operating on {} with {} workers", region.name, params.workers);
+    let op = Operator {
+        queue: Mutex::new(WorkQueue::new(params.min_interval)),
+        config: Mutex::new((conf, region)),
+        generations: Mutex::new(BTreeMap::new()),
+        watch_failures: Mutex::new(BTreeMap::new()),
+    };
+    // all of these run forever (retrying their own failures)
+    futures::try_join!(
+        watch_manifests(&op, mfs),
+        watch_configs(&op, cfgs),
+        resync(&op, params.resync),
+        serve_health(&op, params.port),
+        work(&op, params.workers),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{manifest_changed, retry_delay, WorkQueue};
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn work_queue_dedup_and_backoff() {
+        let min = Duration::from_secs(60);
+        let mut q = WorkQueue::new(min);
+        let now = Instant::now();
+        q.add("fake-ask");
+        q.add("fake-storage");
+        q.add("fake-ask");
+        assert_eq!(q.health().queued, 2);
+
+        assert_eq!(q.next(now), Some("fake-ask".into()));
+        // queued while running => requeued once done
+        q.add("fake-ask");
+        assert_eq!(q.health().queued, 1);
+        q.done("fake-ask", true);
+        assert_eq!(q.next(now), Some("fake-storage".into()));
+        // rate limited
+        assert_eq!(q.next(now), None);
+        assert_eq!(q.next(now + min), Some("fake-ask".into()));
+
+        // failures are retried with a doubling backoff
+        q.done("fake-ask", false);
+        assert_eq!(q.health().failing, vec!["fake-ask".to_string()]);
+        assert_eq!(q.next(now + min * 2), None);
+        assert_eq!(q.next(now + min * 3), Some("fake-ask".into()));
+        q.done("fake-ask", true);
+        assert!(q.health().failing.is_empty());
+        assert_eq!(q.health().queued, 0);
+    }
+
+    #[test]
+    fn manifest_changes_while_running() {
+        let min = Duration::from_secs(60);
+        let mut q = WorkQueue::new(min);
+        let mut generations = BTreeMap::new();
+        let now = Instant::now();
+        manifest_changed(&mut generations, &mut q, "fake-ask", 1);
+        assert_eq!(q.next(now), Some("fake-ask".into()));
+
+        // modified while reconciling => reconciled again afterwards
+        manifest_changed(&mut generations, &mut q, "fake-ask", 2);
+        q.done("fake-ask", true);
+        assert_eq!(q.next(now + min), Some("fake-ask".into()));
+        q.done("fake-ask", true);
+
+        // events without spec changes are ignored
+        manifest_changed(&mut generations, &mut q, "fake-ask", 2);
+        assert_eq!(q.health().queued, 0);
+    }
+
+    #[test]
+    fn health_and_retries() {
+        let mut q = WorkQueue::new(Duration::from_secs(60));
+        q.add("fake-ask");
+        let svc = q.next(Instant::now()).unwrap();
+        q.done(&svc, false);
+        // failing services alone do not need an operator restart
+        let mut health = q.health();
+        assert!(health.is_healthy());
+        health.failing_watches = vec!["shipcatmanifest".into()];
+        assert!(!health.is_healthy());
+
+        assert_eq!(retry_delay(1), Duration::from_secs(2));
+        assert_eq!(retry_delay(3), Duration::from_secs(8));
+        assert_eq!(retry_delay(100), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{kubectl, Config, Region, Result};
+use regex::Regex;
+use std::path::PathBuf;
+use tokio::fs;
+
+/// A version pin to write for a service in a region
+#[derive(Debug, Clone)]
+pub struct Promotion {
+    /// Name of service
+    pub service: String,
+    /// Version currently pinned in the target region (if any)
+    pub current: Option<String>,
+    /// Version running in the source region
+    pub version: String,
+    /// Override file the pin is written to
+    pub file: PathBuf,
+}
+
+/// Set the version pin in the contents of a manifest override file
+///
+/// Replaces an existing `version:` key in place to preserve comments and ordering,
+/// otherwise the key is prepended.
+pub fn set_version_pin(data: &str, version: &str) -> String {
+    let re = Regex::new(r"(?m)^version:.*$").unwrap();
+    let pin = format!("version: {}", version);
+    if re.is_match(data) {
+        re.replace(data, pin.as_str()).to_string()
+    } else {
+        format!("{}\n{}", pin, data)
+    }
+}
+
+async fn promotion(
+    svc: &str,
+    from: &Region,
+    to: &Region,
+    conf_to: &Config,
+    env_shared: bool,
+) -> Result<Option<Promotion>> {
+    // NB: assumes kube contexts are named after regions
+    let version = kubectl::get_running_version_in(svc, &from.namespace, Some(&from.name)).await?;
+    to.versioningScheme.verify(&version)?;
+    let current = shipcat_filebacked::load_metadata(svc, conf_to, to).await?.version;
+    if current.as_ref() == Some(&version) {
+        debug!("{} already pinned to {} in {}", svc, version, to.name);
+        return Ok(None);
+    }
+    Ok(Some(Promotion {
+        service: svc.into(),
+        current,
+        version,
+        file: shipcat_filebacked::version_override_path(svc, to, env_shared),
+    }))
+}
+
+async fn write_pin(p: &Promotion) -> Result<()> {
+    let data = if p.file.is_file() {
+        fs::read_to_string(&p.file).await?
+    } else {
+        String::new()
+    };
+    fs::write(&p.file, set_version_pin(&data, &p.version)).await?;
+    Ok(())
+}
+
+/// Entry point for `shipcat promote`
+///
+/// Pins the version running in the `from` region in the manifests of the `to` region.
+/// Without a service, every service available in both regions is promoted.
+pub async fn promote(
+    svc: Option<String>,
+    from: &Region,
+    conf_from: &Config,
+    to: &Region,
+    conf_to: &Config,
+) -> Result<Vec<Promotion>> {
+    if from.name == to.name {
+        bail!("Cannot promote from {} to itself", from.name);
+    }
+    let env_shared = Config::read()
+        .await?
+        .get_regions()
+        .iter()
+        .filter(|r| r.environment == to.environment)
+        .count()
+        > 1;
+
+    let mut promotions = vec![];
+    if let Some(s) = svc {
+        promotions.extend(promotion(&s, from, to, conf_to, env_shared).await?);
+    } else {
+        let sources = shipcat_filebacked::available(conf_from, from).await?;
+        for mf in shipcat_filebacked::available(conf_to, to).await? {
+            let name = mf.base.name;
+            if !sources.iter().any(|s| s.base.name == name) {
+                continue;
+            }
+            match promotion(&name, from, to, conf_to, env_shared).await {
+                Ok(p) => promotions.extend(p),
+                Err(e) => warn!("Not promoting {}: {}", name, e),
+            }
+        }
+    }
+
+    for p in &promotions {
+        write_pin(p).await?;
+        info!(
+            "Promoted {} from {} to {} in {}",
+            p.service,
+            p.current.clone().unwrap_or_else(|| "unpinned".into()),
+            p.version,
+            p.file.display()
+        );
+    }
+    Ok(promotions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::set_version_pin;
+
+    #[test]
+    fn version_pin_replace() {
+        let data = "# pinned by release train\nversion: 1.6.0\nenv:\n  MODE: development\n";
+        let res = set_version_pin(data, "1.7.0");
+        assert_eq!(
+            res,
+            "# pinned by release train\nversion: 1.7.0\nenv:\n  MODE: development\n"
+        );
+        // nested keys named version are left alone
+        let nested = "env:\n  version: 1\n";
+        assert_eq!(
+            set_version_pin(nested, "2.0.0"),
+            "version: 2.0.0\nenv:\n  version: 1\n"
+        );
+        assert_eq!(set_version_pin("", "2.0.0"), "version: 2.0.0\n");
+    }
+}
@@ -0,0 +1,339 @@
+use std::str::FromStr;
+
+use super::{Error, Result};
+use crate::diff::Changes;
+
+/// How to print a diff report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    /// Diffs for terminals
+    Human,
+    /// Summary table with collapsible diffs for pull request comments
+    Markdown,
+    /// The full report as json
+    Json,
+    /// A testsuite with a testcase per service for CI test reporters
+    Junit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "human" => Ok(Self::Human),
+            "markdown" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            "junit" => Ok(Self::Junit),
+            _ => bail!("Report format must be human, markdown, json or junit"),
+        }
+    }
+}
+
+/// Summarised outcome of diffing a service
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffStatus {
+    Changed,
+    Unchanged,
+    /// Only the version changed (and everything that mentions it)
+    VersionOnly,
+    /// The service could not be diffed
+    Failed,
+}
+
+impl DiffStatus {
+    fn name(self) -> &'static str {
+        match self {
+            DiffStatus::Changed => "changed",
+            DiffStatus::Unchanged => "unchanged",
+            DiffStatus::VersionOnly => "version-only",
+            DiffStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Diff of a single service
+#[derive(Serialize, Clone, Debug)]
+pub struct DiffResult {
+    pub name: String,
+    pub status: DiffStatus,
+    /// Old and new version when the version changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub versions: Option<(String, String)>,
+    /// Changes (none if unchanged or failed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Changes>,
+    /// Why the diff failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DiffResult {
+    /// Classify the outcome of diffing a service
+    ///
+    /// Secrets must be obfuscated before this point.
+    pub fn new(name: &str, res: Result<Changes>) -> Self {
+        let mut dr = DiffResult {
+            name: name.to_string(),
+            status: DiffStatus::Unchanged,
+            versions: None,
+            diff: None,
+            error: None,
+        };
+        match res {
+            Err(e) => {
+                dr.status = DiffStatus::Failed;
+                dr.error = Some(e.to_string());
+            }
+            Ok(changes) => {
+                if !changes.is_empty() {
+                    dr.versions = changes.version_change();
+                    dr.status = match &dr.versions {
+                        Some((v1, v2)) if changes.is_version_only((v1, v2)) => DiffStatus::VersionOnly,
+                        _ => DiffStatus::Changed,
+                    };
+                    dr.diff = Some(changes);
+                }
+            }
+        }
+        dr
+    }
+
+    fn title(&self) -> String {
+        match &self.versions {
+            Some((v1, v2)) if self.status == DiffStatus::VersionOnly => {
+                format!("{} ({} -> {})", self.status.name(), v1, v2)
+            }
+            _ => self.status.name().to_string(),
+        }
+    }
+
+    fn details(&self) -> Option<String> {
+        match (&self.diff, &self.error) {
+            (Some(d), _) => Some(d.render(false)),
+            (None, Some(e)) => Some(e.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Diffs of a set of services in a region
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DiffReport {
+    pub region: String,
+    pub services: Vec<DiffResult>,
+}
+
+impl DiffReport {
+    pub fn new(region: &str) -> Self {
+        DiffReport {
+            region: region.to_string(),
+            services: vec![],
+        }
+    }
+
+    /// Add a service (keeping services sorted)
+    pub fn push(&mut self, dr: DiffResult) {
+        let idx = self.services.iter().take_while(|s| s.name < dr.name).count();
+        self.services.insert(idx, dr);
+    }
+
+    /// Exit code for the report
+    ///
+    /// 0 when every service is unchanged, 1 when some changed, and 2 when some failed to diff.
+    pub fn exit_code(&self) -> i32 {
+        if self.count(DiffStatus::Failed) > 0 {
+            2
+        } else if self.services.iter().all(|s| s.status == DiffStatus::Unchanged) {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn count(&self, status: DiffStatus) -> usize {
+        self.services.iter().filter(|s| s.status == status).count()
+    }
+
+    /// One line summary, e.g. `2 changed, 1 version-only, 10 unchanged, 0 failed`
+    pub fn summary(&self) -> String {
+        let all = [
+            DiffStatus::Changed,
+            DiffStatus::VersionOnly,
+            DiffStatus::Unchanged,
+            DiffStatus::Failed,
+        ];
+        all.iter()
+            .map(|s| format!("{} {}", self.count(*s), s.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Render the report in a given format
+    pub fn render(&self, fmt: ReportFormat) -> Result<String> {
+        Ok(match fmt {
+            ReportFormat::Human => self.human(),
+            ReportFormat::Markdown => self.markdown(),
+            ReportFormat::Json => serde_json::to_string_pretty(self)?,
+            ReportFormat::Junit => self.junit(),
+        })
+    }
+
+    fn human(&self) -> String {
+        let mut res = vec![];
+        for s in &self.services {
+            res.push(format!("{}: {}", s.name, s.title()));
+            res.extend(s.details());
+        }
+        res.push(self.summary());
+        res.join("\n")
+    }
+
+    fn markdown(&self) -> String {
+        let mut res = vec![
+            format!("### shipcat diff in `{}`", self.region),
+            String::new(),
+            self.summary(),
+            String::new(),
+            "| Service | Status |".to_string(),
+            "| --- | --- |".to_string(),
+        ];
+        for s in &self.services {
+            res.push(format!("| `{}` | {} |", s.name, s.title()));
+        }
+        for s in &self.services {
+            if let Some(details) = s.details() {
+                res.push(String::new());
+                res.push("<details>".to_string());
+                res.push(format!(
+                    "<summary><code>{}</code> {}</summary>",
+                    s.name,
+                    s.title()
+                ));
+                res.push(String::new());
+                res.push("```diff".to_string());
+                // nothing can escape the code block
+                res.push(details.replace("```", "'''"));
+                res.push("```".to_string());
+                res.push(String::new());
+                res.push("</details>".to_string());
+            }
+        }
+        res.join("\n")
+    }
+
+    fn junit(&self) -> String {
+        let mut res = vec![
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+            format!(
+                r#"<testsuite name="shipcat diff {}" tests="{}" failures="{}" errors="{}">"#,
+                xml_escape(&self.region),
+                self.services.len(),
+                self.count(DiffStatus::Changed),
+                self.count(DiffStatus::Failed),
+            ),
+        ];
+        for s in &self.services {
+            let case = format!(
+                r#"  <testcase classname="{}" name="{}""#,
+                xml_escape(&self.region),
+                xml_escape(&s.name)
+            );
+            let body = s.details().map(|d| xml_escape(&d)).unwrap_or_default();
+            res.push(match s.status {
+                DiffStatus::Unchanged => format!("{}/>", case),
+                DiffStatus::VersionOnly => {
+                    format!("{}>\n    <system-out>{}</system-out>\n  </testcase>", case, body)
+                }
+                DiffStatus::Changed => format!(
+                    "{}>\n    <failure message=\"{}\">{}</failure>\n  </testcase>",
+                    case,
+                    s.title(),
+                    body
+                ),
+                DiffStatus::Failed => format!(
+                    "{}>\n    <error message=\"{}\">{}</error>\n  </testcase>",
+                    case,
+                    s.title(),
+                    body
+                ),
+            });
+        }
+        res.push("</testsuite>".to_string());
+        res.join("\n")
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiffReport, DiffResult, DiffStatus, ReportFormat};
+    use crate::diff::Changes;
+    use serde_json::json;
+
+    #[test]
+    fn diff_report_formats() {
+        let dep = |v: &str, replicas: u32| {
+            json!({
+                "kind": "Deployment",
+                "metadata": { "name": "fake" },
+                "spec": { "replicas": replicas, "image": format!("fake:{}", v) },
+            })
+        };
+        let mut report = DiffReport::new("dev-uk");
+        report.push(DiffResult::new(
+            "fake-storage",
+            Ok(Changes::between(&[dep("1.0.0", 1)], &[dep("1.1.0", 1)])),
+        ));
+        report.push(DiffResult::new(
+            "fake-ask",
+            Ok(Changes::between(&[dep("1.0.0", 1)], &[dep("1.0.0", 2)])),
+        ));
+        report.push(DiffResult::new("blog", Ok(Changes::default())));
+        report.push(DiffResult::new("webapp", Err("no <crd> found".into())));
+
+        let statuses = report.services.iter().map(|s| s.status).collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                DiffStatus::Unchanged,
+                DiffStatus::Changed,
+                DiffStatus::VersionOnly,
+                DiffStatus::Failed,
+            ]
+        );
+        assert_eq!(report.exit_code(), 2);
+        assert_eq!(
+            report.summary(),
+            "1 changed, 1 version-only, 1 unchanged, 1 failed"
+        );
+
+        let md = report.render(ReportFormat::Markdown).unwrap();
+        assert!(md.contains("| `fake-storage` | version-only (1.0.0 -> 1.1.0) |"));
+        assert!(md.contains("<summary><code>fake-ask</code> changed</summary>"));
+        assert!(md.contains("+ spec.replicas: 2"));
+        assert!(!md.contains("<code>blog</code>"));
+
+        let junit = report.render(ReportFormat::Junit).unwrap();
+        assert!(junit.contains(r#"tests="4" failures="1" errors="1""#));
+        assert!(junit.contains(r#"<testcase classname="dev-uk" name="blog"/>"#));
+        assert!(junit.contains("no &lt;crd&gt; found"));
+
+        let js = serde_json::to_value(&report).unwrap();
+        assert_eq!(js["services"][2]["status"], "versionOnly");
+        assert_eq!(js["services"][2]["versions"], json!(["1.0.0", "1.1.0"]));
+        assert_eq!(js["services"][3]["error"], "no <crd> found");
+
+        report.services.pop();
+        assert_eq!(report.exit_code(), 1);
+        report.services.retain(|s| s.status == DiffStatus::Unchanged);
+        assert_eq!(report.exit_code(), 0);
+    }
+}
@@ -1,5 +1,5 @@
 use super::{Config, Region, Result};
-use shipcat_definitions::{ShipcatConfig, ShipcatManifest};
+use shipcat_definitions::{schema::json_schema, ShipcatConfig, ShipcatManifest};
 
 /// Print the config
 ///
@@ -18,6 +18,20 @@ pub fn config_crd(conf: Config) -> Result<()> {
     Ok(())
 }
 
+/// Print the JSON Schema of a shipcat file
+///
+/// Editors can use this to autocomplete and validate manifests and config.
+pub fn schema(kind: &str) -> Result<()> {
+    let schema = match kind {
+        "manifest" => shipcat_filebacked::manifest_schema(),
+        "config" => json_schema::<Config>(),
+        "region" => json_schema::<Region>(),
+        _ => bail!("Schemas are available for manifest, config and region"),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
 // TODO: deprecate
 pub async fn manifest_crd(svc: &str, conf: &Config, reg: &Region) -> Result<()> {
     let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
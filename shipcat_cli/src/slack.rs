@@ -31,8 +31,8 @@ pub struct Message {
     /// Optional color for the attachment API
     pub color: Option<String>,
 
-    /// Optional code input
-    pub code: Option<String>,
+    /// Optional diff to attach
+    pub diff: Option<diff::Changes>,
 
     /// Optional version to send when not having code diffs
     pub version: Option<String>,
@@ -164,17 +164,18 @@ async fn send_internal(msg: Message, chan: String, owners: &Owners) -> Result<()
     let mut texts = vec![Text(msg.text.into())];
 
     let mut codeattach = None;
-    if let Some(diff) = msg.code {
+    if let Some(changes) = msg.diff {
         // does the diff contain versions?
-        let is_version_only = if let Some((v1, v2)) = diff::infer_version_change(&diff) {
+        let is_version_only = if let Some((v1, v2)) = changes.version_change() {
             let lnk = create_github_compare_url(&md, (&v1, &v2));
             texts.push(lnk);
-            diff::is_version_only(&diff, (&v1, &v2))
+            changes.is_version_only((&v1, &v2))
         } else {
             false
         };
         // is diff otherwise meaningful?
         if !is_version_only {
+            let diff = changes.render(false);
             codeattach = Some(
                 AttachmentBuilder::new(diff.clone())
                     .color("#439FE0")
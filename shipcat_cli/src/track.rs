@@ -1,13 +1,24 @@
 //- kubeapi module to track upgrades
-use crate::{kubeapi::ShipKube, slack::short_ver, Result};
-use chrono::{Duration, Utc};
+use crate::{
+    events::{self, ApplyEvent},
+    kubeapi::ShipKube,
+    slack::short_ver,
+    ErrorKind, Result,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::{
+    future::{self, Either},
+    StreamExt, TryStreamExt,
+};
+use indicatif::ProgressBar;
 use k8s_openapi::api::{
     apps::v1::{Deployment, ReplicaSet, StatefulSet},
-    core::v1::Pod,
+    core::v1::{Event, Pod},
 };
-use kube::api::{Meta, ObjectList};
+use kube::api::{Meta, ObjectList, WatchEvent};
 use shipcat_definitions::{Manifest, PrimaryWorkload};
 use std::{
+    collections::BTreeSet,
     convert::{TryFrom, TryInto},
     fmt::{self, Debug},
 };
@@ -161,12 +172,193 @@ impl TryFrom<ReplicaSet> for ReplicaSetSummary {
     }
 }
 
+/// A summary of a kubernetes Event
+pub struct EventSummary {
+    pub kind: String,
+    pub name: String,
+    pub type_: String,
+    pub reason: String,
+    pub message: String,
+    pub count: i32,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+impl Debug for EventSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = self
+            .last_seen
+            .map(|t| format_duration(Utc::now().signed_duration_since(t)))
+            .unwrap_or_else(|| "?".into());
+        let count = if self.count > 1 {
+            format!(" (x{})", self.count)
+        } else {
+            "".into()
+        };
+        write!(
+            f,
+            "{0:<6} {1:<8} {2:<20} {3:<60} {4}{5}",
+            age,
+            self.type_,
+            self.reason,
+            format!("{}/{}", self.kind, self.name),
+            self.message,
+            count
+        )
+    }
+}
+
+impl From<Event> for EventSummary {
+    /// Helper to convert the openapi Event to the useful info
+    fn from(ev: Event) -> EventSummary {
+        let last_seen = match (ev.last_timestamp, ev.event_time, ev.first_timestamp) {
+            (Some(t), _, _) => Some(t.0),
+            (None, Some(t), _) => Some(t.0),
+            (None, None, t) => t.map(|t| t.0),
+        };
+        EventSummary {
+            kind: ev.involved_object.kind.unwrap_or_default(),
+            name: ev.involved_object.name.unwrap_or_default(),
+            type_: ev.type_.unwrap_or_default(),
+            reason: ev.reason.unwrap_or_default(),
+            message: ev.message.unwrap_or_default().trim().to_string(),
+            count: ev.count.unwrap_or(1),
+            last_seen,
+        }
+    }
+}
+
+impl EventSummary {
+    /// Whether the event is about one of the named workloads (or the objects they create)
+    ///
+    /// Pods, ReplicaSets and canaries are named after the workload they belong to,
+    /// followed by suffixes generated by kubernetes (see `is_generated_suffix`).
+    fn concerns(&self, names: &[String]) -> bool {
+        names.iter().any(|n| match self.name.strip_prefix(n.as_str()) {
+            Some(rest) => is_generated_suffix(rest),
+            None => false,
+        })
+    }
+
+    fn is_warning(&self) -> bool {
+        self.type_ == "Warning"
+    }
+
+    /// Identity of an event when deduplicating
+    ///
+    /// Object names are left out so the same failure is shown once across all pods.
+    fn key(&self) -> (String, String, String) {
+        (self.kind.clone(), self.reason.clone(), self.message.clone())
+    }
+}
+
+/// Characters kubernetes uses for generated names (without vowels, so they never form words)
+const GENERATED_NAME_CHARS: &str = "bcdfghjklmnpqrstvwxz2456789";
+
+/// Whether the rest of an object name after a workload name was generated for that workload
+///
+/// Matches `-<hash>` (ReplicaSets), `-<hash>-<id>` (pods), `-<ordinal>` (StatefulSet pods),
+/// as well as these under `-canary` for the canary Deployment.
+/// Other services sharing the prefix (like `api-gateway` for `api`) do not match.
+fn is_generated_suffix(rest: &str) -> bool {
+    let rest = rest.strip_prefix("-canary").unwrap_or(rest);
+    if rest.is_empty() {
+        return true;
+    }
+    let parts = match rest.strip_prefix('-') {
+        Some(r) => r.split('-').collect::<Vec<_>>(),
+        None => return false,
+    };
+    let generated = |s: &str| !s.is_empty() && s.chars().all(|c| GENERATED_NAME_CHARS.contains(c));
+    let ordinal = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    match parts.as_slice() {
+        [single] => generated(single) || ordinal(single),
+        [hash, id] => generated(hash) && generated(id),
+        _ => false,
+    }
+}
+
+/// Names of the workloads of a service, for filtering events
+fn event_names(mf: &Manifest) -> Vec<String> {
+    workloads(mf).into_iter().map(|w| w.name).collect()
+}
+
+/// Recent events about the objects of a service, oldest first
+pub async fn recent_events(mf: &Manifest, kube: &ShipKube) -> Result<Vec<EventSummary>> {
+    let names = event_names(mf);
+    let mut evs = kube
+        .get_events()
+        .await?
+        .items
+        .into_iter()
+        .map(EventSummary::from)
+        .filter(|e| e.concerns(&names))
+        .collect::<Vec<_>>();
+    evs.sort_by_key(|e| e.last_seen);
+    Ok(evs)
+}
+
+/// Show new warnings about the objects of a service until cancelled
+///
+/// Warnings are deduplicated across objects, and printed above the progress bars
+/// (or emitted as events when streaming events).
+async fn watch_events(mf: &Manifest, kube: &ShipKube, pb: &ProgressBar) -> Result<()> {
+    let names = event_names(mf);
+    // only show events from now on
+    let version = kube
+        .get_events()
+        .await?
+        .metadata
+        .resource_version
+        .unwrap_or_default();
+    let inf = kube.events_informer(version);
+    let mut seen = BTreeSet::new();
+    loop {
+        let mut stream = inf.poll().await.map_err(ErrorKind::KubeError)?.boxed();
+        while let Some(we) = stream.try_next().await.map_err(ErrorKind::KubeError)? {
+            let ev = match we {
+                WatchEvent::Added(e) | WatchEvent::Modified(e) => EventSummary::from(e),
+                _ => continue,
+            };
+            if !ev.is_warning() || !ev.concerns(&names) || !seen.insert(ev.key()) {
+                continue;
+            }
+            if events::is_jsonl() {
+                events::emit(ApplyEvent::KubeEvent {
+                    service: mf.name.clone(),
+                    object: format!("{}/{}", ev.kind, ev.name),
+                    reason: ev.reason.clone(),
+                    message: ev.message.clone(),
+                });
+            } else {
+                pb.println(format!("{:?}", ev));
+            }
+        }
+    }
+}
+
 /// Debug why a workload is in the state it is in
 pub async fn debug(mf: &Manifest, kube: &ShipKube) -> Result<()> {
     match mf.workload {
-        PrimaryWorkload::Deployment => debug_deployment(kube).await,
-        PrimaryWorkload::Statefulset => debug_statefulset(kube).await,
+        PrimaryWorkload::Deployment => debug_deployment(kube).await?,
+        PrimaryWorkload::Statefulset => debug_statefulset(kube).await?,
     }
+    debug_events(mf, kube).await
+}
+
+/// Show the recent events about a service
+///
+/// Kubernetes keeps events for an hour by default.
+async fn debug_events(mf: &Manifest, kube: &ShipKube) -> Result<()> {
+    let evs = recent_events(mf, kube).await?;
+    if evs.is_empty() {
+        info!("No recent events for {}", mf.name);
+        return Ok(());
+    }
+    info!("Recent events:");
+    for e in evs {
+        events::print_human(&format!("{:?}", e));
+    }
+    Ok(())
 }
 
 /// Debug a deployment
@@ -201,16 +393,16 @@ async fn debug_statefulset(kube: &ShipKube) -> Result<()> {
 async fn debug_pods(pods: ObjectList<Pod>, kube: &ShipKube) -> Result<()> {
     for pod in pods {
         let podstate = PodSummary::try_from(pod)?;
-        println!("{:?}", podstate);
+        events::print_human(&format!("{:?}", podstate));
         if podstate.running != podstate.containers as i32 {
             info!(
                 "Fetching logs from non-ready main container in pod: {}",
                 podstate.name
             );
-            match kube.get_pod_logs(&podstate.name).await {
+            match kube.get_pod_logs(&podstate.name, &kube.name).await {
                 Ok(logs) => {
                     warn!("Last 30 log lines:");
-                    println!("{}", logs)
+                    events::print_human(&logs)
                 }
                 Err(e) => warn!("Failed to get logs from {}: {}", podstate.name, e),
             }
@@ -219,6 +411,123 @@ async fn debug_pods(pods: ObjectList<Pod>, kube: &ShipKube) -> Result<()> {
     Ok(())
 }
 
+/// Container waiting reasons that will not resolve without a new image
+const BAD_IMAGE_REASONS: &[&str] = &["ImagePullBackOff", "InvalidImageName", "ErrImageNeverPull"];
+
+/// Restarts before a crash looping container is considered broken
+const CRASHLOOP_RESTARTS: i32 = 3;
+
+/// Seconds a pod may be unschedulable before giving up (leaves time for node autoscaling)
+const UNSCHEDULABLE_GRACE_SECS: i64 = 180;
+
+/// A reason for a rollout to be unable to progress without intervention
+#[derive(Debug, PartialEq)]
+pub enum RolloutBlocker {
+    /// The image of a container cannot be pulled
+    BadImage {
+        pod: String,
+        container: String,
+        message: String,
+    },
+    /// A container keeps crashing
+    CrashLoop {
+        pod: String,
+        container: String,
+        restarts: i32,
+    },
+    /// A pod cannot be placed on any node
+    Unschedulable { pod: String, message: String },
+}
+
+impl RolloutBlocker {
+    /// Reason used in the rollout condition
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RolloutBlocker::BadImage { .. } => "BadImage",
+            RolloutBlocker::CrashLoop { .. } => "CrashLoop",
+            RolloutBlocker::Unschedulable { .. } => "Unschedulable",
+        }
+    }
+}
+
+impl fmt::Display for RolloutBlocker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RolloutBlocker::BadImage {
+                pod,
+                container,
+                message,
+            } => write!(
+                f,
+                "cannot pull the image of {} in {}: {}",
+                container, pod, message
+            ),
+            RolloutBlocker::CrashLoop {
+                pod,
+                container,
+                restarts,
+            } => write!(
+                f,
+                "{} in {} is crash looping ({} restarts)",
+                container, pod, restarts
+            ),
+            RolloutBlocker::Unschedulable { pod, message } => {
+                write!(f, "{} cannot be scheduled: {}", pod, message)
+            }
+        }
+    }
+}
+
+/// Find why a pod is stuck (if it is)
+fn pod_blocker(pod: &Pod, now: DateTime<Utc>) -> Option<RolloutBlocker> {
+    let name = pod.metadata.as_ref()?.name.clone()?;
+    let status = pod.status.as_ref()?;
+    for cs in status.container_statuses.iter().flatten() {
+        let waiting = match cs.state.as_ref().and_then(|s| s.waiting.as_ref()) {
+            Some(w) => w,
+            None => continue,
+        };
+        let reason = waiting.reason.clone().unwrap_or_default();
+        if BAD_IMAGE_REASONS.contains(&reason.as_str()) {
+            return Some(RolloutBlocker::BadImage {
+                pod: name,
+                container: cs.name.clone(),
+                message: waiting.message.clone().unwrap_or(reason),
+            });
+        }
+        if reason == "CrashLoopBackOff" && cs.restart_count >= CRASHLOOP_RESTARTS {
+            return Some(RolloutBlocker::CrashLoop {
+                pod: name,
+                container: cs.name.clone(),
+                restarts: cs.restart_count,
+            });
+        }
+    }
+    for c in status.conditions.iter().flatten() {
+        if c.type_ != "PodScheduled" || c.status != "False" || c.reason.as_deref() != Some("Unschedulable") {
+            continue;
+        }
+        let since = c.last_transition_time.as_ref().map(|t| t.0).unwrap_or(now);
+        if now.signed_duration_since(since) >= Duration::seconds(UNSCHEDULABLE_GRACE_SECS) {
+            return Some(RolloutBlocker::Unschedulable {
+                pod: name,
+                message: c.message.clone().unwrap_or_default(),
+            });
+        }
+    }
+    None
+}
+
+/// Find why the pods of a pinned workload revision are stuck (if they are)
+async fn rollout_blocker(w: &Workload, kube: &ShipKube, hash: &str) -> Result<Option<RolloutBlocker>> {
+    let pods = match w.kind {
+        PrimaryWorkload::Deployment => kube.get_pods_by_template_hash(hash).await?,
+        PrimaryWorkload::Statefulset => kube.get_pods_by_revision(hash).await?,
+    };
+    let now = Utc::now();
+    Ok(pods.items.iter().find_map(|p| pod_blocker(p, now)))
+}
+
 /// A summary of a Deployment's status
 #[derive(Debug)]
 pub struct DeploySummary {
@@ -314,22 +623,22 @@ struct RolloutResult {
 }
 
 /// Check if a rollout has completed
-async fn rollout_status(mf: &Manifest, kube: &ShipKube, hash: &Option<String>) -> Result<RolloutResult> {
-    match mf.workload {
+async fn rollout_status(w: &Workload, kube: &ShipKube, hash: &Option<String>) -> Result<RolloutResult> {
+    match w.kind {
         PrimaryWorkload::Deployment => {
             // Get root data from Deployment status
             let deploy = kube.get_deploy().await?;
             let d = DeploySummary::try_from(deploy)?;
-            debug!("{}: {:?}", mf.name, d);
+            debug!("{}: {:?}", w.name, d);
             // Wait for at least the minimum number...
 
             let mut acurate_progress = None; // accurate progress number
-            let mut minimum = mf.min_replicas(); // minimum replicas we wait for
+            let mut minimum = w.minimum; // minimum replicas we wait for
             if let Some(tpl_hash) = hash {
                 // Infer from pinned ReplicaSet status (that was latest during apply)
                 if let Some(rs) = kube.get_rs_by_template_hash(&tpl_hash).await? {
                     let r = ReplicaSetSummary::try_from(rs)?;
-                    debug!("{}: {:?}", mf.name, r);
+                    debug!("{}: {:?}", w.name, r);
                     acurate_progress = Some(r.ready);
                     // rs might have scaled it up during rollout
                     minimum = std::cmp::max(minimum, r.replicas.try_into().unwrap_or(0));
@@ -378,7 +687,7 @@ async fn rollout_status(mf: &Manifest, kube: &ShipKube, hash: &Option<String>) -
         PrimaryWorkload::Statefulset => {
             let ss = kube.get_statefulset().await?;
             let s = StatefulSummary::try_from(ss)?;
-            let minimum = mf.min_replicas();
+            let minimum = w.minimum;
 
             let ok = s.updated_replicas >= minimum as i32
                 && s.updated_replicas == s.ready
@@ -411,17 +720,121 @@ async fn rollout_status(mf: &Manifest, kube: &ShipKube, hash: &Option<String>) -
     }
 }
 
-/// Track the rollout of the main workload
+/// A workload of a service that is tracked during an upgrade
+#[derive(Clone, Debug)]
+struct Workload {
+    /// Name of the workload object (and the app label of its pods)
+    name: String,
+    kind: PrimaryWorkload,
+    /// Number of ready replicas needed for the rollout to complete
+    minimum: u32,
+}
+
+/// The main workload followed by the worker deployments
+fn workloads(mf: &Manifest) -> Vec<Workload> {
+    let mut res = vec![Workload {
+        name: mf.name.clone(),
+        kind: mf.workload.clone(),
+        minimum: mf.min_replicas(),
+    }];
+    for w in &mf.workers {
+        res.push(Workload {
+            name: w.container.name.clone(),
+            kind: PrimaryWorkload::Deployment,
+            minimum: w.min_replicas(),
+        });
+    }
+    res
+}
+
+/// Track the rollout of all workloads of a service
+///
+/// The main workload and every worker get a progress bar and are tracked concurrently.
+/// When streaming events, the bars are hidden and progress events are emitted instead.
+/// Kubernetes warnings about the service are shown as they happen.
+/// CronJobs are verified to have been accepted with the new template.
+/// Returns false if any workload timed out, and the first error otherwise encountered.
 pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
+    use futures::future::join_all;
+    use indicatif::{MultiProgress, ProgressDrawTarget};
+
+    let mp = if events::is_jsonl() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+    let tracked = workloads(mf)
+        .into_iter()
+        .map(|w| {
+            let pb = mp.add(ProgressBar::new(w.minimum.into()));
+            (w, pb)
+        })
+        .collect::<Vec<_>>();
+    let cronbar = if mf.cronJobs.is_empty() {
+        None
+    } else {
+        Some(mp.add(ProgressBar::new(mf.cronJobs.len() as u64)))
+    };
+    // bars are drawn from a separate thread until all of them are finished
+    let drawing = tokio::task::spawn_blocking(move || mp.join());
+
+    let tracking = async {
+        let mut results = vec![];
+        if let Some(pb) = &cronbar {
+            results.push(verify_cronjobs(mf, kube, pb).await);
+        }
+        let rollouts = tracked.iter().map(|(w, pb)| track_workload(mf, w, kube, pb));
+        results.extend(join_all(rollouts).await);
+        results
+    };
+    // warnings are shown while tracking, but never fail the rollout
+    let watching = async {
+        if let Err(e) = watch_events(mf, kube, &tracked[0].1).await {
+            debug!("Stopped watching events for {}: {}", mf.name, e);
+        }
+        future::pending::<()>().await
+    };
+    let results = match future::select(Box::pin(tracking), Box::pin(watching)).await {
+        Either::Left((results, _)) => results,
+        Either::Right(_) => unreachable!("event watching never completes"),
+    };
+
+    for pb in tracked.iter().map(|(_, pb)| pb).chain(cronbar.iter()) {
+        if !pb.is_finished() {
+            pb.abandon();
+        }
+    }
+    if let Err(e) = drawing.await {
+        warn!("Failed to draw rollout progress for {}: {}", mf.name, e);
+    }
+    let mut ok = true;
+    for res in results {
+        ok &= res?;
+    }
+    Ok(ok)
+}
+
+/// Track the rollout of a single workload
+async fn track_workload(mf: &Manifest, w: &Workload, kube: &ShipKube, pb: &ProgressBar) -> Result<bool> {
     use futures_timer::Delay;
-    use indicatif::{ProgressBar, ProgressStyle};
-    let minimum = mf.min_replicas();
+    use indicatif::ProgressStyle;
+    let kube = kube.workload(&w.name);
     let waittime = mf.estimate_wait_time();
     let one_sec = std::time::Duration::from_millis(1000);
 
-    match rollout_status(mf, kube, &None).await {
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("> {bar:40.green/black} {prefix} {pos}/{len} ({elapsed}) {msg}"),
+    );
+    pb.set_draw_delta(1);
+    pb.set_prefix(&w.name);
+
+    match rollout_status(w, &kube, &None).await {
         Ok(rr) => {
             if rr.ok {
+                emit_progress(mf, w, &rr);
+                pb.set_position(rr.progress.into());
+                pb.finish_at_current_pos();
                 return Ok(true);
             } else {
                 debug!("Ignoring rollout failure right after upgrade")
@@ -431,21 +844,21 @@ pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
     };
 
     Delay::new(one_sec).await;
-    // TODO: Don't count until image has been pulled + handle unscheduleble - #96
+    // TODO: Don't count until image has been pulled - #96
 
     info!(
         "Waiting {}s for {:?} {} to rollout (not ready yet)",
-        waittime, mf.workload, mf.name
+        waittime, w.kind, w.name
     );
     let mut hash = None;
-    match mf.workload {
+    match w.kind {
         PrimaryWorkload::Deployment => {
             // Attempt to find an owning RS hash to track
             if let Some(rs) = kube.get_rs_from_deploy().await? {
                 if let Some(meta) = rs.metadata {
                     if let Some(labels) = meta.labels {
                         if let Some(h) = labels.get("pod-template-hash") {
-                            debug!("Tracking replicaset {} for {}", h, mf.name);
+                            debug!("Tracking replicaset {} for {}", h, w.name);
                             hash = Some(h.clone());
                         }
                     }
@@ -457,34 +870,25 @@ pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
             let sts = kube.get_statefulset().await?;
             let summary = StatefulSummary::try_from(sts)?;
             if let Some(ur) = summary.update_revision {
-                debug!("Tracking statefulset {:?} for {}", ur, mf.name);
+                debug!("Tracking statefulset {:?} for {}", ur, w.name);
                 hash = Some(ur);
             }
         }
     }
 
-    // TODO: create progress bar above this fn so we can use MultiProgressBar in cluster.rs
-    let pb = ProgressBar::new(minimum.into());
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("> {bar:40.green/black} {prefix} {pos}/{len} ({elapsed}) {msg}"),
-    );
-    pb.set_draw_delta(1);
     if let Some(h) = &hash {
-        match mf.workload {
+        match w.kind {
             PrimaryWorkload::Deployment => {
-                pb.set_prefix(&format!("{}-{}", mf.name, h));
+                pb.set_prefix(&format!("{}-{}", w.name, h));
             }
             PrimaryWorkload::Statefulset => {
                 pb.set_prefix(h); // statefulset hash already prefixes name
             }
         }
-    } else {
-        pb.set_prefix(&mf.name);
     }
 
     for i in 1..20 {
-        trace!("poll iteration {}", i);
+        trace!("poll iteration {} for {}", i, w.name);
         let mut waited = 0;
         // sleep until 1/20th of estimated upgrade time and poll for status
         while waited < waittime / 20 {
@@ -492,8 +896,9 @@ pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
             trace!("sleep 1s (waited {})", waited);
             Delay::new(one_sec).await;
         }
-        let rr = rollout_status(mf, kube, &hash).await?;
+        let rr = rollout_status(w, &kube, &hash).await?;
         debug!("RR: {:?}", rr);
+        emit_progress(mf, w, &rr);
         if let Some(msg) = rr.message {
             pb.set_message(&msg);
         }
@@ -503,6 +908,280 @@ pub async fn workload_rollout(mf: &Manifest, kube: &ShipKube) -> Result<bool> {
             pb.finish_at_current_pos();
             return Ok(true);
         }
+        // Stop early if the new pods cannot become ready without intervention
+        if let Some(h) = &hash {
+            if let Some(b) = rollout_blocker(w, &kube, h).await? {
+                pb.abandon_with_message(b.reason());
+                let logs = blocker_logs(&b, &kube).await;
+                events::emit(ApplyEvent::PodFailure {
+                    service: mf.name.clone(),
+                    workload: w.name.clone(),
+                    reason: b.reason().into(),
+                    message: b.to_string(),
+                    logs: logs.clone(),
+                });
+                let err = ErrorKind::RolloutBlocked(w.name.clone(), b.reason().into(), b.to_string(), logs);
+                return Err(err.into());
+            }
+        }
+    }
+    warn!("{} did not roll out within {}s", w.name, waittime);
+    Ok(false) // timeout
+}
+
+fn emit_progress(mf: &Manifest, w: &Workload, rr: &RolloutResult) {
+    events::emit(ApplyEvent::Progress {
+        service: mf.name.clone(),
+        workload: w.name.clone(),
+        progress: rr.progress,
+        expected: rr.expected,
+        message: rr.message.clone(),
+    });
+}
+
+/// Verify that the CronJobs of a service were accepted with the new template
+///
+/// The apiserver does not run anything until the schedule fires,
+/// so this only checks that each CronJob exists at the applied schedule and version.
+async fn verify_cronjobs(mf: &Manifest, kube: &ShipKube, pb: &ProgressBar) -> Result<bool> {
+    use indicatif::ProgressStyle;
+    pb.set_style(ProgressStyle::default_bar().template("> {bar:40.cyan/black} {prefix} {pos}/{len} {msg}"));
+    pb.set_prefix(&format!("{}-cronjobs", mf.name));
+    let version = mf.version.clone().unwrap_or_default();
+    for cj in &mf.cronJobs {
+        let name = &cj.container.name;
+        pb.set_message(name);
+        let live = match kube.get_cronjob(name).await? {
+            Some(l) => l,
+            None => {
+                pb.abandon_with_message("missing");
+                bail!("CronJob {} was not created", name);
+            }
+        };
+        let schedule = live.spec.as_ref().map(|s| s.schedule.clone()).unwrap_or_default();
+        if schedule != cj.schedule {
+            pb.abandon_with_message("outdated");
+            bail!("CronJob {} has schedule {}, not {}", name, schedule, cj.schedule);
+        }
+        let labels = live.metadata.and_then(|md| md.labels).unwrap_or_default();
+        if let Some(v) = labels.get("app.kubernetes.io/version") {
+            if v != &version {
+                pb.abandon_with_message("outdated");
+                bail!("CronJob {} is at version {}, not {}", name, v, version);
+            }
+        }
+        pb.inc(1);
+    }
+    events::emit(ApplyEvent::Progress {
+        service: mf.name.clone(),
+        workload: format!("{}-cronjobs", mf.name),
+        progress: mf.cronJobs.len() as u32,
+        expected: mf.cronJobs.len() as u32,
+        message: None,
+    });
+    pb.finish_with_message("accepted");
+    Ok(true)
+}
+
+// last log lines of a crashing container
+async fn blocker_logs(b: &RolloutBlocker, kube: &ShipKube) -> Option<String> {
+    if let RolloutBlocker::CrashLoop { pod, container, .. } = b {
+        match kube.get_previous_pod_logs(pod, container).await {
+            Ok(logs) => {
+                warn!("Last 30 log lines from {} in {}:", container, pod);
+                events::print_human(&logs);
+                return Some(logs);
+            }
+            Err(e) => warn!("Failed to get logs from {}: {}", pod, e),
+        }
+    }
+    None
+}
+
+/// Track a canary step until the canary Deployment has `replicas` ready pods
+///
+/// Polls for at most the estimated wait time of the main workload.
+pub async fn canary_rollout(mf: &Manifest, kube: &ShipKube, replicas: u32) -> Result<bool> {
+    use futures_timer::Delay;
+    use indicatif::ProgressStyle;
+    let waittime = mf.estimate_wait_time();
+    let one_sec = std::time::Duration::from_millis(1000);
+
+    info!(
+        "Waiting {}s for {}-canary to have {} ready",
+        waittime, mf.name, replicas
+    );
+    let pb = if events::is_jsonl() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(replicas.into())
+    };
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("> {bar:40.yellow/black} {prefix} {pos}/{len} ({elapsed}) {msg}"),
+    );
+    pb.set_draw_delta(1);
+    pb.set_prefix(&format!("{}-canary", mf.name));
+
+    for i in 1..20 {
+        trace!("canary poll iteration {}", i);
+        let mut waited = 0;
+        while waited < waittime / 20 {
+            waited += 1;
+            Delay::new(one_sec).await;
+        }
+        // status can be missing right after the canary is created
+        let d = match kube.get_canary_deploy().await.and_then(DeploySummary::try_from) {
+            Ok(d) => d,
+            Err(e) => {
+                debug!("Ignoring canary status failure: {}", e);
+                continue;
+            }
+        };
+        debug!("{}-canary: {:?}", mf.name, d);
+        let ready = std::cmp::max(0, d.ready).try_into().expect("ready >= 0");
+        events::emit(ApplyEvent::Progress {
+            service: mf.name.clone(),
+            workload: format!("{}-canary", mf.name),
+            progress: ready,
+            expected: replicas,
+            message: d.message.clone(),
+        });
+        if let Some(msg) = d.message {
+            pb.set_message(&msg);
+        }
+        pb.set_position(ready.into());
+        if d.ready >= replicas as i32 && d.unavailable <= 0 {
+            pb.finish_at_current_pos();
+            return Ok(true);
+        }
     }
     Ok(false) // timeout
 }
+
+/// Total number of container restarts across the canary pods
+///
+/// Used as a health gate while pausing between canary steps.
+pub async fn canary_restarts(kube: &ShipKube) -> Result<u32> {
+    let pods = kube.get_canary_pods().await?;
+    let mut restarts = 0;
+    for p in pods {
+        let ps = PodSummary::try_from(p)?;
+        restarts += std::cmp::max(0, ps.restarts) as u32;
+    }
+    Ok(restarts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pod_blocker, workloads, EventSummary, RolloutBlocker};
+    use chrono::{Duration, Utc};
+    use k8s_openapi::api::core::v1::{Event, Pod};
+    use serde_json::json;
+    use shipcat_definitions::Manifest;
+
+    fn pod(status: serde_json::Value) -> Pod {
+        serde_json::from_value(json!({ "metadata": { "name": "fake-ask-abc" }, "status": status })).unwrap()
+    }
+
+    fn waiting(reason: &str, restarts: i32) -> Pod {
+        pod(json!({
+            "containerStatuses": [{
+                "name": "fake-ask",
+                "image": "fake-ask:1.0.0",
+                "imageID": "",
+                "ready": false,
+                "restartCount": restarts,
+                "state": { "waiting": { "reason": reason, "message": "not found" } },
+            }]
+        }))
+    }
+
+    #[test]
+    fn pod_blockers() {
+        let now = Utc::now();
+        assert!(pod_blocker(&waiting("ContainerCreating", 0), now).is_none());
+        assert_eq!(
+            pod_blocker(&waiting("ImagePullBackOff", 0), now)
+                .unwrap()
+                .reason(),
+            "BadImage"
+        );
+        // a couple of restarts can be a slow dependency
+        assert!(pod_blocker(&waiting("CrashLoopBackOff", 1), now).is_none());
+        assert_eq!(
+            pod_blocker(&waiting("CrashLoopBackOff", 4), now),
+            Some(RolloutBlocker::CrashLoop {
+                pod: "fake-ask-abc".into(),
+                container: "fake-ask".into(),
+                restarts: 4,
+            })
+        );
+
+        let pending = pod(json!({
+            "conditions": [{
+                "type": "PodScheduled",
+                "status": "False",
+                "reason": "Unschedulable",
+                "message": "0/3 nodes are available: 3 Insufficient memory.",
+                "lastTransitionTime": now.to_rfc3339(),
+            }]
+        }));
+        // autoscaler may still add a node
+        assert!(pod_blocker(&pending, now).is_none());
+        let blocker = pod_blocker(&pending, now + Duration::minutes(5)).unwrap();
+        assert_eq!(
+            blocker.to_string(),
+            "fake-ask-abc cannot be scheduled: 0/3 nodes are available: 3 Insufficient memory."
+        );
+    }
+
+    #[test]
+    fn worker_workloads() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.replicaCount = Some(2);
+        mf.workers = vec![serde_yaml::from_str(
+            "name: fake-ask-worker\nreplicaCount: 1\nautoScaling:\n  minReplicas: 3\n  maxReplicas: 5\n  metrics: []\n",
+        )
+        .unwrap()];
+        let ws = workloads(&mf);
+        assert_eq!(ws.len(), 2);
+        assert_eq!((ws[0].name.as_str(), ws[0].minimum), ("fake-ask", 2));
+        assert_eq!((ws[1].name.as_str(), ws[1].minimum), ("fake-ask-worker", 3));
+    }
+
+    #[test]
+    fn event_filtering() {
+        let event = |name: &str| -> EventSummary {
+            let ev: Event = serde_json::from_value(json!({
+                "metadata": { "name": format!("{}.15f", name) },
+                "involvedObject": { "kind": "Pod", "name": name },
+                "type": "Warning",
+                "reason": "FailedScheduling",
+                "message": "0/3 nodes are available: 3 Insufficient cpu. ",
+                "count": 4,
+            }))
+            .unwrap();
+            ev.into()
+        };
+        let names = vec!["fake-ask".to_string(), "worker".to_string()];
+        let pod = event("fake-ask-5d8f9-x2x4z");
+        assert!(pod.is_warning());
+        assert_eq!(pod.count, 4);
+        assert_eq!(pod.message, "0/3 nodes are available: 3 Insufficient cpu.");
+        assert!(pod.concerns(&names));
+        assert!(event("worker").concerns(&names));
+        assert!(!event("fake-asking-5d8f9-x2x4z").concerns(&names));
+        assert!(event("fake-ask-7c9f6d8b5").concerns(&names));
+        assert!(event("fake-ask-canary-7c9f6d8b5-x2x4z").concerns(&names));
+        assert!(event("fake-ask-0").concerns(&names));
+        // other services sharing the name as a prefix
+        let api = vec!["api".to_string()];
+        assert!(event("api-5d8f9-x2x4z").concerns(&api));
+        assert!(!event("api-gateway").concerns(&api));
+        assert!(!event("api-gateway-5d8f9-x2x4z").concerns(&api));
+        assert!(!event("api-gateway-0").concerns(&api));
+        // same failure on another pod is a duplicate
+        assert_eq!(pod.key(), event("fake-ask-5d8f9-b7n2q").key());
+    }
+}
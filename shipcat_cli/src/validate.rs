@@ -1,4 +1,12 @@
-use super::{Config, Manifest, Region, Result};
+use serde_json::json;
+use shipcat_definitions::{
+    policy::{self, Policy, Severity},
+    Result as ManifestResult,
+};
+use shipcat_filebacked::{Location, SourceMap};
+use std::{collections::BTreeSet, fmt, path::Path, str::FromStr};
+
+use super::{Config, Error, Manifest, Region, Result};
 use crate::{error_chain::ChainedError, git};
 use futures::stream::{self, StreamExt};
 
@@ -8,6 +16,7 @@ async fn verify_manifest(svc: String, conf: &Config, reg: &Region) -> Result<Man
         .stub(&reg)
         .await?;
     mf.verify(&conf, &reg)?;
+    mf.verify_policies(conf, reg)?;
     Ok(mf)
 }
 
@@ -104,30 +113,203 @@ pub async fn all_manifests() -> Result<()> {
     Ok(())
 }
 
+/// How to print the results of validating manifests
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationFormat {
+    /// `file:line:column: message` lines
+    Human,
+    /// A SARIF log for code scanning annotations
+    Sarif,
+}
+
+impl FromStr for ValidationFormat {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "human" => Ok(Self::Human),
+            "sarif" => Ok(Self::Sarif),
+            _ => bail!("Validation format must be human or sarif"),
+        }
+    }
+}
+
+/// A validation failure, located in the manifest files when possible
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub service: String,
+    /// Id of the violated policy, or `manifest` for the built-in validation
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(loc) => write!(f, "{}: {}", loc, self.message),
+            None => write!(f, "{}: {}", self.service, self.message),
+        }
+    }
+}
+
+impl Finding {
+    fn manifest(svc: &str, err: &shipcat_definitions::Error, sources: &SourceMap) -> Self {
+        let (location, message) = sources.blame(err);
+        Finding {
+            service: svc.to_string(),
+            rule: "manifest".into(),
+            severity: Severity::Error,
+            message,
+            location,
+        }
+    }
+}
+
+async fn load_verified(svc: &str, conf: &Config, reg: &Region, secrets: bool) -> ManifestResult<Manifest> {
+    let mf = shipcat_filebacked::load_manifest(svc, conf, reg).await?;
+    let mf = if secrets {
+        mf.complete(reg).await?
+    } else {
+        mf.stub(reg).await?
+    };
+    mf.verify(conf, reg)?;
+    Ok(mf)
+}
+
+/// Validate a service, and evaluate the policies in shipcat.conf against it
+async fn findings(svc: &str, conf: &Config, reg: &Region, secrets: bool) -> Vec<Finding> {
+    // unparseable files are reported when loading the manifest
+    let sources = shipcat_filebacked::load_sources(svc, reg)
+        .await
+        .unwrap_or_default();
+    let mf = match load_verified(svc, conf, reg, secrets).await {
+        Ok(mf) => mf,
+        Err(e) => return vec![Finding::manifest(svc, &e, &sources)],
+    };
+    if mf.external {
+        return vec![];
+    }
+    match policy::evaluate(&conf.policies, &mf, reg) {
+        Err(e) => vec![Finding::manifest(svc, &e, &sources)],
+        Ok(violations) => violations
+            .into_iter()
+            .map(|v| Finding {
+                service: svc.to_string(),
+                location: sources.locate(&v.field),
+                rule: v.policy,
+                severity: v.severity,
+                message: v.message,
+            })
+            .collect(),
+    }
+}
+
+/// SARIF 2.1.0 log of validation findings
+///
+/// Findings without a known location are reported against the service's `manifest.yml`.
+pub fn sarif(findings: &[Finding], policies: &[Policy]) -> Result<String> {
+    let rules = findings
+        .iter()
+        .map(|f| f.rule.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|id| {
+            let desc = match policies.iter().find(|p| p.id == id) {
+                Some(p) => p
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| format!("Policy {} in shipcat.conf", id)),
+                None => "Manifest validation".to_string(),
+            };
+            json!({ "id": id, "shortDescription": { "text": desc } })
+        })
+        .collect::<Vec<_>>();
+    let results = findings
+        .iter()
+        .map(|f| {
+            let (uri, line, column) = match &f.location {
+                Some(loc) => (loc.file.clone(), loc.line, loc.column),
+                None => (Path::new("services").join(&f.service).join("manifest.yml"), 1, 1),
+            };
+            json!({
+                "ruleId": f.rule,
+                "level": match f.severity {
+                    Severity::Error => "error",
+                    Severity::Warn => "warning",
+                },
+                "message": { "text": f.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": uri.display().to_string() },
+                        "region": { "startLine": line, "startColumn": column },
+                    }
+                }],
+            })
+        })
+        .collect::<Vec<_>>();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "shipcat",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/babylonhealth/shipcat",
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    });
+    Ok(serde_json::to_string_pretty(&log)?)
+}
+
 /// Validate the manifest of a service in the services directory
 ///
 /// This will populate the manifest for all supported environments,
 /// and `verify` their parameters.
 /// Optionally, it will also verify that all secrets are found in the corresponding
 /// vault locations serverside (which require vault credentials).
-pub async fn manifest(services: Vec<String>, conf: &Config, reg: &Region, secrets: bool) -> Result<()> {
+///
+/// Failures are located in the manifest files where possible,
+/// and printed as lines or a SARIF log depending on the format.
+pub async fn manifest(
+    services: Vec<String>,
+    conf: &Config,
+    reg: &Region,
+    secrets: bool,
+    fmt: ValidationFormat,
+) -> Result<()> {
     conf.verify()?; // this should work even with a limited config!
+    let mut all = vec![];
     for svc in services {
         debug!("validating {} for {}", svc, reg.name);
-        let mf = if secrets {
-            shipcat_filebacked::load_manifest(&svc, conf, reg)
-                .await?
-                .complete(reg)
-                .await?
-        } else {
-            shipcat_filebacked::load_manifest(&svc, conf, reg)
-                .await?
-                .stub(reg)
-                .await?
-        };
-        mf.verify(conf, reg)?;
+        all.extend(findings(&svc, conf, reg, secrets).await);
         debug!("validated {} for {}", svc, reg.name);
     }
+
+    match fmt {
+        ValidationFormat::Sarif => println!("{}", sarif(&all, &conf.policies)?),
+        ValidationFormat::Human => {
+            for f in &all {
+                match f.severity {
+                    Severity::Error => error!("{}", f),
+                    Severity::Warn => warn!("{}", f),
+                }
+            }
+        }
+    }
+    let failed = all
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .map(|f| &f.service)
+        .collect::<BTreeSet<_>>();
+    if !failed.is_empty() {
+        bail!("Invalid shipcat data in {} manifests", failed.len());
+    }
     Ok(())
 }
 
@@ -181,7 +363,7 @@ pub async fn secret_presence_git(conf: &Config, regions: Vec<String>) -> Result<
         reg.verify_secrets_exist().await?; // verify secrets for the region
 
         // Try to find services changed by git:
-        let svcs = match git_diff_changes() {
+        let svcs = match git::changed_services() {
             Ok(svcs) => svcs,
             // if that for some reason fails, then do all services for that region
             Err(e) => {
@@ -217,22 +399,57 @@ pub fn config(conf: Config) -> Result<()> {
     Ok(())
 }
 
-// Dumb git diff helper that matches normal service files:
-//
-// Effectively checks:
-// git diff --name-only $(git merge-base origin/master HEAD) | grep ./services/{svc}/*
-fn git_diff_changes() -> Result<Vec<String>> {
-    use regex::Regex;
-    let merge_base = git::merge_base()?;
-    let diff_output = git::diff_filenames(&merge_base)?;
-    let svc_re = Regex::new(r"^services/(?P<svc>[0-9a-z\-]{1,50})/").unwrap();
-    let mut res = vec![];
-    for l in diff_output.lines() {
-        if let Some(caps) = svc_re.captures(l) {
-            if let Some(svc) = caps.name("svc") {
-                res.push(svc.as_str().to_string());
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::{sarif, Finding};
+    use shipcat_definitions::policy::Severity;
+    use shipcat_filebacked::Location;
+    use std::path::PathBuf;
+
+    #[test]
+    fn validation_sarif() {
+        let findings = vec![
+            Finding {
+                service: "fake-ask".into(),
+                rule: "manifest".into(),
+                severity: Severity::Error,
+                message: "Need replicaCount to be at least 1".into(),
+                location: Some(Location {
+                    file: PathBuf::from("services/fake-ask/dev-uk.yml"),
+                    line: 14,
+                    column: 5,
+                }),
+            },
+            Finding {
+                service: "fake-storage".into(),
+                rule: "kong-auth".into(),
+                severity: Severity::Warn,
+                message: "kongApis[0].authorization must be set".into(),
+                location: None,
+            },
+        ];
+        assert_eq!(
+            findings[0].to_string(),
+            "services/fake-ask/dev-uk.yml:14:5: Need replicaCount to be at least 1"
+        );
+        assert_eq!(
+            findings[1].to_string(),
+            "fake-storage: kongApis[0].authorization must be set"
+        );
+
+        let log: serde_json::Value = serde_json::from_str(&sarif(&findings, &[]).unwrap()).unwrap();
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "kong-auth");
+        let res = &run["results"];
+        assert_eq!(res[0]["level"], "error");
+        let loc = &res[0]["locations"][0]["physicalLocation"];
+        assert_eq!(loc["artifactLocation"]["uri"], "services/fake-ask/dev-uk.yml");
+        assert_eq!(loc["region"]["startLine"], 14);
+        assert_eq!(res[1]["level"], "warning");
+        assert_eq!(
+            res[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "services/fake-storage/manifest.yml"
+        );
     }
-    Ok(res)
 }
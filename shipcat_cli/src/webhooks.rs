@@ -1,5 +1,10 @@
 use super::{Config, Region, Webhook};
-use crate::{apply::UpgradeInfo, audit, slack, Result};
+use crate::{
+    apply::UpgradeInfo,
+    audit,
+    events::{self, ApplyEvent},
+    slack, Result,
+};
 
 /// The different states an upgrade can be in
 #[derive(Serialize, PartialEq, Clone)]
@@ -15,6 +20,14 @@ pub enum UpgradeState {
     Completed,
     /// Errors
     Failed,
+    /// Rollback to the last successful version has started after a failure
+    RollbackStarted,
+    /// Rollback completed without errors
+    RollbackCompleted,
+    /// Rollback had errors
+    RollbackFailed,
+    /// A deploy hook failed (before the upgrade started, or after it rolled out)
+    HookFailed,
 }
 
 pub fn ensure_requirements(reg: &Region) -> Result<()> {
@@ -43,16 +56,24 @@ pub async fn reconcile_event(us: UpgradeState, reg: &Region) {
 /// Throw events to configured webhooks
 pub async fn apply_event(us: UpgradeState, info: &UpgradeInfo, reg: &Region, conf: &Config) {
     debug!("Apply event: {:?}", info);
+    events::emit(ApplyEvent::State {
+        service: info.name.clone(),
+        state: us.clone(),
+    });
     // Webhooks defined in shipcat.conf for the region:
     for wh in &reg.webhooks {
         if let Ok(whc) = wh.get_configuration() {
             let res = match wh {
                 Webhook::Audit(h) => {
                     match us {
-                        UpgradeState::Started | UpgradeState::Completed | UpgradeState::Failed => {
-                            audit::apply(&us, &info, &h, whc).await
-                        }
-                        _ => Ok(()), // audit only sends Started / Failed / Completed
+                        UpgradeState::Started
+                        | UpgradeState::Completed
+                        | UpgradeState::Failed
+                        | UpgradeState::RollbackStarted
+                        | UpgradeState::RollbackCompleted
+                        | UpgradeState::RollbackFailed
+                        | UpgradeState::HookFailed => audit::apply(&us, &info, &h, whc).await,
+                        _ => Ok(()), // audit only sends Started / Failed / Completed (and rollbacks)
                     }
                 }
             };
@@ -68,6 +89,18 @@ pub async fn apply_event(us: UpgradeState, info: &UpgradeInfo, reg: &Region, con
             "danger",
             format!("failed to apply `{}` in `{}`", info.name, info.region),
         ),
+        UpgradeState::RollbackCompleted => (
+            "warning",
+            format!("rolled back `{}` in `{}`", info.name, info.region),
+        ),
+        UpgradeState::RollbackFailed => (
+            "danger",
+            format!("failed to roll back `{}` in `{}`", info.name, info.region),
+        ),
+        UpgradeState::HookFailed => (
+            "danger",
+            format!("deploy hook failed for `{}` in `{}`", info.name, info.region),
+        ),
         _ => (
             "good",
             format!(
@@ -77,11 +110,15 @@ pub async fn apply_event(us: UpgradeState, info: &UpgradeInfo, reg: &Region, con
         ),
     };
     match us {
-        UpgradeState::Completed | UpgradeState::Failed => {
+        UpgradeState::Completed
+        | UpgradeState::Failed
+        | UpgradeState::RollbackCompleted
+        | UpgradeState::RollbackFailed
+        | UpgradeState::HookFailed => {
             let _ = slack::send(
                 slack::Message {
                     text,
-                    code: info.diff.clone(),
+                    diff: info.diff.clone(),
                     color: Some(String::from(color)),
                     version: Some(info.version.clone()),
                     mode: info.slackMode.clone(),
@@ -120,7 +157,7 @@ pub async fn delete_event(us: &UpgradeState, info: &UpgradeInfo, reg: &Region, c
             let _ = slack::send(
                 slack::Message {
                     text,
-                    code: info.diff.clone(),
+                    diff: info.diff.clone(),
                     color: Some(String::from(color)),
                     version: Some(info.version.clone()),
                     mode: info.slackMode.clone(),
@@ -1,6 +1,6 @@
 mod common;
 use crate::common::setup;
-use shipcat::graph::{generate, nodeidx_from_name};
+use shipcat::graph::{full_graph, generate, nodeidx_from_name, waves};
 use shipcat_definitions::{Config, ConfigState};
 
 #[tokio::test]
@@ -20,3 +20,22 @@ async fn graph_generate() {
     println!("edge: {:?}", edge);
     assert_eq!(edge.intent, Some("testing graph module".into()));
 }
+
+#[tokio::test]
+async fn graph_waves() {
+    setup();
+    let (conf, reg) = Config::new(ConfigState::Base, "dev-uk").await.unwrap();
+    let graph = full_graph(&conf, &reg).await.unwrap();
+    let svcs = vec!["fake-ask".to_string(), "fake-storage".to_string()];
+    let res = waves(&graph, &svcs).unwrap();
+    // fake-ask depends on fake-storage
+    assert_eq!(res, vec![vec!["fake-storage".to_string()], vec!["fake-ask".to_string()]]);
+
+    let single = waves(&graph, &["fake-ask".to_string()]).unwrap();
+    assert_eq!(single, vec![vec!["fake-ask".to_string()]]);
+
+    // duplicates are only applied once
+    let dupes = vec!["fake-ask".to_string(), "fake-ask".to_string(), "fake-storage".to_string()];
+    let res = waves(&graph, &dupes).unwrap();
+    assert_eq!(res, vec![vec!["fake-storage".to_string()], vec!["fake-ask".to_string()]]);
+}
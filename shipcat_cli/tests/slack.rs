@@ -1,8 +1,20 @@
 mod common;
 use crate::common::setup;
-use shipcat::slack::{env_channel, send, send_dumb, DumbMessage, Message};
+use serde_json::json;
+use shipcat::{
+    diff::Changes,
+    slack::{env_channel, send, send_dumb, DumbMessage, Message},
+};
 use shipcat_definitions::{structs::NotificationMode, Config, ConfigState};
 
+fn pod(image: &str, value: Option<&str>) -> serde_json::Value {
+    json!({
+        "kind": "Pod",
+        "metadata": { "name": "slack" },
+        "spec": { "image": image, "value": value },
+    })
+}
+
 // integration temporarily disabled
 #[tokio::test]
 #[ignore]
@@ -29,10 +41,9 @@ async fn slack_test() {
                 version: mf.version.clone(),
                 mode: NotificationMode::default(),
                 metadata: mf.base.metadata.clone(),
-                code: Some(format!(
-                    "Pod changed:
--  image: \"blah:e7c1e5dd5de74b2b5da5eef76eb5bf12bdc2ac19\"
-+  image: \"blah:d4f01f5143643e75d9cc2d5e3221e82a9e1c12e5\""
+                diff: Some(Changes::between(
+                    &[pod("blah:e7c1e5dd5de74b2b5da5eef76eb5bf12bdc2ac19", None)],
+                    &[pod("blah:d4f01f5143643e75d9cc2d5e3221e82a9e1c12e5", None)],
                 )),
             },
             &conf.owners,
@@ -48,11 +59,9 @@ async fn slack_test() {
                 mode: NotificationMode::default(),
                 metadata: mf.base.metadata,
                 version: mf.version.clone(),
-                code: Some(format!(
-                    "Pod changed:
--  value: \"somedeletedvar\"
--  image: \"blah:abc12345678\"
-+  image: \"blah:abc23456789\""
+                diff: Some(Changes::between(
+                    &[pod("blah:abc12345678", Some("somedeletedvar"))],
+                    &[pod("blah:abc23456789", None)],
                 )),
             },
             &conf.owners,
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use kube_derive::CustomResource;
+use schemars::JsonSchema;
 use semver::Version;
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -9,12 +10,13 @@ use crate::teams;
 
 #[allow(unused_imports)] use super::{Error, Result};
 use crate::{
+    policy::Policy,
     region::{Environment, Region},
     states::ConfigState,
 };
 
 /// Kubernetes cluster information
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct Cluster {
     /// Name of the cluster
@@ -28,7 +30,7 @@ pub struct Cluster {
     pub regions: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct Location {
     /// Location name
@@ -41,14 +43,14 @@ pub struct Location {
     pub local_region: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct GithubParameters {
     /// Organisation name
     pub organisation: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct SlackParameters {
     /// Team name (T...)
@@ -58,7 +60,7 @@ pub struct SlackParameters {
 // ----------------------------------------------------------------------------------
 
 /// Main manifest, serializable from shipcat.conf
-#[derive(CustomResource, Serialize, Deserialize, Debug, Clone)]
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[kube(
     group = "babylontech.co.uk",
     kind = "ShipcatConfig",
@@ -71,6 +73,7 @@ pub struct Config {
     /// Global defaults for the manifests (used by shipcat_filebacked only)
     #[serde(default)]
     #[cfg(feature = "filesystem")]
+    #[schemars(with = "serde_json::Value")]
     pub defaults: serde_yaml::Value,
 
     /// Cluster definitions
@@ -104,6 +107,7 @@ pub struct Config {
     pub allowedCustomMetadata: BTreeSet<String>,
 
     /// Shipcat version pins
+    #[schemars(with = "BTreeMap<Environment, String>")]
     pub versions: BTreeMap<Environment, Version>,
 
     /// Owners of services, squads, tribes
@@ -112,6 +116,10 @@ pub struct Config {
     #[serde(default)]
     pub owners: teams::Owners,
 
+    /// Rules that manifests must satisfy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<Policy>,
+
     // Internal state of the config
     #[serde(default, skip_serializing, skip_deserializing)]
     state: ConfigState,
@@ -158,6 +166,9 @@ impl Config {
                 bail!("Region {} served by missing cluster '{}'", r.name, r.cluster);
             }
             r.vault.verify(&r.name)?;
+            for f in &r.freezes {
+                f.verify()?;
+            }
             for v in r.base_urls.values() {
                 if v.ends_with('/') {
                     bail!("A base_url must not end with a slash");
@@ -171,6 +182,14 @@ impl Config {
                 used_kong_urls.push(kong.config_url.clone());
             }
         }
+
+        let mut policy_ids = BTreeSet::new();
+        for p in &self.policies {
+            p.verify()?;
+            if !policy_ids.insert(&p.id) {
+                bail!("Policy ids must be unique, found {} twice", p.id);
+            }
+        }
         Ok(())
     }
 
@@ -1,16 +1,30 @@
 use super::{config::ShipcatConfig, manifest::ShipcatManifest, Manifest};
-use crate::{config::Config, states::ManifestState};
+use crate::{config::Config, schema, states::ManifestState};
 
 // We are < 1.17 so use v1beta1
 use apiexts::CustomResourceDefinition;
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1beta1 as apiexts;
 
 pub fn gen_all_crds() -> Vec<CustomResourceDefinition> {
-    let shipcatManifest = ShipcatManifest::crd();
+    let mut shipcatManifest = ShipcatManifest::crd();
+    shipcatManifest.spec.validation = Some(manifest_validation());
     let shipcatConfig = ShipcatConfig::crd();
     vec![shipcatConfig, shipcatManifest]
 }
 
+/// Validate the spec of ShipcatManifests against the `Manifest` schema
+fn manifest_validation() -> apiexts::CustomResourceValidation {
+    let spec = schema::openapi_schema::<Manifest>().expect("manifest schema is valid openapi");
+    let root = apiexts::JSONSchemaProps {
+        type_: Some("object".into()),
+        properties: Some(btreemap! { "spec".to_string() => spec }),
+        ..apiexts::JSONSchemaProps::default()
+    };
+    apiexts::CustomResourceValidation {
+        open_api_v3_schema: Some(root),
+    }
+}
+
 impl From<Manifest> for ShipcatManifest {
     fn from(mf: Manifest) -> ShipcatManifest {
         // we assume the manifest has all it needs to fill in the pieces
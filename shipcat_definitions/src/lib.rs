@@ -72,6 +72,10 @@ error_chain! {
             description("failed to build manifest")
             display("failed to build manifest for {} in {}", &service_name, &region_name)
         }
+        InvalidField(field: String) {
+            description("invalid manifest field")
+            display("invalid {}", &field)
+        }
     }
 }
 
@@ -91,9 +95,16 @@ pub use crate::manifest::{Manifest, ShipcatManifest};
 pub mod base;
 pub use crate::base::BaseManifest;
 
+/// Declarative rules for manifests from shipcat.conf
+pub mod policy;
+pub use crate::policy::Policy;
+
 /// Definitions of teams/squads/tribes (via ewok or otherwise)
 pub mod teams;
 
+/// JSON Schemas of the manifest and config types
+pub mod schema;
+
 /// Crd wrappers
 mod crds;
 pub use crate::crds::gen_all_crds;
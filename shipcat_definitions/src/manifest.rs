@@ -1,9 +1,10 @@
 use crate::vault::Vault;
 use kube_derive::CustomResource;
 use regex::Regex;
+use schemars::JsonSchema;
 use std::collections::{BTreeMap, BTreeSet};
 
-use super::Result;
+use super::{Error, ErrorKind, Result, ResultExt};
 use crate::{
     config::Config,
     region::{Region, VaultConfig},
@@ -20,12 +21,13 @@ use super::structs::{
     tolerations::Tolerations,
     volume::{Volume, VolumeMount},
     ConfigMap, Container, CronJob, Dependency, DestinationRule, EnvVars, EventStream, Gate, HealthCheck,
-    HostAlias, Kafka, KafkaResources, Kong, LifeCycle, Metadata, NotificationMode, PersistentVolume, Port,
-    Probe, PrometheusAlert, Rbac, ResourceRequirements, RollingUpdate, SecurityContext, VaultOpts, Worker,
+    Hooks, HostAlias, Kafka, KafkaResources, Kong, LifeCycle, Metadata, NotificationMode, PersistentVolume,
+    Port, Probe, PrometheusAlert, Rbac, ResourceRequirements, RollingUpdate, RolloutStrategy,
+    SecurityContext, VaultOpts, Worker,
 };
 
 /// Main manifest, serializable from manifest.yml or the shipcat CRD.
-#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 #[kube(
     group = "babylontech.co.uk",
     kind = "ShipcatManifest",
@@ -496,6 +498,26 @@ pub struct Manifest {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub rollingUpdate: Option<RollingUpdate>,
 
+    /// Rollout strategy for new versions
+    ///
+    /// Allows progressive delivery through a canary `Deployment` created next to the
+    /// main `Deployment`. Each step scales the canary to a percentage of `replicaCount`,
+    /// waits for it to become ready, then pauses while checking for container restarts.
+    /// The main `Deployment` is upgraded after the last step.
+    ///
+    /// ```yaml
+    /// rolloutStrategy:
+    ///   canary:
+    ///     steps:
+    ///     - weight: 10
+    ///       pause: 5m
+    ///     - weight: 50
+    ///       pause: 10m
+    ///     - weight: 100
+    /// ```
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rolloutStrategy: Option<RolloutStrategy>,
+
     /// `HorizontalPodAutoScaler` parameters for kubernetes
     ///
     /// Passed all parameters directly onto the `spec` of a kube HPA.
@@ -615,6 +637,25 @@ pub struct Manifest {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cronJobs: Vec<CronJob>,
 
+    /// Containers to run as kubernetes `Job` objects around upgrades
+    ///
+    /// `preDeploy` hooks must succeed before the workloads are upgraded,
+    /// and `postDeploy` hooks run once the upgrade has rolled out.
+    /// Hooks use the image and version of the service unless they set their own.
+    ///
+    /// ```yaml
+    /// hooks:
+    ///   preDeploy:
+    ///   - name: migrate
+    ///     command: ["bundle", "exec", "rake", "db:migrate"]
+    ///     timeout: 300
+    ///   postDeploy:
+    ///   - name: smoke-test
+    ///     command: ["./smoke-test.sh"]
+    /// ```
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+
     /// Annotations to set on `Service` objects
     ///
     /// Useful for `LoadBalancer` type `Service` objects.
@@ -923,8 +964,9 @@ impl Manifest {
     pub fn verify_destination_rules(&self, region: &Region) -> Result<()> {
         if let Some(ref _destinationRules) = &self.destinationRules {
             if let Some(ref destinationRuleHostRegex) = &region.destinationRuleHostRegex {
-                for dr in _destinationRules {
-                    dr.verify(destinationRuleHostRegex)?;
+                for (i, dr) in _destinationRules.iter().enumerate() {
+                    dr.verify(destinationRuleHostRegex)
+                        .chain_err(|| invalid(&format!("destinationRules[{}]", i)))?;
                 }
             } else {
                 bail!("Cannot use `destinationRules` in a region without a `destinationRuleHostRegex`")
@@ -952,11 +994,12 @@ impl Manifest {
 
         // TODO: remove?
         if let Some(ref dh) = self.dataHandling {
-            dh.verify()?
+            dh.verify().chain_err(|| invalid("dataHandling"))?
         }
 
         if let Some(ref md) = self.metadata {
-            md.verify(&conf.owners, &conf.allowedCustomMetadata)?;
+            md.verify(&conf.owners, &conf.allowedCustomMetadata)
+                .chain_err(|| invalid("metadata"))?;
         } else {
             bail!("Missing metadata for {}", self.name);
         }
@@ -967,7 +1010,10 @@ impl Manifest {
         }
 
         if let Some(v) = &self.version {
-            region.versioningScheme.verify(v)?;
+            region
+                .versioningScheme
+                .verify(v)
+                .chain_err(|| invalid("version"))?;
         }
 
         // TODO [DIP-499]: Separate gate/kong params + adjust the checks
@@ -976,61 +1022,87 @@ impl Manifest {
                 bail!("Can't have a `gate` configuration without a `kong` one");
             }
             if g.public != self.publiclyAccessible {
-                bail!("[Migration plan] `publiclyAccessible` and `gate.public` must be equal");
+                bail!(invalid_value(
+                    "gate.public",
+                    "[Migration plan] `publiclyAccessible` and `gate.public` must be equal"
+                ));
             }
         }
 
         // run the `Verify` trait on all imported structs
         // mandatory structs first
         if let Some(ref r) = self.resources {
-            r.verify()?;
+            r.verify().chain_err(|| invalid("resources"))?;
         } else {
             bail!("Resources is mandatory");
         }
 
         // optional/vectorised entries
-        for d in &self.dependencies {
-            d.verify()?;
+        for (i, d) in self.dependencies.iter().enumerate() {
+            d.verify()
+                .chain_err(|| invalid(&format!("dependencies[{}]", i)))?;
         }
 
-        for ha in &self.hostAliases {
-            ha.verify()?;
+        for (i, ha) in self.hostAliases.iter().enumerate() {
+            ha.verify()
+                .chain_err(|| invalid(&format!("hostAliases[{}]", i)))?;
         }
-        for tl in &self.tolerations {
-            tl.verify()?;
+        for (i, tl) in self.tolerations.iter().enumerate() {
+            tl.verify()
+                .chain_err(|| invalid(&format!("tolerations[{}]", i)))?;
         }
-        for r in &self.rbac {
-            r.verify()?;
+        for (i, r) in self.rbac.iter().enumerate() {
+            r.verify().chain_err(|| invalid(&format!("rbac[{}]", i)))?;
         }
-        for pv in &self.persistentVolumes {
-            pv.verify()?;
+        for (i, pv) in self.persistentVolumes.iter().enumerate() {
+            pv.verify()
+                .chain_err(|| invalid(&format!("persistentVolumes[{}]", i)))?;
         }
         if let Some(ref cmap) = self.configs {
-            cmap.verify()?;
+            cmap.verify().chain_err(|| invalid("configs"))?;
         }
         for k in self.labels.keys() {
             if !conf.allowedLabels.contains(k) {
-                bail!("Service: {} using label {} not defined in config", self.name, k)
+                bail!(invalid_value(
+                    &format!("labels.{}", k),
+                    format!("Service: {} using label {} not defined in config", self.name, k)
+                ))
             }
         }
-        for es in &self.eventStreams {
-            es.verify()?;
+        for (i, es) in self.eventStreams.iter().enumerate() {
+            es.verify()
+                .chain_err(|| invalid(&format!("eventStreams[{}]", i)))?;
         }
         if let Some(kr) = &self.kafkaResources {
-            kr.verify()?;
+            kr.verify().chain_err(|| invalid("kafkaResources"))?;
         }
-        for pa in &self.prometheusAlerts {
-            pa.verify(&self.name)?;
+        for (i, pa) in self.prometheusAlerts.iter().enumerate() {
+            pa.verify(&self.name)
+                .chain_err(|| invalid(&format!("prometheusAlerts[{}]", i)))?;
         }
         // misc minor properties
         if self.replicaCount.unwrap() == 0 {
-            bail!("Need replicaCount to be at least 1");
+            bail!(invalid_value(
+                "replicaCount",
+                "Need replicaCount to be at least 1"
+            ));
         }
         if let Some(ref ru) = &self.rollingUpdate {
-            ru.verify(self.replicaCount.unwrap())?;
+            ru.verify(self.replicaCount.unwrap())
+                .chain_err(|| invalid("rollingUpdate"))?;
+        }
+        self.hooks.verify().chain_err(|| invalid("hooks"))?;
+        if let Some(ref rs) = &self.rolloutStrategy {
+            rs.verify().chain_err(|| invalid("rolloutStrategy"))?;
+            if let (Some(_), PrimaryWorkload::Statefulset) = (rs.canary(), &self.workload) {
+                bail!(invalid_value(
+                    "rolloutStrategy",
+                    "Canary rolloutStrategy requires a Deployment workload"
+                ));
+            }
         }
 
-        self.env.verify()?;
+        self.env.verify().chain_err(|| invalid("env"))?;
 
         // internal errors - implicits set these!
         if self.image.is_none() {
@@ -1086,6 +1158,12 @@ impl Manifest {
         for c in &mut self.cronJobs {
             envs.push(&mut c.container.env);
         }
+        for h in &mut self.hooks.preDeploy {
+            envs.push(&mut h.container.env);
+        }
+        for h in &mut self.hooks.postDeploy {
+            envs.push(&mut h.container.env);
+        }
         for i in &mut self.initContainers {
             envs.push(&mut i.env);
         }
@@ -1156,6 +1234,38 @@ impl Manifest {
         secrets
     }
 
+    /// Salted checksum of all resolved secrets and secretFiles
+    ///
+    /// Stored in the shipcatmanifest status so that rotated secrets trigger an upgrade.
+    /// A random salt is stored with the checksum (as `salt:sha256`), so readers of the status
+    /// cannot check guesses against a precomputed table, or spot services sharing secrets.
+    /// Only meaningful on a completed manifest.
+    pub fn secret_checksum(&self) -> String {
+        let salt = uuid::Uuid::new_v4().to_simple().to_string();
+        self.salted_secret_checksum(&salt)
+    }
+
+    /// Whether a `secret_checksum` was made from the same secrets
+    ///
+    /// Returns None for checksums without a salt (from older shipcat versions).
+    pub fn secrets_match(&self, checksum: &str) -> Option<bool> {
+        let salt = &checksum[..checksum.find(':')?];
+        Some(self.salted_secret_checksum(salt) == checksum)
+    }
+
+    fn salted_secret_checksum(&self, salt: &str) -> String {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        hasher.input(format!("{}\n", salt).as_bytes());
+        // BTreeMaps are sorted, and keys are prefixed to separate the two maps
+        for (prefix, secrets) in &[("env", &self.secrets), ("file", &self.secretFiles)] {
+            for (k, v) in secrets.iter() {
+                hasher.input(format!("{}:{}={}\n", prefix, k, base64::encode(v)).as_bytes());
+            }
+        }
+        format!("{}:{:x}", salt, hasher.result())
+    }
+
     pub async fn verify_secrets_exist(&self, vc: &VaultConfig) -> Result<()> {
         use std::collections::HashSet;
         // what are we requesting
@@ -1205,6 +1315,20 @@ impl Manifest {
     }
 }
 
+/// Blame a validation error on a manifest field
+///
+/// Fields are paths like `resources` or `dependencies[0]`, so errors can be traced to source files.
+fn invalid(field: &str) -> ErrorKind {
+    ErrorKind::InvalidField(field.to_string())
+}
+
+/// A validation error about the value of a manifest field
+///
+/// The message stays the error; the field is only chained below it for locating the error.
+fn invalid_value(field: &str, msg: impl Into<String>) -> Error {
+    Error::with_chain(Error::from(invalid(field)), ErrorKind::Msg(msg.into()))
+}
+
 // Cross-crate test manifest creator
 impl Manifest {
     pub fn test(name: &str) -> Manifest {
@@ -1229,3 +1353,33 @@ impl Manifest {
         mf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Manifest;
+
+    #[test]
+    fn secret_checksum_changes() {
+        let mut mf = Manifest::test("fake-ask");
+        mf.secrets.insert("DB_PASSWORD".into(), "hunter2".into());
+        mf.secretFiles.insert("cert.pem".into(), "Y2VydA==".into());
+        let sum = mf.secret_checksum();
+        assert_eq!(mf.secrets_match(&sum), Some(true));
+        // salted, so the same secrets give different checksums
+        assert_ne!(sum, mf.secret_checksum());
+        assert_eq!(mf.secrets_match(&mf.secret_checksum()), Some(true));
+        // unsalted checksums from older versions cannot be compared
+        assert_eq!(mf.secrets_match(&sum[33..]), None);
+
+        // rotating a secret changes the checksum
+        let mut rotated = mf.clone();
+        rotated.secrets.insert("DB_PASSWORD".into(), "hunter3".into());
+        assert_eq!(rotated.secrets_match(&sum), Some(false));
+
+        // moving a secret between env and files changes the checksum
+        let mut moved = Manifest::test("fake-ask");
+        moved.secretFiles.insert("DB_PASSWORD".into(), "hunter2".into());
+        moved.secrets.insert("cert.pem".into(), "Y2VydA==".into());
+        assert_eq!(moved.secrets_match(&sum), Some(false));
+    }
+}
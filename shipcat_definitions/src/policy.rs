@@ -0,0 +1,376 @@
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::fmt;
+
+use super::{Config, Manifest, Region, Result};
+use crate::{
+    region::Environment,
+    structs::resources::{parse_cpu, parse_memory},
+};
+
+/// A declarative rule that completed manifests must satisfy
+///
+/// ```yaml
+/// policies:
+/// - id: prod-replicas
+///   description: Production services need redundancy
+///   scope:
+///     environments: [prod]
+///   field: replicaCount
+///   min: 2
+/// - id: kong-authorization
+///   severity: warn
+///   field: kongApis[].authorization
+///   required: true
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct Policy {
+    /// Unique name of the rule, reported on every violation
+    pub id: String,
+
+    /// Why the rule exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Whether a violation fails validation (defaults to error)
+    #[serde(default)]
+    pub severity: Severity,
+
+    /// Where the rule applies (everywhere by default)
+    #[serde(default)]
+    pub scope: PolicyScope,
+
+    /// Path to a field in the completed manifest
+    ///
+    /// Keys are separated by dots, and `[]` checks every element of a list,
+    /// e.g. `resources.limits.memory` or `kongApis[].authorization`.
+    pub field: String,
+
+    /// Require the field to be set
+    #[serde(default)]
+    pub required: bool,
+
+    /// Smallest allowed value (a number or a kubernetes quantity like `500m` or `1Gi`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<Value>,
+
+    /// Largest allowed value (a number or a kubernetes quantity like `500m` or `1Gi`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<Value>,
+}
+
+/// How serious a policy violation is
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Fails validation
+    Error,
+    /// Logged as a warning
+    Warn,
+}
+
+impl Default for Severity {
+    fn default() -> Severity {
+        Severity::Error
+    }
+}
+
+/// Restrictions on where a policy applies
+///
+/// Empty lists match everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct PolicyScope {
+    /// Environments of the regions to check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<Environment>,
+
+    /// Regions to check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<String>,
+
+    /// Teams whose services to check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teams: Vec<String>,
+}
+
+/// A failed policy check
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// Id of the violated policy
+    pub policy: String,
+    pub severity: Severity,
+    /// Path of the offending value, e.g. `kongApis[0].authorization`
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.policy, self.message)
+    }
+}
+
+impl Policy {
+    pub fn verify(&self) -> Result<()> {
+        if self.id.is_empty() {
+            bail!("Policies need an id");
+        }
+        if self.field.is_empty() || self.field.split('.').any(|k| k.is_empty() || k == "[]") {
+            bail!("Policy {} has an invalid field '{}'", self.id, self.field);
+        }
+        if !self.required && self.min.is_none() && self.max.is_none() {
+            bail!("Policy {} needs one of required, min or max", self.id);
+        }
+        for limit in self.min.iter().chain(self.max.iter()) {
+            if quantity(limit).is_err() {
+                bail!("Policy {} has an invalid limit {}", self.id, show(limit));
+            }
+        }
+        Ok(())
+    }
+
+    fn applies(&self, mf: &Manifest, region: &Region) -> bool {
+        let s = &self.scope;
+        let team = mf.metadata.as_ref().map(|md| md.team.as_str());
+        (s.environments.is_empty() || s.environments.contains(&region.environment))
+            && (s.regions.is_empty() || s.regions.contains(&region.name))
+            && (s.teams.is_empty() || s.teams.iter().any(|t| Some(t.as_str()) == team))
+    }
+
+    /// Check the policy against a serialized manifest
+    fn check(&self, data: &Value) -> Vec<Violation> {
+        let mut res = vec![];
+        for (path, val) in lookup(data, &self.field) {
+            let message = match val {
+                None if self.required => Some(format!("{} must be set", path)),
+                None => None,
+                Some(v) => self.compare(&path, v),
+            };
+            res.extend(message.map(|message| Violation {
+                policy: self.id.clone(),
+                severity: self.severity,
+                field: path,
+                message,
+            }));
+        }
+        res
+    }
+
+    fn compare(&self, path: &str, val: &Value) -> Option<String> {
+        if self.min.is_none() && self.max.is_none() {
+            return None;
+        }
+        let x = match quantity(val) {
+            Ok(x) => x,
+            Err(_) => {
+                return Some(format!(
+                    "{} is {}, which is not a number or quantity",
+                    path,
+                    show(val)
+                ))
+            }
+        };
+        if let Some(min) = &self.min {
+            match quantity(min) {
+                Ok(m) if x >= m => {}
+                _ => {
+                    return Some(format!(
+                        "{} is {}, below the minimum of {}",
+                        path,
+                        show(val),
+                        show(min)
+                    ))
+                }
+            }
+        }
+        if let Some(max) = &self.max {
+            match quantity(max) {
+                Ok(m) if x <= m => {}
+                _ => {
+                    return Some(format!(
+                        "{} is {}, above the maximum of {}",
+                        path,
+                        show(val),
+                        show(max)
+                    ))
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Find all values at a policy field path
+///
+/// Missing and null values are returned as `None` along with their full path.
+fn lookup<'a>(data: &'a Value, field: &str) -> Vec<(String, Option<&'a Value>)> {
+    let mut found = vec![(String::new(), Some(data))];
+    for seg in field.split('.') {
+        let (key, each) = match seg.strip_suffix("[]") {
+            Some(k) => (k, true),
+            None => (seg, false),
+        };
+        let mut next = vec![];
+        for (at, val) in found {
+            let child = val.and_then(|v| v.get(key)).filter(|v| !v.is_null());
+            let at = if at.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", at, key)
+            };
+            if !each {
+                next.push((at, child));
+            } else if let Some(Value::Array(xs)) = child {
+                for (i, x) in xs.iter().enumerate() {
+                    next.push((format!("{}[{}]", at, i), Some(x)));
+                }
+            }
+        }
+        found = next;
+    }
+    found
+}
+
+/// Numeric value of a number or a memory/cpu quantity
+fn quantity(v: &Value) -> Result<f64> {
+    match v {
+        Value::Number(n) => Ok(n.as_f64().unwrap_or_default()),
+        Value::String(s) => parse_memory(s).or_else(|_| parse_cpu(s)),
+        _ => bail!("{} is not a number or quantity", v),
+    }
+}
+
+fn show(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        _ => v.to_string(),
+    }
+}
+
+/// Evaluate all applicable policies against a manifest
+pub fn evaluate(policies: &[Policy], mf: &Manifest, region: &Region) -> Result<Vec<Violation>> {
+    let applicable = policies
+        .iter()
+        .filter(|p| p.applies(mf, region))
+        .collect::<Vec<_>>();
+    if applicable.is_empty() {
+        return Ok(vec![]);
+    }
+    let data = serde_json::to_value(mf)?;
+    Ok(applicable.iter().flat_map(|p| p.check(&data)).collect())
+}
+
+impl Manifest {
+    /// Evaluate the policies in shipcat.conf against a completed manifest
+    ///
+    /// Warnings are logged, and errors fail with the id of every violated policy.
+    /// Like most validation, this is skipped for kube-external services.
+    pub fn verify_policies(&self, conf: &Config, region: &Region) -> Result<()> {
+        if self.external {
+            return Ok(());
+        }
+        let mut errs = vec![];
+        for v in evaluate(&conf.policies, self, region)? {
+            match v.severity {
+                Severity::Warn => warn!("{}: {}", self.name, v),
+                Severity::Error => errs.push(v.to_string()),
+            }
+        }
+        if !errs.is_empty() {
+            bail!("{} violates policies: {}", self.name, errs.join(", "));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, Policy, Severity};
+    use crate::{Manifest, Region};
+
+    fn policies(yaml: &str) -> Vec<Policy> {
+        let ps: Vec<Policy> = serde_yaml::from_str(yaml).unwrap();
+        for p in &ps {
+            p.verify().unwrap();
+        }
+        ps
+    }
+
+    #[test]
+    fn policy_evaluation() {
+        let ps = policies(
+            r#"
+- id: prod-replicas
+  scope:
+    environments: [prod]
+  field: replicaCount
+  min: 2
+- id: memory-cap
+  field: resources.limits.memory
+  max: 8Gi
+- id: kong-auth
+  severity: warn
+  field: kongApis[].authorization
+  required: true
+- id: readiness
+  scope:
+    teams: [other]
+  field: readinessProbe
+  required: true
+"#,
+        );
+        let mut mf: Manifest = serde_yaml::from_str(
+            r#"
+name: fake
+replicaCount: 1
+metadata:
+  team: core
+  repo: https://github.com/babylonhealth/fake
+resources:
+  requests:
+    cpu: 100m
+    memory: 1Gi
+  limits:
+    cpu: 1
+    memory: 10Gi
+kongApis:
+- name: fake
+  uris: /fake
+"#,
+        )
+        .unwrap();
+        let mut reg = Region {
+            name: "dev-uk".into(),
+            ..Region::default()
+        };
+
+        let vs = evaluate(&ps, &mf, &reg).unwrap();
+        let ids = vs.iter().map(|v| v.policy.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["memory-cap", "kong-auth"]);
+        assert_eq!(
+            vs[0].to_string(),
+            "[memory-cap] resources.limits.memory is 10Gi, above the maximum of 8Gi"
+        );
+        assert_eq!(vs[1].message, "kongApis[0].authorization must be set");
+        assert_eq!(vs[1].field, "kongApis[0].authorization");
+        assert_eq!(vs[1].severity, Severity::Warn);
+
+        reg.environment = crate::Environment::Prod;
+        mf.resources.as_mut().unwrap().limits.memory = "512Mi".into();
+        mf.kongApis.clear();
+        let vs = evaluate(&ps, &mf, &reg).unwrap();
+        assert_eq!(vs.len(), 1);
+        assert_eq!(vs[0].message, "replicaCount is 1, below the minimum of 2");
+    }
+
+    #[test]
+    fn policy_verify() {
+        let bad = |yaml: &str| serde_yaml::from_str::<Policy>(yaml).unwrap().verify().is_err();
+        assert!(bad("id: a\nfield: replicaCount\n"));
+        assert!(bad("id: a\nfield: replicaCount\nmin: lots\n"));
+        assert!(bad("id: a\nfield: kongApis..uris\nrequired: true\n"));
+        assert!(!bad("id: a\nfield: resources.requests.cpu\nmax: 500m\n"));
+    }
+}
@@ -1,4 +1,6 @@
 use crate::structs::kong::Kong;
+use chrono::{DateTime, Datelike, NaiveTime, Timelike, Utc, Weekday};
+use schemars::JsonSchema;
 use std::{collections::BTreeMap, env};
 
 use regex::Regex;
@@ -16,7 +18,7 @@ use super::structs::Authorization;
 ///
 /// This is valdiated strictly using `shipcat validate` when versions are found in manifests.
 /// Otherwise, it's validated on upgrade time (via `shipcat apply`) when it's passed.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub enum VersionScheme {
     /// Version must be valid semver (no leading v)
     ///
@@ -58,7 +60,7 @@ impl VersionScheme {
 }
 
 /// Vault configuration for a region
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(test, derive(Default))]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct VaultConfig {
@@ -114,7 +116,7 @@ impl VaultConfig {
 //}
 
 /// Kafka configuration for a region
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct KafkaConfig {
     /// Broker urls in "hostname:port" format.
@@ -140,7 +142,7 @@ pub struct KafkaConfig {
 }
 
 /// Webhook types that shipcat might trigger after actions
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[serde(tag = "name", deny_unknown_fields, rename_all = "snake_case")]
 pub enum Webhook {
     /// Audit webhook details
@@ -148,7 +150,7 @@ pub enum Webhook {
 }
 
 /// Where / how to send audited events
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct AuditWebhook {
     /// Endpoint
@@ -158,7 +160,7 @@ pub struct AuditWebhook {
 }
 
 /// Configure how CRs will be deployed on a region
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct CRSettings {
     #[serde(rename = "config")]
@@ -168,7 +170,7 @@ pub struct CRSettings {
 // ----------------------------------------------------------------------------------
 
 /// Kong configuration for a region
-#[derive(Serialize, Deserialize, Clone, Debug, Default)] // TODO: better Default impl
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)] // TODO: better Default impl
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct KongConfig {
     /// Base URL to use (e.g. uk.dev.babylontech.co.uk)
@@ -188,7 +190,7 @@ pub struct KongConfig {
 }
 
 /// StatusCake configuration for a region
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct StatuscakeConfig {
     /// Contact Group that will be used if tests go down
@@ -200,7 +202,7 @@ pub struct StatuscakeConfig {
 }
 
 /// Logz.io configuration for a region
-#[derive(Serialize, Deserialize, Clone, Debug, Default)] // TODO: better Default impl
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)] // TODO: better Default impl
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct LogzIoConfig {
     /// Base URL to use (e.g. https://app-eu.logz.io/#/dashboard/kibana/dashboard)
@@ -210,7 +212,7 @@ pub struct LogzIoConfig {
 }
 
 /// Grafana details for a region
-#[derive(Serialize, Deserialize, Clone, Debug, Default)] // TODO: better Default impl
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)] // TODO: better Default impl
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct GrafanaConfig {
     /// Base URL to use (e.g. https://dev-grafana.ops.babylontech.co.uk)
@@ -220,20 +222,20 @@ pub struct GrafanaConfig {
 }
 
 /// Sentry details for a region
-#[derive(Serialize, Deserialize, Clone, Debug, Default)] // TODO: better Default impl
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)] // TODO: better Default impl
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct SentryConfig {
     /// Base URL to use (e.g. https://dev-uk-sentry.ops.babylontech.co.uk)
     pub url: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct KongAnonymousConsumers {
     pub anonymous: BTreeMap<String, String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct KongOauthConsumer {
     pub oauth_client_id: String,
@@ -241,14 +243,14 @@ pub struct KongOauthConsumer {
     pub username: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct KongJwtConsumer {
     pub kid: String,
     pub public_key: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct KongTcpLogConfig {
     pub enabled: bool,
@@ -264,14 +266,14 @@ impl KongConfig {
 
 /// Defaults for services in this region
 // TODO: This should be ManifestDefaults from shipcat_filebacked
-#[derive(Deserialize, Clone, Debug, Default)]
+#[derive(Deserialize, Clone, Debug, Default, JsonSchema)]
 #[serde(default)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct DefaultConfig {
     pub kong: DefaultKongConfig,
 }
 
-#[derive(Deserialize, Clone, Debug, Default)]
+#[derive(Deserialize, Clone, Debug, Default, JsonSchema)]
 #[serde(default)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct DefaultKongConfig {
@@ -396,8 +398,180 @@ mod test_webhooks {
 
 // ----------------------------------------------------------------------------------
 
+/// A deployment freeze window
+///
+/// `shipcat apply` refuses to upgrade services during a freeze,
+/// unless the service is exempt, or `--override-freeze` is passed.
+/// A freeze is either a one-off window with a `start` and `end`, or `weekly`.
+///
+/// ```yaml
+/// freezes:
+/// - name: christmas
+///   start: 2020-12-23T17:00:00Z
+///   end: 2021-01-04T08:00:00Z
+/// - name: friday-afternoon
+///   weekly:
+///     days: [Fri]
+///     from: "14:00"
+///     to: "23:59"
+///   exemptTeams: [devops]
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct Freeze {
+    /// Name of the freeze (shown when refusing to apply)
+    pub name: String,
+    /// Start of a one-off freeze
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<DateTime<Utc>>,
+    /// End of a one-off freeze
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    /// Recurring weekly freeze
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly: Option<WeeklyWindow>,
+    /// Services that can be applied during the freeze
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exemptServices: Vec<String>,
+    /// Teams whose services can be applied during the freeze
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exemptTeams: Vec<String>,
+}
+
+/// A window of time recurring on certain days of the week
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
+pub struct WeeklyWindow {
+    /// Days the window applies to (e.g. `Fri`)
+    pub days: Vec<Weekday>,
+    /// Start time of the window in UTC (`HH:MM`)
+    pub from: String,
+    /// End time of the window in UTC (`HH:MM`, inclusive)
+    pub to: String,
+}
+
+impl WeeklyWindow {
+    fn parse_time(t: &str) -> Result<NaiveTime> {
+        Ok(NaiveTime::parse_from_str(t, "%H:%M")?)
+    }
+
+    fn verify(&self) -> Result<()> {
+        if self.days.is_empty() {
+            bail!("weekly freeze window needs at least one day");
+        }
+        let from = Self::parse_time(&self.from)?;
+        let to = Self::parse_time(&self.to)?;
+        if from > to {
+            bail!("weekly freeze window must start before it ends");
+        }
+        Ok(())
+    }
+
+    fn contains(&self, now: DateTime<Utc>) -> bool {
+        if !self.days.contains(&now.weekday()) {
+            return false;
+        }
+        match (Self::parse_time(&self.from), Self::parse_time(&self.to)) {
+            (Ok(from), Ok(to)) => {
+                // compare at minute resolution so `to` is inclusive
+                let t = NaiveTime::from_hms(now.hour(), now.minute(), 0);
+                from <= t && t <= to
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Freeze {
+    pub fn verify(&self) -> Result<()> {
+        match (&self.start, &self.end, &self.weekly) {
+            (Some(s), Some(e), None) => {
+                if s >= e {
+                    bail!("freeze {} must start before it ends", self.name);
+                }
+            }
+            (None, None, Some(w)) => w.verify()?,
+            _ => bail!(
+                "freeze {} needs either a start and end, or a weekly window",
+                self.name
+            ),
+        }
+        Ok(())
+    }
+
+    /// Whether the freeze is in effect at a point in time
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        if let (Some(s), Some(e)) = (&self.start, &self.end) {
+            return *s <= now && now < *e;
+        }
+        if let Some(w) = &self.weekly {
+            return w.contains(now);
+        }
+        false
+    }
+
+    /// Whether a service (owned by a team) is exempt from the freeze
+    pub fn exempts(&self, svc: &str, team: Option<&str>) -> bool {
+        if self.exemptServices.iter().any(|s| s == svc) {
+            return true;
+        }
+        match team {
+            Some(t) => self.exemptTeams.iter().any(|et| et == t),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_freezes {
+    use super::Freeze;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn freeze_windows() {
+        let weekly: Freeze = serde_yaml::from_str(
+            r#"
+name: friday-afternoon
+weekly:
+  days: [Fri]
+  from: "14:00"
+  to: "23:59"
+exemptTeams: [devops]
+"#,
+        )
+        .unwrap();
+        weekly.verify().unwrap();
+        // 2020-04-03 was a friday
+        assert!(weekly.is_active(Utc.ymd(2020, 4, 3).and_hms(14, 0, 0)));
+        assert!(weekly.is_active(Utc.ymd(2020, 4, 3).and_hms(23, 59, 30)));
+        assert!(!weekly.is_active(Utc.ymd(2020, 4, 3).and_hms(13, 59, 0)));
+        assert!(!weekly.is_active(Utc.ymd(2020, 4, 2).and_hms(15, 0, 0)));
+        assert!(weekly.exempts("fake-ask", Some("devops")));
+        assert!(!weekly.exempts("fake-ask", Some("other")));
+
+        let oneoff: Freeze = serde_yaml::from_str(
+            r#"
+name: christmas
+start: 2020-12-23T17:00:00Z
+end: 2021-01-04T08:00:00Z
+exemptServices: [fake-ask]
+"#,
+        )
+        .unwrap();
+        oneoff.verify().unwrap();
+        assert!(oneoff.is_active(Utc.ymd(2020, 12, 25).and_hms(12, 0, 0)));
+        assert!(!oneoff.is_active(Utc.ymd(2021, 1, 4).and_hms(8, 0, 0)));
+        assert!(oneoff.exempts("fake-ask", None));
+
+        let invalid: Freeze = serde_yaml::from_str("name: nothing").unwrap();
+        assert!(invalid.verify().is_err());
+    }
+}
+
+// ----------------------------------------------------------------------------------
+
 /// Environments are well defined strings
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Environment {
     /// Production environment
@@ -432,7 +606,7 @@ impl ToString for Environment {
 // ----------------------------------------------------------------------------------
 
 /// Environments are well defined strings
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum ReconciliationMode {
     /// Shipcat owned, CRD based decision
     ///
@@ -453,7 +627,7 @@ impl Default for ReconciliationMode {
 ///
 /// Either it's a pure kubernetes context with a namespace and a cluster,
 /// or it's an abstract concept with many associated real kubernetes contexts.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(test, derive(Default))]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct Region {
@@ -469,6 +643,16 @@ pub struct Region {
     #[serde(default)]
     pub reconciliationMode: ReconciliationMode,
 
+    /// Roll back to the last successfully rolled out version when a rollout fails
+    ///
+    /// Default for `shipcat apply --rollback-on-failure` in this region.
+    #[serde(default)]
+    pub rollbackOnFailure: bool,
+
+    /// Deployment freezes for the region
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub freezes: Vec<Freeze>,
+
     /// Primary cluster serving this region
     ///
     /// Shipcat does not use this for to decide where a region gets deployed,
@@ -527,10 +711,12 @@ pub struct Region {
     // TODO: Rename to `defaults` after removing legacy field
     #[serde(skip_serializing, default)]
     #[cfg(feature = "filesystem")]
+    #[schemars(with = "Option<serde_json::Value>")]
     pub defaultsV2: Option<serde_yaml::Value>,
 
     /// The regular expression used to verify destination rules' regions
     #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_regex")]
+    #[schemars(with = "Option<String>", skip_serializing)] // schemars cannot serialize a default through serde_regex
     pub destinationRuleHostRegex: Option<Regex>,
 }
 
@@ -553,6 +739,13 @@ impl Region {
         Ok(())
     }
 
+    /// The freeze (if any) preventing a service from being applied at a point in time
+    pub fn active_freeze(&self, now: DateTime<Utc>, svc: &str, team: Option<&str>) -> Option<&Freeze> {
+        self.freezes
+            .iter()
+            .find(|f| f.is_active(now) && !f.exempts(svc, team))
+    }
+
     // Get the Vault URL for a given service in this region
     pub fn vault_url(&self, app: &str) -> String {
         let vault_url = self.vault.url.clone();
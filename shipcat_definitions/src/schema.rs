@@ -0,0 +1,170 @@
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1beta1::JSONSchemaProps;
+use schemars::{
+    gen::SchemaSettings,
+    schema::{RootSchema, Schema, SchemaObject},
+    visit::{self, Visitor},
+    JsonSchema,
+};
+use serde_json::{Map, Value};
+
+use super::Result;
+
+/// JSON Schema (draft 7) of a type, using doc comments as descriptions
+///
+/// Suitable for editor integrations like yaml-language-server.
+pub fn json_schema<T: JsonSchema>() -> RootSchema {
+    SchemaSettings::draft07()
+        .with_visitor(WriteOnlyOptional)
+        .into_generator()
+        .into_root_schema_for::<T>()
+}
+
+/// OpenAPI v3 schema of a type, limited to what kubernetes accepts in CRDs
+///
+/// Kubernetes does not allow references, defaults, or `additionalProperties` next to `properties`,
+/// so everything is inlined and the schema only checks the known fields.
+pub fn openapi_schema<T: JsonSchema>() -> Result<JSONSchemaProps> {
+    let settings = SchemaSettings::openapi3()
+        .with(|s| s.inline_subschemas = true)
+        .with_visitor(WriteOnlyOptional);
+    let root = settings.into_generator().into_root_schema_for::<T>();
+    let mut data = serde_json::to_value(root.schema)?;
+    kubernetes_compatible(&mut data);
+    Ok(serde_json::from_value(data)?)
+}
+
+/// Makes fields that are never serialized optional
+///
+/// Schemars marks them as required even when serde has a default for them.
+#[derive(Debug, Clone)]
+struct WriteOnlyOptional;
+
+impl Visitor for WriteOnlyOptional {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        if let Some(obj) = &mut schema.object {
+            let write_only = obj
+                .properties
+                .iter()
+                .filter(|(_, s)| match s {
+                    Schema::Object(o) => o.metadata.as_ref().map(|m| m.write_only) == Some(true),
+                    Schema::Bool(_) => false,
+                })
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<_>>();
+            for k in write_only {
+                obj.required.remove(&k);
+            }
+        }
+        visit::visit_schema_object(self, schema)
+    }
+}
+
+/// Strip a json schema down to the subset allowed in CRD validation
+fn kubernetes_compatible(data: &mut Value) {
+    if let Value::Object(o) = data {
+        for k in &[
+            "$schema",
+            "definitions",
+            "default",
+            "examples",
+            "format",
+            "readOnly",
+            "writeOnly",
+            "uniqueItems",
+        ] {
+            o.remove(*k);
+        }
+        if o.contains_key("properties") {
+            o.remove("additionalProperties");
+        }
+        if let Some(Value::Array(types)) = o.get("type").cloned() {
+            // a nullable type is the only union kubernetes can express
+            let types = types.into_iter().filter(|t| t != "null").collect::<Vec<_>>();
+            if types.len() == 1 {
+                o.insert("type".into(), types[0].clone());
+                o.insert("nullable".into(), true.into());
+            } else {
+                o.remove("type");
+            }
+        }
+        if o.get("nullable") == Some(&Value::Bool(true)) {
+            if let Some(Value::Array(variants)) = o.get_mut("enum") {
+                if !variants.contains(&Value::Null) {
+                    variants.push(Value::Null);
+                }
+            }
+        }
+        for (k, v) in o.iter_mut() {
+            let subschemas = match k.as_str() {
+                "properties" => v.as_object_mut().map(|ps| ps.values_mut().collect()),
+                "items" | "additionalProperties" | "not" => Some(vec![v]),
+                "allOf" | "anyOf" | "oneOf" => v.as_array_mut().map(|xs| xs.iter_mut().collect()),
+                _ => None,
+            };
+            for s in subschemas.unwrap_or_default() {
+                if s == &Value::Bool(true) {
+                    // anything goes (e.g. json values)
+                    *s = Value::Object(Map::new());
+                }
+                kubernetes_compatible(s);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_schema, openapi_schema};
+    use crate::{Config, Manifest, Region};
+
+    #[test]
+    fn schema_descriptions() {
+        let region = serde_json::to_value(json_schema::<Region>()).unwrap();
+        assert_eq!(
+            region["properties"]["namespace"]["description"],
+            "Kubernetes namespace"
+        );
+        let required = region["required"].as_array().unwrap();
+        for field in &[
+            "name",
+            "namespace",
+            "environment",
+            "cluster",
+            "versioningScheme",
+            "vault",
+        ] {
+            assert!(
+                required.contains(&(*field).into()),
+                "{} should be required",
+                field
+            );
+        }
+        // defaulted fields stay optional
+        assert!(!required.contains(&"webhooks".into()));
+        let conf = serde_json::to_value(json_schema::<Config>()).unwrap();
+        assert!(conf["definitions"]["Policy"]["properties"]["field"].is_object());
+        // never serialized, but still optional in shipcat.conf
+        let kong = &conf["definitions"]["KongConfig"];
+        assert_eq!(kong["properties"]["extra_apis"]["writeOnly"], true);
+        assert!(!kong["required"]
+            .as_array()
+            .unwrap()
+            .contains(&"extra_apis".into()));
+    }
+
+    #[test]
+    fn schema_openapi() {
+        let mf = openapi_schema::<Manifest>().unwrap();
+        let data = serde_json::to_string(&mf).unwrap();
+        assert!(!data.contains("$ref"));
+        assert!(!data.contains("\"default\""));
+        let props = mf.properties.unwrap();
+        assert_eq!(props["replicaCount"].type_.as_deref(), Some("integer"));
+        assert_eq!(props["replicaCount"].nullable, Some(true));
+        assert!(props["kongApis"].items.is_some());
+        assert!(!data.contains("uniqueItems"));
+
+        let crds = crate::gen_all_crds();
+        assert!(crds[1].spec.validation.is_some());
+    }
+}
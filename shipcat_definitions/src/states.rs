@@ -1,7 +1,8 @@
 use super::{vault::Vault, Manifest, Region, Result};
+use schemars::JsonSchema;
 
 /// Type of primary workload that is associated with the Manifest
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub enum PrimaryWorkload {
     Deployment,
     Statefulset,
@@ -22,7 +23,7 @@ impl Default for PrimaryWorkload {
 /// Various internal states a manifest can exist in depending on resolution.
 ///
 /// This only matters within shipcat and is used to optimize speed of accessors.
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
 pub enum ManifestState {
     /// A completed manifest
     ///
@@ -121,7 +122,7 @@ impl Manifest {
 /// Various states a Config can exist in depending on resolution.
 ///
 /// Within shipcat, this is used to optimize speed of accessors.
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
 pub enum ConfigState {
     /// A filtered config for a specific region, with resolved secrets
     Filtered,
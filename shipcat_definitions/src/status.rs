@@ -18,8 +18,16 @@ pub struct ManifestStatus {
     /// A more easily readable summary of why the conditions are what they are
     #[serde(default)]
     pub summary: Option<ConditionSummary>,
-    /* TODO: vault secret hash
-     * MAYBE: kong status?
+    /// Bounded list of finished rollouts, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<RolloutRecord>,
+    /// Checksum of the secrets used in the last successful apply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_checksum: Option<String>,
+    /// When the secrets were last compared against `secret_checksum`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets_checked: Option<String>,
+    /* MAYBE: kong status?
      * MAYBE: canary status? */
 }
 
@@ -55,12 +63,12 @@ pub struct Conditions {
     pub rolledout: Option<Condition>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ConditionSummary {
     /// Date string (RFC3339) of when we generated the template successfully
     #[serde(default)]
-    last_successful_generate: Option<String>,
+    pub last_successful_generate: Option<String>,
 
     /// Date string (RFC3339) of when we last applied manifest configuration
     #[serde(default)]
@@ -68,33 +76,56 @@ pub struct ConditionSummary {
 
     /// Date string (RFC3339) of when an apply passed all checks
     #[serde(default)]
-    last_successful_apply: Option<String>,
+    pub last_successful_apply: Option<String>,
 
     /// Date string (RFC3339) of when a rollout wait completed
     #[serde(default)]
-    last_rollout: Option<String>,
+    pub last_rollout: Option<String>,
 
     /// Date string (RFC3339) of when a rollout wait completed and passed
     #[serde(default)]
-    last_successful_rollout: Option<String>,
+    pub last_successful_rollout: Option<String>,
 
     // last action we performed
     #[serde(default)]
-    last_action: Option<String>,
+    pub last_action: Option<String>,
 
     /// reason for last failure (if any)
     #[serde(default)]
-    last_failure_reason: Option<String>,
+    pub last_failure_reason: Option<String>,
 
     /// Best effort reason for why an apply was triggered
     #[serde(default)]
-    last_apply_reason: Option<String>,
+    pub last_apply_reason: Option<String>,
 
     /// Last version that was successfully rolled out
     #[serde(default)]
     pub last_successful_rollout_version: Option<String>,
 }
 
+/// Record of a finished rollout
+///
+/// Written alongside the `rolledout` condition so that past deploys can be listed.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RolloutRecord {
+    /// Version that was rolled out
+    pub version: String,
+    /// Whether the rollout succeeded
+    pub status: bool,
+    /// When the rollout finished (RFC 3339 timestamp)
+    pub timestamp: String,
+    /// Best effort reason for why the apply was triggered
+    #[serde(default)]
+    pub apply_reason: Option<String>,
+    /// Reason for failure if the rollout failed
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Originator of the rollout
+    #[serde(default)]
+    pub source: Option<Applier>,
+}
+
 /// Condition
 ///
 /// Stated out like a normal kubernetes conditions like PodCondition:
@@ -1,5 +1,7 @@
+use schemars::JsonSchema;
+
 /// Configuration for authorization of requests
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, JsonSchema)]
 pub struct Authorization {
     /// Allowed values for the `aud` claim of the JWT payload.
     pub allowed_audiences: Vec<String>,
@@ -1,6 +1,7 @@
 use super::{EnvVars, Port, Probe, ResourceRequirements, VolumeMount};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Container {
     /// Name of container
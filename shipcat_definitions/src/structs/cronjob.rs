@@ -1,7 +1,8 @@
 use super::Container;
+use schemars::JsonSchema;
 use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct JobVolumeClaim {
     /// The cron job name
@@ -9,7 +10,7 @@ pub struct JobVolumeClaim {
     pub mountPath: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct CronJob {
     /// Common properties for all types of container
     #[serde(flatten)]
@@ -1,4 +1,5 @@
 use super::Result;
+use schemars::JsonSchema;
 use std::collections::{BTreeMap, BTreeSet};
 
 /// Environment variables to inject
@@ -29,7 +30,7 @@ use std::collections::{BTreeMap, BTreeSet};
 /// region, and replace them internally.
 ///
 /// The `as_secret` destinction only serves to put `AUTH_SECRET` into `Manifest::secrets`.
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, JsonSchema)]
 #[serde(default)]
 pub struct EnvVars {
     /// Plain text (non-secret) environment variables
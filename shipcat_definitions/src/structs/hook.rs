@@ -0,0 +1,105 @@
+use super::{Container, Result};
+use schemars::JsonSchema;
+use std::collections::BTreeSet;
+
+/// Containers run to completion as kubernetes `Job` objects around an upgrade
+///
+/// Useful for database migrations, cache warmers and smoke tests.
+/// Hooks run with the same service account, environment and secrets as the service.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct Hooks {
+    /// Jobs that must succeed before the main workload is upgraded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preDeploy: Vec<Hook>,
+
+    /// Jobs that must succeed after the main workload has rolled out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub postDeploy: Vec<Hook>,
+}
+
+/// A single hook container
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct Hook {
+    /// Common properties for all types of container
+    ///
+    /// The image and version of the service are used when not set.
+    #[serde(flatten)]
+    pub container: Container,
+
+    /// Optional timeout, in seconds (defaults to 10 minutes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+
+    /// Optional number of retries before marking the hook as failed
+    ///
+    /// Defaults to zero; hooks are not assumed to be idempotent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoffLimit: Option<u16>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.preDeploy.is_empty() && self.postDeploy.is_empty()
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for h in self.preDeploy.iter().chain(self.postDeploy.iter()) {
+            h.verify()?;
+            if !names.insert(&h.container.name) {
+                bail!("Hook names must be unique, found {} twice", h.container.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Hook {
+    /// Time to wait for the hook to complete
+    pub fn timeout_secs(&self) -> u32 {
+        self.timeout.unwrap_or(600)
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let c = &self.container;
+        if c.name.is_empty() {
+            bail!("Hooks need a name");
+        }
+        match (&c.image, &c.version) {
+            (Some(_), None) => bail!(
+                "Cannot specify image without specifying version in hook {}",
+                c.name
+            ),
+            (None, Some(_)) => bail!(
+                "Cannot specify version without specifying image in hook {}",
+                c.name
+            ),
+            (_, _) => {}
+        }
+        if self.timeout == Some(0) {
+            bail!("Hook {} needs a positive timeout", c.name);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hooks;
+
+    #[test]
+    fn hooks_verify() {
+        let hooks: Hooks = serde_yaml::from_str(
+            "preDeploy:\n- name: migrate\n  command: [./migrate]\npostDeploy:\n- name: smoke\n  timeout: 60\n",
+        )
+        .unwrap();
+        assert!(hooks.verify().is_ok());
+        assert_eq!(hooks.preDeploy[0].timeout_secs(), 600);
+        assert_eq!(hooks.postDeploy[0].timeout_secs(), 60);
+
+        let dupes: Hooks = serde_yaml::from_str("preDeploy:\n- name: a\npostDeploy:\n- name: a\n").unwrap();
+        assert!(dupes.verify().is_err());
+        let unpinned: Hooks = serde_yaml::from_str("preDeploy:\n- name: a\n  image: foo\n").unwrap();
+        assert!(unpinned.verify().is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use super::Result;
 use regex::Regex;
+use schemars::JsonSchema;
 
 // HostAlias support for all pods regardless of network configuration.
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 pub struct HostAlias {
     /// ip address string
     pub ip: String,
@@ -1,10 +1,11 @@
+use schemars::JsonSchema;
 use std::{collections::BTreeMap, ops::Not};
 
 use super::Authorization;
 use crate::deserializers::comma_separated_string;
 
 /// Kong setup for a service
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 #[serde(default)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct Kong {
@@ -126,7 +127,7 @@ fn preserve_host_default() -> bool {
 }
 
 /// Cors plugin data
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct Cors {
     pub credentials: bool,
@@ -140,7 +141,7 @@ pub struct Cors {
 }
 
 /// Babylon Auth Header plugin data
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct BabylonAuthHeader {
     pub auth_service: String,
@@ -149,7 +150,7 @@ pub struct BabylonAuthHeader {
     pub http_timeout_msec: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct KongRateLimit {
     pub per_second: Option<u32>,
     pub per_minute: Option<u32>,
@@ -157,7 +158,7 @@ pub struct KongRateLimit {
     pub per_day: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum Authentication {
     None,
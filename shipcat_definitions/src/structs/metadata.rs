@@ -1,5 +1,6 @@
 use crate::teams::Owners;
 use regex::Regex;
+use schemars::JsonSchema;
 use std::{
     collections::{BTreeMap, BTreeSet},
     ops::{Deref, DerefMut},
@@ -11,7 +12,7 @@ use crate::config::SlackParameters;
 /// Legacy contact data
 ///
 /// This property is being phased out in favour of .maintainer
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub struct Contact {
     /// Free text name
     pub name: String,
@@ -49,7 +50,7 @@ impl Contact {
 }
 
 /// Slack channel verifier
-#[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug, JsonSchema)]
 pub struct SlackChannel(String);
 impl SlackChannel {
     pub fn new(chan: &str) -> Self {
@@ -90,7 +91,7 @@ impl DerefMut for SlackChannel {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Language {
     Rust,
@@ -122,7 +123,7 @@ pub enum Language {
 /// context:
 ///   name: consultations
 /// ```
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Context {
     /// name of parent context
@@ -143,7 +144,7 @@ impl Context {
 }
 
 /// Metadata for a service
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[cfg_attr(test, derive(Default))]
 pub struct Metadata {
     /// Git repository
@@ -67,6 +67,9 @@ pub use self::probes::Probe;
 /// Kubernetes rolling-update settings
 pub mod rollingupdate;
 pub use self::rollingupdate::RollingUpdate;
+/// Progressive rollout strategies
+pub mod rolloutstrategy;
+pub use self::rolloutstrategy::RolloutStrategy;
 /// Kubernetes horizontal pod autoscaler
 pub mod autoscaling;
 /// Kubernetes container lifecycle events
@@ -90,6 +93,9 @@ pub use self::vault::VaultOpts;
 /// Cron Jobs
 pub mod cronjob;
 pub use self::cronjob::{CronJob, JobVolumeClaim};
+/// Pre- and post-deploy hook jobs
+pub mod hook;
+pub use self::hook::{Hook, Hooks};
 
 // Kubernetes Containers
 pub mod container;
@@ -1,9 +1,10 @@
 use super::{resources::parse_memory, Result};
+use schemars::JsonSchema;
 
 /// K8s Access modes for PVCs
 ///
 /// See [K8s access mode docs](https://kubernetes.io/docs/concepts/storage/persistent-volumes/#access-modes).
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub enum VolumeAccessMode {
     ReadWriteOnce,
     ReadOnlyMany,
@@ -19,7 +20,7 @@ impl Default for VolumeAccessMode {
 /// A kubernetes Persistent Volume Claim
 ///
 /// See [K8s persistent volume docs](https://kubernetes.io/docs/concepts/storage/persistent-volumes/)-.
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, JsonSchema)]
 pub struct PersistentVolume {
     pub name: String,
     pub mountPath: String,
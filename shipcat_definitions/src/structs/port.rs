@@ -1,4 +1,6 @@
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use schemars::JsonSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum PortProtocol {
     Tcp,
@@ -13,7 +15,7 @@ impl Default for PortProtocol {
 }
 
 /// Port to open on a container
-#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Port {
     /// Name of the port
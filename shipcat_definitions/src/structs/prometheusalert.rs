@@ -1,12 +1,13 @@
 use super::Result;
 use inflector::cases::pascalcase::is_pascal_case;
 use regex::Regex;
+use schemars::JsonSchema;
 
 /// Data describing one Prometheus alert.
 ///
 /// This roughly corresponds to a Rule object in the Prometheus Operator API spec:
 /// https://github.com/coreos/prometheus-operator/blob/master/Documentation/api.md#rule
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub struct PrometheusAlert {
     /// Name of the alert
     ///
@@ -45,7 +46,7 @@ pub struct PrometheusAlert {
 ///
 /// Represents the set of alert severities we allow in our Prometheus alerts.
 #[serde(rename_all = "lowercase")]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub enum PrometheusAlertSeverity {
     /// Warning severity
     ///
@@ -1,4 +1,5 @@
 use super::Result;
+use schemars::JsonSchema;
 use std::ops::{Add, AddAssign, Mul};
 
 // Kubernetes resouce structs
@@ -10,7 +11,7 @@ use std::ops::{Add, AddAssign, Mul};
 // implemented to be a bit more useful, as well as some to convert between them.
 
 /// Kubernetes resource requests or limit
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct Resources<T> {
     /// CPU request string
@@ -23,7 +24,7 @@ pub struct Resources<T> {
 /// Kubernetes resources
 ///
 /// This can be inlined straight into a container spec at the moment
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct ResourceRequirements<T> {
     /// Resource requests for k8s
@@ -186,9 +187,10 @@ pub fn parse_memory(s: &str) -> Result<f64> {
     Ok(res)
 }
 
-// Parse normal k8s cpu resource values into floats
-// We don't allow power of two variants here
-fn parse_cpu(s: &str) -> Result<f64> {
+/// Parse normal k8s cpu resource values into floats
+///
+/// We don't allow power of two variants here.
+pub fn parse_cpu(s: &str) -> Result<f64> {
     let digits = s
         .chars()
         .take_while(|ch| ch.is_digit(10) || *ch == '.')
@@ -0,0 +1,176 @@
+use super::Result;
+use regex::Regex;
+use schemars::JsonSchema;
+
+/// Strategy used when rolling out a new version of a service
+///
+/// Without one, a plain kubernetes rolling update is done (tweakable via `rollingUpdate`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RolloutStrategy {
+    /// Plain kubernetes rolling update of the primary workload
+    RollingUpdate,
+    /// Progressive rollout through a canary workload
+    Canary(CanaryStrategy),
+}
+
+/// Canary parameters
+///
+/// A `{name}-canary` Deployment is created next to the primary Deployment
+/// and scaled up through the `steps`, before the primary is upgraded.
+/// Only supported for services rendered natively (`SHIPCAT_NATIVE_CHARTS=1` with the base chart).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, JsonSchema)]
+pub struct CanaryStrategy {
+    /// Steps to progress through, the last one must have weight 100
+    pub steps: Vec<CanaryStep>,
+
+    /// Maximum number of container restarts tolerated in canary pods
+    ///
+    /// Checked after every step. Defaults to zero.
+    #[serde(default)]
+    pub maxRestarts: u32,
+}
+
+/// A single step in a canary rollout
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct CanaryStep {
+    /// Percentage of replicas that should run the new version
+    pub weight: u32,
+
+    /// How long to wait after the step is ready before continuing
+    ///
+    /// Examples: '30s', '5m', '1h'.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause: Option<String>,
+}
+
+impl RolloutStrategy {
+    pub fn verify(&self) -> Result<()> {
+        match self {
+            RolloutStrategy::RollingUpdate => Ok(()),
+            RolloutStrategy::Canary(c) => c.verify(),
+        }
+    }
+
+    /// The canary parameters if this is a canary strategy
+    pub fn canary(&self) -> Option<&CanaryStrategy> {
+        match self {
+            RolloutStrategy::Canary(c) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl CanaryStrategy {
+    pub fn verify(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            bail!("Canary rolloutStrategy needs at least one step");
+        }
+        let mut prev = 0;
+        for s in &self.steps {
+            s.verify()?;
+            if s.weight <= prev {
+                bail!("Canary step weights must be strictly increasing");
+            }
+            prev = s.weight;
+        }
+        if prev != 100 {
+            bail!("The last canary step must have weight 100");
+        }
+        Ok(())
+    }
+}
+
+impl CanaryStep {
+    pub fn verify(&self) -> Result<()> {
+        if self.weight == 0 || self.weight > 100 {
+            bail!(
+                "Canary step weight must be between 1 and 100 (got {})",
+                self.weight
+            );
+        }
+        if let Some(p) = &self.pause {
+            if !Regex::new(r"^\d+[smh]$").unwrap().is_match(p) {
+                bail!("Canary step has invalid pause value (needs to be like '30s', '5m' or '1h')");
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of seconds to pause after this step
+    pub fn pause_seconds(&self) -> u64 {
+        if let Some(p) = &self.pause {
+            let (digits, unit) = p.split_at(p.len() - 1);
+            let n: u64 = digits.parse().unwrap_or(0); // safe due to verify ^
+            match unit {
+                "h" => n * 3600,
+                "m" => n * 60,
+                _ => n,
+            }
+        } else {
+            0
+        }
+    }
+
+    /// Number of canary replicas for this step given the total replica count
+    ///
+    /// Rounds up so that every step has at least one canary pod.
+    pub fn replicas(&self, total: u32) -> u32 {
+        let r = ((f64::from(total) * f64::from(self.weight)) / 100.0).ceil() as u32;
+        std::cmp::max(1, r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CanaryStep, RolloutStrategy};
+
+    #[test]
+    fn canary_parse_and_verify() {
+        let rs: RolloutStrategy = serde_yaml::from_str(
+            r#"
+canary:
+  steps:
+  - weight: 10
+    pause: 5m
+  - weight: 50
+    pause: 30s
+  - weight: 100
+"#,
+        )
+        .unwrap();
+        rs.verify().unwrap();
+        let c = rs.canary().unwrap();
+        assert_eq!(c.maxRestarts, 0);
+        assert_eq!(c.steps[0].pause_seconds(), 300);
+        assert_eq!(c.steps[1].pause_seconds(), 30);
+        assert_eq!(c.steps[2].pause_seconds(), 0);
+
+        let plain: RolloutStrategy = serde_yaml::from_str("rollingUpdate").unwrap();
+        assert_eq!(plain, RolloutStrategy::RollingUpdate);
+        assert!(plain.canary().is_none());
+    }
+
+    #[test]
+    fn canary_verify_failures() {
+        let bad: RolloutStrategy =
+            serde_yaml::from_str("canary: { steps: [ { weight: 50 }, { weight: 20 } ] }").unwrap();
+        assert!(bad.verify().is_err());
+        let unfinished: RolloutStrategy =
+            serde_yaml::from_str("canary: { steps: [ { weight: 50 } ] }").unwrap();
+        assert!(unfinished.verify().is_err());
+        let badpause: RolloutStrategy =
+            serde_yaml::from_str("canary: { steps: [ { weight: 100, pause: 5d } ] }").unwrap();
+        assert!(badpause.verify().is_err());
+    }
+
+    #[test]
+    fn canary_replicas() {
+        let step = |weight| CanaryStep { weight, pause: None };
+        assert_eq!(step(10).replicas(4), 1);
+        assert_eq!(step(50).replicas(4), 2);
+        assert_eq!(step(50).replicas(5), 3);
+        assert_eq!(step(100).replicas(5), 5);
+        assert_eq!(step(1).replicas(0), 1);
+    }
+}
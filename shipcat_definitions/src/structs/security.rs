@@ -1,11 +1,12 @@
 use super::Result;
 use regex::Regex;
+use schemars::JsonSchema;
 use std::path::Path;
 
 /// What sensitive data is managed and how
 ///
 /// See https://engineering.ops.babylontech.co.uk/docs/principles-security/
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct DataHandling {
     /// Where and how data is stored
@@ -25,7 +26,7 @@ impl DataHandling {
 }
 
 /// Possible levels of information classification of the data stored in the data store.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum InformationClassification {
     StrictlyConfidential,
@@ -42,7 +43,7 @@ impl Default for InformationClassification {
 }
 
 /// Data storage information and encryption information
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct DataStore {
     /// Storage type (one of "MySQL", "DynamoDB", "S3", "File", "Kafka")
@@ -101,7 +102,7 @@ impl DataStore {
 }
 
 /// Data storage information and encryption information
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct DataField {
     /// Canonical name of the data field
@@ -123,7 +124,7 @@ pub struct DataField {
 }
 
 /// Data storage information and encryption information
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 #[cfg_attr(feature = "filesystem", serde(deny_unknown_fields))]
 pub struct DataProcess {
     /// Canonical field name
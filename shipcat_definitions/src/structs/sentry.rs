@@ -1,4 +1,5 @@
 use super::metadata::SlackChannel;
+use schemars::JsonSchema;
 
 /// Monitoring section covering Sentry configurations
 ///
@@ -7,7 +8,7 @@ use super::metadata::SlackChannel;
 ///   slack: C12ABYZ78
 ///   silent: true
 /// ```
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Sentry {
     pub slack: SlackChannel,
@@ -1,11 +1,12 @@
 use super::Result;
+use schemars::JsonSchema;
 use std::collections::BTreeMap;
 
 // These structs contain a straight translation of kubernetes volumes
 // TODO: cross reference better with
 // https://kubernetes.io/docs/concepts/storage/volumes/
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct VolumeSecretItem {
     #[serde(default = "volume_key")]
     pub key: String,
@@ -20,35 +21,35 @@ fn volume_default_mode() -> u32 {
     420
 } // 0o644
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct VolumeSecretDetail {
     pub secretName: String,
     pub items: Vec<VolumeSecretItem>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct ProjectedVolumeSecretSourceDetail {
     pub name: String,
     pub items: Vec<VolumeSecretItem>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct ProjectedVolumeSecretSource {
     pub secret: ProjectedVolumeSecretSourceDetail,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct ProjectedVolumeSecret {
     pub sources: Vec<ProjectedVolumeSecretSource>,
     // pub default_mode: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct DownwardApiWrapper {
     pub items: Vec<DownwardApiItem>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct DownwardApiItem {
     /// Kube path to string
     pub path: String,
@@ -56,7 +57,7 @@ pub struct DownwardApiItem {
     pub resourceFieldRef: DownWardApiResource,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct DownWardApiResource {
     /// Name of container TODO: default to service name
     pub containerName: String,
@@ -67,7 +68,7 @@ pub struct DownWardApiResource {
     pub divisor: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct Volume {
     pub name: String,
     /// A projection combines multiple volume items
@@ -92,7 +93,7 @@ impl Volume {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct VolumeMount {
     pub name: String,
     pub mountPath: String,
@@ -1,11 +1,12 @@
 use super::{autoscaling::AutoScaling, Container};
+use schemars::JsonSchema;
 use std::collections::BTreeMap;
 
 /// Worker for a service
 ///
 /// Essentially a side-car like object that can scale resources separately to the main pods.
 /// Useful for services that have one single side service that polls or does some work.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Worker {
     /// Replication limits
     pub replicaCount: u32,
@@ -35,3 +36,14 @@ pub struct Worker {
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub podAnnotations: BTreeMap<String, String>,
 }
+
+impl Worker {
+    /// Minimum number of replicas of the worker deployment
+    pub fn min_replicas(&self) -> u32 {
+        if let Some(ref hpa) = self.autoScaling {
+            hpa.minReplicas
+        } else {
+            self.replicaCount
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use merge::Merge;
+use schemars::JsonSchema;
+
+use shipcat_definitions::{
+    structs::{Hook, Hooks},
+    ErrorKind, Result, ResultExt,
+};
+
+use crate::util::Build;
+
+use super::source::{ContainerBuildParams, ContainerSource};
+
+#[derive(Deserialize, Merge, Clone, Default, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HooksSource {
+    pub pre_deploy: Option<Vec<HookSource>>,
+    pub post_deploy: Option<Vec<HookSource>>,
+}
+
+#[derive(Deserialize, Merge, Clone, Default, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HookSource {
+    pub timeout: Option<u32>,
+    pub backoff_limit: Option<u16>,
+
+    #[serde(flatten)]
+    pub container: ContainerSource,
+}
+
+impl Build<Hooks, ContainerBuildParams> for HooksSource {
+    fn build(self, params: &ContainerBuildParams) -> Result<Hooks> {
+        Ok(Hooks {
+            preDeploy: self
+                .pre_deploy
+                .unwrap_or_default()
+                .build(params)
+                .chain_err(|| ErrorKind::InvalidField("preDeploy".into()))?,
+            postDeploy: self
+                .post_deploy
+                .unwrap_or_default()
+                .build(params)
+                .chain_err(|| ErrorKind::InvalidField("postDeploy".into()))?,
+        })
+    }
+}
+
+impl Build<Hook, ContainerBuildParams> for HookSource {
+    fn build(self, params: &ContainerBuildParams) -> Result<Hook> {
+        Ok(Hook {
+            container: self.container.build(params)?,
+            timeout: self.timeout,
+            backoffLimit: self.backoff_limit,
+        })
+    }
+}
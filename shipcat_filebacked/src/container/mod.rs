@@ -10,6 +10,7 @@ pub use image::{ImageNameSource, ImageTagSource};
 pub use resources::ResourceRequirementsSource;
 
 mod cronjob;
+mod hook;
 mod initcontainer;
 
 mod port;
@@ -17,6 +18,7 @@ mod sidecar;
 mod worker;
 
 pub use cronjob::CronJobSource;
+pub use hook::HooksSource;
 pub use initcontainer::InitContainerSource;
 pub use port::PortSource;
 pub use sidecar::SidecarSource;
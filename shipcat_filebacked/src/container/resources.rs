@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use shipcat_definitions::{
     structs::resources::{ResourceRequirements, Resources},
     Result,
@@ -5,7 +6,7 @@ use shipcat_definitions::{
 
 use crate::util::{Build, RelaxedString, Require};
 
-#[derive(Deserialize, Clone, Default)]
+#[derive(Deserialize, Clone, Default, JsonSchema)]
 #[serde(default, rename_all = "camelCase", deny_unknown_fields)]
 pub struct ResourceRequirementsSource {
     pub requests: ResourcesSource,
@@ -23,7 +24,7 @@ impl Build<ResourceRequirements<String>, ()> for ResourceRequirementsSource {
     }
 }
 
-#[derive(Deserialize, Clone, Default)]
+#[derive(Deserialize, Clone, Default, JsonSchema)]
 #[serde(default, rename_all = "camelCase", deny_unknown_fields)]
 pub struct ResourcesSource {
     pub cpu: Option<RelaxedString>,
@@ -1,5 +1,6 @@
 use merge::Merge;
 use regex::Regex;
+use schemars::JsonSchema;
 
 use shipcat_definitions::{
     structs::{Container, Probe, VolumeMount},
@@ -15,7 +16,7 @@ use super::{
     EnvVarsSource,
 };
 
-#[derive(Deserialize, Clone, Default)]
+#[derive(Deserialize, Clone, Default, JsonSchema)]
 pub struct ContainerName(String);
 
 impl Build<String, ()> for ContainerName {
@@ -30,7 +31,7 @@ impl Build<String, ()> for ContainerName {
 }
 
 /// Source configuration for a K8s container, deserialized from a service manifest.
-#[derive(Deserialize, Merge, Clone, Default)]
+#[derive(Deserialize, Merge, Clone, Default, JsonSchema)]
 #[serde(default, rename_all = "camelCase", deny_unknown_fields)]
 pub struct ContainerSource {
     pub name: Option<ContainerName>,
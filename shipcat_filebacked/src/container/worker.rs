@@ -1,4 +1,5 @@
 use merge::Merge;
+use schemars::JsonSchema;
 
 use shipcat_definitions::{
     structs::{autoscaling::AutoScaling, Worker},
@@ -9,7 +10,7 @@ use super::source::{ContainerBuildParams, ContainerSource};
 use crate::util::{Build, RelaxedString, Require};
 use std::collections::BTreeMap;
 
-#[derive(Deserialize, Merge, Clone, Default)]
+#[derive(Deserialize, Merge, Clone, Default, JsonSchema)]
 #[serde(default, rename_all = "camelCase")]
 pub struct WorkerSource {
     pub replica_count: Option<u32>,
@@ -1,4 +1,5 @@
 use merge::Merge;
+use schemars::JsonSchema;
 use std::collections::BTreeMap;
 
 use shipcat_definitions::{
@@ -11,7 +12,7 @@ use super::{
     util::{Build, Enabled, EnabledMap},
 };
 
-#[derive(Deserialize, Default, Merge, Clone)]
+#[derive(Deserialize, Default, Merge, Clone, JsonSchema)]
 #[serde(default)]
 pub struct KongApisSource {
     /// Default values to merge into every API
@@ -93,7 +94,7 @@ impl KongApisSource {
     }
 }
 
-#[derive(Deserialize, Default, Merge, Clone)]
+#[derive(Deserialize, Default, Merge, Clone, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub struct KongSource {
     pub upstream_url: Option<String>,
@@ -223,7 +224,7 @@ impl KongSource {
     }
 }
 
-#[derive(Deserialize, Default, Merge, Clone)]
+#[derive(Deserialize, Default, Merge, Clone, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub struct KongRateLimitSource {
     pub per_second: Option<u32>,
@@ -14,10 +14,14 @@ pub use crate::simple::SimpleManifest;
 mod kong;
 
 mod load;
+mod location;
+pub use crate::location::{Location, SourceMap};
 mod util;
 
 use manifest::ManifestSource;
-use shipcat_definitions::{BaseManifest, Config, Manifest, Region, Result};
+use schemars::schema::RootSchema;
+use shipcat_definitions::{schema::json_schema, BaseManifest, Config, Manifest, Region, Result};
+use std::path::PathBuf;
 
 pub async fn load_manifest(service: &str, conf: &Config, reg: &Region) -> Result<Manifest> {
     ManifestSource::load_manifest(service, conf, reg).await
@@ -27,6 +31,11 @@ pub async fn load_metadata(service: &str, conf: &Config, reg: &Region) -> Result
     ManifestSource::load_metadata(service, conf, reg).await
 }
 
+/// Positions of keys in the manifest files of a service, for locating validation errors
+pub async fn load_sources(service: &str, reg: &Region) -> Result<SourceMap> {
+    ManifestSource::load_sources(service, reg).await
+}
+
 pub async fn all(conf: &Config) -> Result<Vec<BaseManifest>> {
     ManifestSource::all(conf).await
 }
@@ -34,3 +43,12 @@ pub async fn all(conf: &Config) -> Result<Vec<BaseManifest>> {
 pub async fn available(conf: &Config, reg: &Region) -> Result<Vec<SimpleManifest>> {
     ManifestSource::available(conf, reg).await
 }
+
+/// JSON Schema of `manifest.yml` (including the overrides allowed in region and environment files)
+pub fn manifest_schema() -> RootSchema {
+    json_schema::<ManifestSource>()
+}
+
+pub fn version_override_path(service: &str, reg: &Region, env_shared: bool) -> PathBuf {
+    ManifestSource::version_override_path(service, reg, env_shared)
+}
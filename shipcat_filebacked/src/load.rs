@@ -5,7 +5,7 @@ use serde::de::DeserializeOwned;
 use shipcat_definitions::{Config, ErrorKind, Manifest, Region, Result, ResultExt};
 use walkdir::WalkDir;
 
-use super::{authorization::AuthorizationSource, util::Enabled, BaseManifest, SimpleManifest};
+use super::{authorization::AuthorizationSource, util::Enabled, BaseManifest, SimpleManifest, SourceMap};
 use crate::manifest::{ManifestDefaults, ManifestOverrides, ManifestSource};
 
 impl ManifestSource {
@@ -61,6 +61,25 @@ impl ManifestSource {
         Ok(manifest)
     }
 
+    /// Key positions in the files a manifest is merged from
+    ///
+    /// Mirrors the file order of `load_merged`, so errors can be traced to the file that set a value.
+    pub async fn load_sources(service: &str, reg: &Region) -> Result<SourceMap> {
+        use tokio::fs;
+        let dir = Self::services_dir().join(service);
+        let paths = [
+            dir.join("manifest.yml"),
+            dir.join(format!("{}.yml", reg.environment.to_string())),
+            dir.join(format!("{}.yml", reg.name)),
+        ];
+        let mut sources = SourceMap::default();
+        for path in paths.iter().filter(|p| p.is_file()) {
+            let data = fs::read_to_string(path).await?;
+            sources.add(path, &data)?;
+        }
+        Ok(sources)
+    }
+
     fn all_names() -> Vec<String> {
         let mut res: Vec<_> = WalkDir::new(&ManifestSource::services_dir())
             .min_depth(1)
@@ -110,11 +129,34 @@ impl ManifestSource {
         Ok(available)
     }
 
+    /// Override file that a version pin for a region should be written to
+    ///
+    /// This is where the pin currently comes from, if it is specific to the region.
+    /// An environment file pin is only reused if no other region shares the environment.
+    /// Otherwise the region override file is used, as it takes precedence.
+    pub fn version_override_path(service: &str, reg: &Region, env_shared: bool) -> PathBuf {
+        let dir = Self::services_dir().join(service);
+        let region_path = dir.join(format!("{}.yml", reg.name));
+        let env_path = dir.join(format!("{}.yml", reg.environment.to_string()));
+        if !has_version_pin(&region_path) && !env_shared && has_version_pin(&env_path) {
+            env_path
+        } else {
+            region_path
+        }
+    }
+
     fn services_dir() -> PathBuf {
         Path::new(".").join("services")
     }
 }
 
+fn has_version_pin(path: &PathBuf) -> bool {
+    match std::fs::read_to_string(path) {
+        Ok(data) => data.lines().any(|l| l.starts_with("version:")),
+        Err(_) => false,
+    }
+}
+
 impl ManifestDefaults {
     fn builtin() -> Self {
         let mut defaults = Self::default();
@@ -236,6 +278,22 @@ mod tests {
         assert_eq!(manifest.image, Some("quay.io/babylonhealth/fake-ask".into()));
     }
 
+    #[tokio::test]
+    async fn version_override_path() {
+        setup();
+
+        let conf = Config::read().await.unwrap();
+        let devuk = conf.get_region("dev-uk").unwrap();
+        let preprod = conf.get_region("preprod-uk").unwrap();
+
+        // region file pins take precedence
+        let pth = ManifestSource::version_override_path("fake-ask", &devuk, false);
+        assert_eq!(pth, Path::new(".").join("services/fake-ask/dev-uk.yml"));
+        // no pins anywhere => new region file
+        let pth = ManifestSource::version_override_path("fake-ask", &preprod, false);
+        assert_eq!(pth, Path::new(".").join("services/fake-ask/preprod-uk.yml"));
+    }
+
     #[tokio::test]
     async fn all() {
         setup();
@@ -0,0 +1,268 @@
+use std::{
+    collections::BTreeMap,
+    error::Error as StdError,
+    fmt, iter,
+    path::{Path, PathBuf},
+};
+
+use shipcat_definitions::{Error, ErrorKind, Result};
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+/// Position of a key in a manifest source file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub file: PathBuf,
+    /// Line number (starting at 1)
+    pub line: usize,
+    /// Column number (starting at 1)
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// Line and column of every key path in a file
+type Keys = BTreeMap<String, (usize, usize)>;
+
+/// Positions of every key in the files a manifest was merged from
+///
+/// Files are kept in merge order, so later files take precedence.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<(PathBuf, Keys)>,
+}
+
+impl SourceMap {
+    /// Record the key positions of a yaml file
+    ///
+    /// Keys are recorded as paths like `resources.limits.memory` or `dependencies[0].name`.
+    pub fn add(&mut self, path: &Path, data: &str) -> Result<()> {
+        let mut positions = Positions::default();
+        if let Err(e) = Parser::new(data.chars()).load(&mut positions, false) {
+            bail!("Manifest file {} did not parse as YAML: {}", path.display(), e);
+        }
+        // paths are reported relative to the manifests repository
+        let file = path.strip_prefix(".").unwrap_or(path).to_path_buf();
+        self.files.push((file, positions.keys));
+        Ok(())
+    }
+
+    /// Find where the value of a field was defined
+    ///
+    /// This is the file that defines the most specific part of the field path,
+    /// preferring later files when several files define it.
+    /// Fields that only come from defaults have no location.
+    pub fn locate(&self, field: &str) -> Option<Location> {
+        let mut best: Option<(usize, Location)> = None;
+        for (file, keys) in self.files.iter().rev() {
+            let found = prefixes(field)
+                .into_iter()
+                .find_map(|p| keys.get(p).map(|pos| (p.len(), pos)));
+            if let Some((len, &(line, column))) = found {
+                if best.as_ref().map(|(l, _)| len > *l).unwrap_or(true) {
+                    let loc = Location {
+                        file: file.clone(),
+                        line,
+                        column,
+                    };
+                    best = Some((len, loc));
+                }
+            }
+        }
+        best.map(|(_, loc)| loc)
+    }
+
+    /// Locate a validation error from the manifest fields it blames
+    ///
+    /// Returns the location (if any) along with the reasons below the blamed field.
+    /// A field at the end of the chain blames the error right above it instead.
+    pub fn blame(&self, err: &Error) -> (Option<Location>, String) {
+        let mut field = String::new();
+        let mut reasons = vec![];
+        let mut above = None;
+        let chain = iter::successors(Some(err as &(dyn StdError + 'static)), |&e| e.source());
+        for e in chain {
+            match e.downcast_ref::<Error>().map(Error::kind) {
+                Some(ErrorKind::InvalidField(f)) => {
+                    if !field.is_empty() && !f.starts_with('[') {
+                        field.push('.');
+                    }
+                    field.push_str(f);
+                    reasons.clear();
+                    if e.source().is_none() {
+                        reasons.extend(above.take());
+                    }
+                }
+                _ => {
+                    above = Some(e.to_string());
+                    reasons.push(e.to_string());
+                }
+            }
+        }
+        let loc = if field.is_empty() {
+            None
+        } else {
+            self.locate(&field)
+        };
+        (loc, reasons.join(": "))
+    }
+}
+
+/// Prefixes of a field path, longest first
+///
+/// E.g. `workers[0].env` gives `workers[0].env`, `workers[0]` and `workers`.
+fn prefixes(field: &str) -> Vec<&str> {
+    let mut res = vec![field];
+    for (i, c) in field.char_indices().rev() {
+        if (c == '.' || c == '[') && i > 0 {
+            res.push(&field[..i]);
+        }
+    }
+    res
+}
+
+/// Collection of a yaml document in progress
+enum Node {
+    /// A mapping at a path, possibly waiting for the value of a key
+    Mapping(String, Option<String>),
+    /// A sequence at a path, with the index of the next element
+    Sequence(String, usize),
+}
+
+/// Event receiver recording where every key starts
+#[derive(Default)]
+struct Positions {
+    stack: Vec<Node>,
+    keys: Keys,
+}
+
+impl Positions {
+    /// Path of the value that is about to start (recording sequence elements)
+    fn enter(&mut self, mark: Marker) -> Option<String> {
+        let path = match self.stack.last()? {
+            Node::Mapping(path, Some(key)) if path.is_empty() => key.clone(),
+            Node::Mapping(path, Some(key)) => format!("{}.{}", path, key),
+            Node::Mapping(_, None) => return None,
+            Node::Sequence(path, i) => {
+                let path = format!("{}[{}]", path, i);
+                self.keys.insert(path.clone(), (mark.line(), mark.col() + 1));
+                path
+            }
+        };
+        Some(path)
+    }
+
+    /// Move past a finished value
+    fn leave(&mut self) {
+        match self.stack.last_mut() {
+            Some(Node::Mapping(_, key)) => *key = None,
+            Some(Node::Sequence(_, i)) => *i += 1,
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for Positions {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => match self.stack.last_mut() {
+                Some(Node::Mapping(path, key @ None)) => {
+                    let full = if path.is_empty() {
+                        value.clone()
+                    } else {
+                        format!("{}.{}", path, value)
+                    };
+                    self.keys.insert(full, (mark.line(), mark.col() + 1));
+                    *key = Some(value);
+                }
+                _ => {
+                    self.enter(mark);
+                    self.leave();
+                }
+            },
+            Event::Alias(_) => {
+                self.enter(mark);
+                self.leave();
+            }
+            Event::MappingStart(_) => {
+                let path = self.enter(mark).unwrap_or_default();
+                self.stack.push(Node::Mapping(path, None));
+            }
+            Event::SequenceStart(_) => {
+                let path = self.enter(mark).unwrap_or_default();
+                self.stack.push(Node::Sequence(path, 0));
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.leave();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Location, SourceMap};
+    use shipcat_definitions::{Error, ErrorKind, ResultExt};
+    use std::path::Path;
+
+    #[test]
+    fn source_map_locations() {
+        let mut sm = SourceMap::default();
+        let manifest =
+            "name: fake\nresources:\n  limits:\n    memory: 1Gi\nworkers:\n- name: a\n  env:\n    FOO: bar\n";
+        sm.add(Path::new("./services/fake/manifest.yml"), manifest)
+            .unwrap();
+        sm.add(
+            Path::new("./services/fake/dev-uk.yml"),
+            "replicaCount: 0\nresources:\n  requests: {}\n",
+        )
+        .unwrap();
+
+        let loc = |line, column, file: &str| {
+            Some(Location {
+                file: Path::new("services/fake").join(file),
+                line,
+                column,
+            })
+        };
+        assert_eq!(sm.locate("replicaCount"), loc(1, 1, "dev-uk.yml"));
+        assert_eq!(sm.locate("resources.limits.memory"), loc(4, 5, "manifest.yml"));
+        assert_eq!(sm.locate("resources.requests.cpu"), loc(3, 3, "dev-uk.yml"));
+        assert_eq!(sm.locate("workers[0].env.FOO"), loc(8, 5, "manifest.yml"));
+        assert_eq!(sm.locate("workers[1]"), loc(5, 1, "manifest.yml"));
+        assert_eq!(sm.locate("health"), None);
+        assert_eq!(
+            loc(1, 1, "dev-uk.yml").unwrap().to_string(),
+            "services/fake/dev-uk.yml:1:1"
+        );
+
+        let err: Error = Err::<(), _>(Error::from("FOO is invalid"))
+            .chain_err(|| ErrorKind::InvalidField("env".into()))
+            .chain_err(|| ErrorKind::InvalidField("workers[0]".into()))
+            .chain_err(|| ErrorKind::FailedToBuildManifest("fake".into(), "dev-uk".into()))
+            .unwrap_err();
+        let (at, reason) = sm.blame(&err);
+        assert_eq!(at, loc(7, 3, "manifest.yml"));
+        assert_eq!(reason, "FOO is invalid");
+
+        // the message stays outermost when the field is chained below it
+        let err = Error::with_chain(
+            Error::from(ErrorKind::InvalidField("replicaCount".into())),
+            "Need replicaCount to be at least 1",
+        );
+        assert_eq!(err.to_string(), "Need replicaCount to be at least 1");
+        let (at, reason) = sm.blame(&err);
+        assert_eq!(at, loc(1, 1, "dev-uk.yml"));
+        assert_eq!(reason, "Need replicaCount to be at least 1");
+
+        assert!(sm.add(Path::new("bad.yml"), "a: [").is_err());
+    }
+}
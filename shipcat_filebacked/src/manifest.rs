@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use merge::Merge;
+use schemars::JsonSchema;
 use std::collections::BTreeMap;
 
 use shipcat_definitions::{
@@ -12,14 +13,14 @@ use shipcat_definitions::{
         volume::Volume,
         ConfigMap, Dependency, DestinationRule, EventStream, Gate, HealthCheck, HostAlias, Kafka,
         KafkaResources, LifeCycle, Metadata, NotificationMode, PersistentVolume, Probe, PrometheusAlert,
-        Rbac, RollingUpdate, SecurityContext, VaultOpts, VolumeMount,
+        Rbac, RollingUpdate, RolloutStrategy, SecurityContext, VaultOpts, VolumeMount,
     },
-    BaseManifest, Config, Manifest, PrimaryWorkload, Region, Result,
+    BaseManifest, Config, ErrorKind, Manifest, PrimaryWorkload, Region, Result, ResultExt,
 };
 
 use super::{
     container::{
-        ContainerBuildParams, CronJobSource, EnvVarsSource, ImageNameSource, ImageTagSource,
+        ContainerBuildParams, CronJobSource, EnvVarsSource, HooksSource, ImageNameSource, ImageTagSource,
         InitContainerSource, PortSource, ResourceRequirementsSource, SidecarSource, WorkerSource,
     },
     kong::{KongApisBuildParams, KongApisSource, KongSource},
@@ -30,7 +31,7 @@ use super::{
 };
 
 /// Helper for optional string/list of string structs
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, JsonSchema)]
 #[serde(untagged)]
 pub enum OneOrMany<T> {
     One(T),
@@ -43,7 +44,7 @@ impl<T> Default for OneOrMany<T> {
     }
 }
 
-#[derive(Deserialize, Default, Clone)]
+#[derive(Deserialize, Default, Clone, JsonSchema)]
 #[serde(default)]
 pub struct MetadataSource {
     pub repo: String,
@@ -77,7 +78,7 @@ pub struct MetadataSource {
 }
 
 /// Main manifest, deserialized from `manifest.yml`
-#[derive(Deserialize, Default)]
+#[derive(Deserialize, Default, JsonSchema)]
 #[serde(default, rename_all = "camelCase")]
 pub struct ManifestSource {
     pub name: Option<String>,
@@ -91,7 +92,7 @@ pub struct ManifestSource {
 }
 
 /// Manifest overrides, deserialized from `dev-uk.yml`/`prod.yml` etc.
-#[derive(Deserialize, Default, Merge, Clone)]
+#[derive(Deserialize, Default, Merge, Clone, JsonSchema)]
 #[serde(default, deny_unknown_fields, rename_all = "camelCase")]
 pub struct ManifestOverrides {
     pub workload: Option<PrimaryWorkload>,
@@ -119,6 +120,7 @@ pub struct ManifestOverrides {
     pub liveness_probe: Option<Probe>,
     pub lifecycle: Option<LifeCycle>,
     pub rolling_update: Option<RollingUpdate>,
+    pub rollout_strategy: Option<RolloutStrategy>,
     pub auto_scaling: Option<AutoScaling>,
     pub tolerations: Option<Vec<Tolerations>>,
     pub host_aliases: Option<Vec<HostAlias>>,
@@ -127,6 +129,7 @@ pub struct ManifestOverrides {
     pub volume_mounts: Option<Vec<VolumeMount>>,
     pub persistent_volumes: Option<Vec<PersistentVolume>>,
     pub cron_jobs: Option<Vec<CronJobSource>>,
+    pub hooks: HooksSource,
     pub service_annotations: BTreeMap<String, String>,
     pub pod_annotations: BTreeMap<String, RelaxedString>,
     pub labels: BTreeMap<String, RelaxedString>,
@@ -148,7 +151,7 @@ pub struct ManifestOverrides {
 }
 
 /// Global/regional manifest defaults, deserialized from `shipcat.conf` etc.
-#[derive(Deserialize, Default, Merge, Clone)]
+#[derive(Deserialize, Default, Merge, Clone, JsonSchema)]
 #[serde(default, deny_unknown_fields, rename_all = "camelCase")]
 pub struct ManifestDefaults {
     pub image_prefix: Option<String>,
@@ -204,14 +207,24 @@ impl ManifestSource {
             command: overrides.command.unwrap_or_default(),
             securityContext: overrides.security_context,
             dataHandling: data_handling,
-            resources: overrides.resources.build(&())?,
+            resources: overrides
+                .resources
+                .build(&())
+                .chain_err(|| ErrorKind::InvalidField("resources".into()))?,
             replicaCount: defaults.replica_count,
-            env: defaults.env.build(&())?,
+            env: defaults
+                .env
+                .build(&())
+                .chain_err(|| ErrorKind::InvalidField("env".into()))?,
             secretFiles: overrides.secret_files,
             configs: configs,
             vault: overrides.vault,
             httpPort: overrides.http_port,
-            ports: overrides.ports.unwrap_or_default().build(&())?,
+            ports: overrides
+                .ports
+                .unwrap_or_default()
+                .build(&())
+                .chain_err(|| ErrorKind::InvalidField("ports".into()))?,
             externalPort: overrides.external_port,
             health: overrides.health,
             dependencies: overrides.dependencies.unwrap_or_default(),
@@ -219,42 +232,61 @@ impl ManifestSource {
             workers: overrides
                 .workers
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .build(&container_build_params)
+                .chain_err(|| ErrorKind::InvalidField("workers".into()))?,
             sidecars: overrides
                 .sidecars
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .build(&container_build_params)
+                .chain_err(|| ErrorKind::InvalidField("sidecars".into()))?,
             readinessProbe: overrides.readiness_probe,
             livenessProbe: overrides.liveness_probe,
             lifecycle: overrides.lifecycle,
             rollingUpdate: overrides.rolling_update,
+            rolloutStrategy: overrides.rollout_strategy,
             autoScaling: overrides.auto_scaling,
             tolerations: overrides.tolerations.unwrap_or_default(),
             hostAliases: overrides.host_aliases.unwrap_or_default(),
             initContainers: overrides
                 .init_containers
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .build(&container_build_params)
+                .chain_err(|| ErrorKind::InvalidField("initContainers".into()))?,
             volumes: overrides.volumes.unwrap_or_default(),
             volumeMounts: overrides.volume_mounts.unwrap_or_default(),
             persistentVolumes: overrides.persistent_volumes.unwrap_or_default(),
             cronJobs: overrides
                 .cron_jobs
                 .unwrap_or_default()
-                .build(&container_build_params)?,
+                .build(&container_build_params)
+                .chain_err(|| ErrorKind::InvalidField("cronJobs".into()))?,
+            hooks: overrides
+                .hooks
+                .build(&container_build_params)
+                .chain_err(|| ErrorKind::InvalidField("hooks".into()))?,
             serviceAnnotations: overrides.service_annotations,
-            podAnnotations: overrides.pod_annotations.build(&())?,
-            labels: overrides.labels.build(&())?,
+            podAnnotations: overrides
+                .pod_annotations
+                .build(&())
+                .chain_err(|| ErrorKind::InvalidField("podAnnotations".into()))?,
+            labels: overrides
+                .labels
+                .build(&())
+                .chain_err(|| ErrorKind::InvalidField("labels".into()))?,
             kongApis: simple.kong_apis,
             gate: overrides.gate,
             kafka: kafka,
             sourceRanges: overrides.source_ranges.unwrap_or_default(),
             rbac: overrides.rbac.unwrap_or_default(),
-            newrelic: overrides.newrelic.build(&team_notifications)?,
+            newrelic: overrides
+                .newrelic
+                .build(&team_notifications)
+                .chain_err(|| ErrorKind::InvalidField("newrelic".into()))?,
             sentry: overrides
                 .sentry
                 .map(|sentry| sentry.build(&team_notifications))
-                .transpose()?,
+                .transpose()
+                .chain_err(|| ErrorKind::InvalidField("sentry".into()))?,
             eventStreams: overrides.event_streams.unwrap_or_default(),
             kafkaResources: overrides.kafka_resources,
             upgradeNotifications: Default::default(),
@@ -277,12 +309,15 @@ impl ManifestSource {
         let overrides = self.overrides.clone();
         let defaults = overrides.defaults;
         let kong_apis = if let Some(k) = &region.kong {
-            defaults.kong_apis.build(&KongApisBuildParams {
-                service: base.name.to_string(),
-                region: region.clone(),
-                kong: k.clone(),
-                single_api: defaults.kong,
-            })?
+            defaults
+                .kong_apis
+                .build(&KongApisBuildParams {
+                    service: base.name.to_string(),
+                    region: region.clone(),
+                    kong: k.clone(),
+                    single_api: defaults.kong,
+                })
+                .chain_err(|| ErrorKind::InvalidField("kongApis".into()))?
         } else {
             // NB: this drops kong entries on the floor if region.kong is None
             vec![]
@@ -295,8 +330,14 @@ impl ManifestSource {
             external: self.external,
 
             // TODO: Make image non-optional
-            image: Some(self.build_image(&base.name)?),
-            version: overrides.version.build(&())?,
+            image: Some(
+                self.build_image(&base.name)
+                    .chain_err(|| ErrorKind::InvalidField("image".into()))?,
+            ),
+            version: overrides
+                .version
+                .build(&())
+                .chain_err(|| ErrorKind::InvalidField("version".into()))?,
             kong_apis,
             base,
         })
@@ -305,7 +346,9 @@ impl ManifestSource {
     pub fn build_base(&self, conf: &Config) -> Result<BaseManifest> {
         // TODO: Remove and use folder name
         let name = self.name.clone().require("name")?;
-        let metadata = self.build_metadata(conf)?;
+        let metadata = self
+            .build_metadata(conf)
+            .chain_err(|| ErrorKind::InvalidField("metadata".into()))?;
         let regions = self.regions.clone();
 
         Ok(BaseManifest {
@@ -1,4 +1,4 @@
-use shipcat_definitions::Result;
+use shipcat_definitions::{ErrorKind, Result, ResultExt};
 use std::collections::BTreeMap;
 
 pub trait Build<T, P> {
@@ -13,14 +13,23 @@ impl<T, P, S: Build<T, P>> Build<Option<T>, P> for Option<S> {
 
 impl<T, P, S: Build<T, P>> Build<Vec<T>, P> for Vec<S> {
     fn build(self, params: &P) -> Result<Vec<T>> {
-        self.into_iter().map(|s| s.build(params)).collect()
+        self.into_iter()
+            .enumerate()
+            .map(|(i, s)| {
+                s.build(params)
+                    .chain_err(|| ErrorKind::InvalidField(format!("[{}]", i)))
+            })
+            .collect()
     }
 }
 
 impl<V, P, S: Build<V, P>> Build<BTreeMap<String, V>, P> for BTreeMap<String, S> {
     fn build(self, params: &P) -> Result<BTreeMap<String, V>> {
         self.into_iter()
-            .map(|(k, s)| s.build(params).map(|v| (k, v)))
+            .map(|(k, s)| {
+                let v = s.build(params).chain_err(|| ErrorKind::InvalidField(k.clone()))?;
+                Ok((k, v))
+            })
             .collect()
     }
 }
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use std::collections::BTreeMap;
 
 use merge::Merge;
@@ -23,9 +24,10 @@ use super::Build;
 ///     value: 3
 /// bar: ~
 /// ```
-#[derive(Deserialize, Default, Clone, PartialEq, Merge)]
+#[derive(Deserialize, Default, Clone, PartialEq, Merge, JsonSchema)]
 #[cfg_attr(test, derive(Debug, Copy))]
 #[serde(default, deny_unknown_fields)]
+#[schemars(bound = "T: JsonSchema + Default + Merge")]
 pub struct Enabled<T: Merge> {
     pub enabled: Option<bool>,
 
@@ -46,7 +48,7 @@ impl<S: Build<B, P> + Merge, B, P> Build<Option<B>, P> for Enabled<S> {
 /// EnabledMap is a map where each value is wrapped in an Enabled.
 ///
 /// It can be built into a map which flattens the Enabled wrappers, so disabled values are excluded.
-#[derive(Deserialize, Default, Clone, PartialEq)]
+#[derive(Deserialize, Default, Clone, PartialEq, JsonSchema)]
 #[cfg_attr(test, derive(Debug))]
 pub struct EnabledMap<K: Clone + std::hash::Hash + Ord, V: Clone + Default + Merge>(BTreeMap<K, Enabled<V>>);
 
@@ -1,3 +1,8 @@
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::de::{Deserialize, Deserializer, Error, Visitor};
 use std::fmt;
 
@@ -30,6 +35,26 @@ impl<'de> Deserialize<'de> for RelaxedString {
     }
 }
 
+impl JsonSchema for RelaxedString {
+    fn schema_name() -> String {
+        "RelaxedString".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let types = vec![
+            InstanceType::String,
+            InstanceType::Number,
+            InstanceType::Boolean,
+            InstanceType::Null,
+        ];
+        SchemaObject {
+            instance_type: Some(types.into()),
+            ..SchemaObject::default()
+        }
+        .into()
+    }
+}
+
 struct RelaxedStringVisitor;
 
 macro_rules! visit_tostring {